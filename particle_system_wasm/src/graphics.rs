@@ -1,19 +1,28 @@
-use std::cell::RefCell;
+use std::cell::{Ref, RefCell, RefMut};
 use std::cmp::min;
-use std::mem;
+use std::collections::HashMap;
+use std::f32::consts::TAU;
 use std::rc::Rc;
-
-use glam::Vec2;
-use js_sys::{Float32Array, Object};
-use log::debug;
-use web_sys::{WebGl2RenderingContext, WebGlTexture};
-use winit::dpi::PhysicalSize;
-use winit::event::WindowEvent;
+use std::str::FromStr;
+
+use glam::{BVec2, Mat2, UVec2, Vec2};
+use half::f16;
+use js_sys::{Float32Array, Function, Object, Uint16Array, Uint32Array, Uint8Array};
+use js_sys::Math::random;
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlRenderbuffer, WebGlSync, WebGlTexture, WebGlUniformLocation};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{ElementState, KeyboardInput, MouseButton, Touch, TouchPhase, VirtualKeyCode, WindowEvent};
 use winit::platform::web::WindowExtWebSys;
 use winit::window::Window;
-use wrend::{FramebufferCreateContext, FramebufferLink, Id, IdDefault, IdName, ProgramLink, RendererData, TextureCreateContext, TextureLink, UniformContext, UniformLink};
+use wrend::{Bridge, FramebufferCreateContext, FramebufferLink, Id, IdDefault, IdName, ProgramLink, RendererData, TextureCreateContext, TextureLink, UniformContext, UniformLink};
 
-use crate::particle::generate_particles;
+use crate::particle::{generate_depths, generate_drags, generate_masses, generate_particles, generate_seeded_particles, MAX_MASS, MAX_VELOCITY, MIN_MASS, MIN_VELOCITY, reset_seed, Particle, VelocityField};
+use crate::hud::{font_atlas, FONT_ATLAS_WIDTH, GLYPH_HEIGHT, GLYPH_WIDTH, HUD_COLUMNS, HUD_LINES, layout};
+use crate::stats::{Conservation, ConservationDrift, Reduction, SharedStats};
 
 type GL = WebGl2RenderingContext;
 
@@ -26,28 +35,194 @@ const UPDATE_FRAGMENT: &'static str = include_str!("shaders/update.frag");
 const PARTITION_VERTEX: &'static str = include_str!("shaders/partition.vert");
 const PARTITION_FRAGMENT: &'static str = include_str!("shaders/partition.frag");
 
+const PARTITION_SLOTS_FRAGMENT: &str = include_str!("shaders/partition_slots.frag");
+const PARTITION_UNPACK_FRAGMENT: &str = include_str!("shaders/partition_unpack.frag");
+
+const BOUNDS_VERTEX: &str = include_str!("shaders/bounds.vert");
+const BOUNDS_FRAGMENT: &str = include_str!("shaders/bounds.frag");
+
+const PRESENT_FRAGMENT: &str = include_str!("shaders/present.frag");
+
+const METABALL_SPLAT_FRAGMENT: &str = include_str!("shaders/metaball_splat.frag");
+const METABALL_SURFACE_FRAGMENT: &str = include_str!("shaders/metaball_surface.frag");
+
+const PICKING_FRAGMENT: &str = include_str!("shaders/picking.frag");
+
+const SORT_KEYS_FRAGMENT: &str = include_str!("shaders/sort_keys.frag");
+const BITONIC_SORT_FRAGMENT: &str = include_str!("shaders/bitonic_sort.frag");
+const CELL_RANGES_FRAGMENT: &str = include_str!("shaders/cell_ranges.frag");
+
+const DEBUG_VIEW_FRAGMENT: &str = include_str!("shaders/debug_view.frag");
+
+const HUD_FRAGMENT: &str = include_str!("shaders/hud.frag");
+
+const REDUCE_FRAGMENT: &str = include_str!("shaders/reduce.frag");
+
+const CONTAINER_FRAGMENT: &str = include_str!("shaders/container.frag");
+
 const PARTICLE_COUNT_SQRT: u32 = 300;
-const PARTICLE_COUNT: u32 = PARTICLE_COUNT_SQRT * PARTICLE_COUNT_SQRT;
+pub const PARTICLE_COUNT: u32 = PARTICLE_COUNT_SQRT * PARTICLE_COUNT_SQRT;
 
 const DATA_TEXTURE_WIDTH: u32 = PARTICLE_COUNT_SQRT;
 const DATA_TEXTURE_HEIGHT: u32 = PARTICLE_COUNT_SQRT;
 
-const GRID_ROWS: u32 = 128;
-const GRID_COLUMNS: u32 = 128;
+/// Size of the reduction textures, which hold the first level of a reduction over the data
+/// texture and every further level in their lower left corner.
+const REDUCTION_TEXTURE_WIDTH: u32 = DATA_TEXTURE_WIDTH.div_ceil(2);
+const REDUCTION_TEXTURE_HEIGHT: u32 = DATA_TEXTURE_HEIGHT.div_ceil(2);
+
+pub const GRID_ROWS: u32 = 128;
+pub const GRID_COLUMNS: u32 = 128;
 
-const BIN_CAPACITY: u32 = 4;
+pub const BIN_CAPACITY: u32 = 4;
+
+// Slotted binning stores one bin slot per RGBA channel and writes them in a single MRT pass.
+// The capacity is therefore not configurable, and as WebGL2 guarantees at least 256 array
+// texture layers, the bins fit on every device without checking MAX_ARRAY_TEXTURE_LAYERS.
+const _: () = assert!(BIN_CAPACITY == 4, "slotted binning needs exactly four slots per bin");
+
+/// Number of keys sorted by sorted binning, padded to a power of two for the bitonic sort.
+const SORT_SIZE: u32 = PARTICLE_COUNT.next_power_of_two();
+const SORT_TEXTURE_WIDTH: u32 = 512;
+const SORT_TEXTURE_HEIGHT: u32 = SORT_SIZE / SORT_TEXTURE_WIDTH;
+
+const _: () = assert!(SORT_SIZE.is_multiple_of(SORT_TEXTURE_WIDTH), "sort keys must fill whole texture rows");
 
 const PARTICLE_RADIUS: f32 = 0.00144675925;
 const PARTICLE_SCALE: f32 = 1.0;
 const PARTICLE_RADIUS_SCALED: f32 = (PARTICLE_RADIUS as f64 * PARTICLE_SCALE as f64) as f32;
 
 const TIME_SCALE: f64 = 0.5;
+const MIN_TIME_SCALE: f64 = 0.05;
+const MAX_TIME_SCALE: f64 = 4.0;
+/// Factor the `+` and `-` keys multiply or divide the time scale by.
+const TIME_SCALE_STEP: f64 = 1.25;
+
+/// Change of gravity per arrow key press.
+const GRAVITY_NUDGE: f32 = 0.1;
+
+pub const DOMAIN_MIN: Vec2 = Vec2::splat(-1.0);
+pub const DOMAIN_MAX: Vec2 = Vec2::splat(1.0);
+
+/// World space size of a grid cell. The axes are independent, so that cells of a rectangular
+/// domain can stay roughly square with the grid dimensions chosen to match its aspect ratio.
+/// Collisions are only checked within a cell, so neither side may be smaller than a particle.
+pub const CELL_SIZE: Vec2 = Vec2::new(
+    (DOMAIN_MAX.x - DOMAIN_MIN.x) / GRID_COLUMNS as f32,
+    (DOMAIN_MAX.y - DOMAIN_MIN.y) / GRID_ROWS as f32,
+);
+
+const _: () = assert!(
+    CELL_SIZE.x >= 2.0 * PARTICLE_RADIUS_SCALED && CELL_SIZE.y >= 2.0 * PARTICLE_RADIUS_SCALED,
+    "grid cells must be at least one particle wide",
+);
+
+/// Distance, in collision radii, up to which the velocity stage of split collisions treats
+/// particles as touching. Must match `SEPARATED_CONTACT_DISTANCE` in the update shader.
+const SEPARATED_CONTACT_DISTANCE: f32 = 2.1;
+
+/// Largest factor the collision radius can be scaled by while a colliding pair still lies in
+/// neighboring cells, at the largest contact distance of any collision stage.
+const MAX_COLLISION_RADIUS_SCALE: f32 = CELL_SIZE.x.min(CELL_SIZE.y) / (SEPARATED_CONTACT_DISTANCE * PARTICLE_RADIUS_SCALED);
+
+pub const GRAVITY: Vec2 = Vec2::new(0.0, -0.987);
+
+const SETTLE_TIME_STEP_MS: f64 = 16.0;
+const SETTLE_DAMPING: f32 = 20.0;
+
+const OCCUPANCY_STATS_INTERVAL_MS: f64 = 250.0;
+
+/// How often one row of the particle data is read back to look for NaN or infinite values.
+const CORRUPTION_CHECK_INTERVAL_MS: f64 = 1000.0;
+
+/// How often the particle positions are read back to find the densest region for the auto camera.
+const AUTO_CAMERA_INTERVAL_MS: f64 = 500.0;
+/// Number of regions per axis the auto camera compares the particle counts of.
+const AUTO_CAMERA_REGIONS: u32 = 16;
+pub const DEFAULT_AUTO_CAMERA_SMOOTHING_MS: f32 = 2000.0;
+/// Age at which particles colored by age reach the end of the palette.
+pub const DEFAULT_AGE_COLOR_LIFETIME_MS: f32 = 5000.0;
+/// Opacity of the particles in the `Alpha` blend mode unless another one is given.
+pub const DEFAULT_PARTICLE_OPACITY: f32 = 0.5;
+/// How many times as many particles another region needs than the tracked one for the auto
+/// camera to switch to it, so that it does not jump between regions of similar density.
+const AUTO_CAMERA_HYSTERESIS: f32 = 1.25;
+
+const FREEZE_BRUSH_RADIUS: f32 = 30.0;
+
+const SPAWN_BURST_COUNT: u32 = 200;
+/// Half the side of the square around the cursor a burst of particles is spawned in.
+const SPAWN_BURST_SPREAD: f32 = 0.02;
+
+/// Where the update pass parks particles removed by the sink, with zero velocity, until they
+/// are replaced. Must match `ABSORBED_POSITION` in the update shader.
+const ABSORBED_POSITION: Vec2 = Vec2::splat(-1000.0);
+
+/// Number of segments the track can consist of, which takes one more point than that.
+pub const MAX_TRACK_SEGMENTS: u32 = 256;
+
+/// Number of platforms that can be placed at once. Must match `MAX_PLATFORMS` in the update shader.
+pub const MAX_PLATFORMS: usize = 4;
+
+/// Number of gravity zones that can be placed at once. Must match `MAX_GRAVITY_ZONES` in the update shader.
+pub const MAX_GRAVITY_ZONES: usize = 4;
+
+/// Canvas-sized render targets are reallocated once the canvas has kept its size for this long,
+/// so that dragging a resize does not reallocate them on every frame.
+const RESIZE_DEBOUNCE_MS: f64 = 150.0;
+
+/// Number of entries of the built-in palette.
+const DEFAULT_PALETTE_SIZE: u32 = 256;
+
+const PALETTE_TEXTURE_UNIT: u32 = 3;
+const MORPH_TARGETS_TEXTURE_UNIT: u32 = 4;
+const DEPTH_TEXTURE_UNIT: u32 = 5;
+const FORCE_FIELD_TEXTURE_UNIT: u32 = 6;
+const SORTED_KEYS_TEXTURE_UNIT: u32 = 7;
+const CELL_RANGES_TEXTURE_UNIT: u32 = 8;
+const MASS_TEXTURE_UNIT: u32 = 9;
+const HUD_TEXT_TEXTURE_UNIT: u32 = 10;
+const HUD_FONT_TEXTURE_UNIT: u32 = 11;
+const SPAWN_TIME_TEXTURE_UNIT: u32 = 12;
+const COLOR_TEXTURE_UNIT: u32 = 13;
+const DRAG_TEXTURE_UNIT: u32 = 14;
+const REDUCTION_TEXTURE_UNIT: u32 = 15;
+const TRACK_TEXTURE_UNIT: u32 = 16;
+
+/// Color of the `Solid` color mode, which particles have in the `Spawn` color mode until they
+/// are spawned in another one. Must match the solid color in the draw shader.
+const SOLID_COLOR: [u8; 4] = [255, 0, 0, 255];
+
+/// Spawn time of the particles that have not been spawned since startup or since they were last
+/// replaced with `set_particles`, which keeps them out of any soft time.
+const NEVER_SPAWNED: f32 = f32::MIN;
+
+/// Canvas pixels per font pixel of the parameter readout.
+const HUD_SCALE: u32 = 2;
+/// Distance of the parameter readout from the top left corner of the canvas in pixels.
+const HUD_MARGIN: u32 = 8;
+
+/// Weight of the latest frame in the frame rate shown by the parameter readout.
+const FPS_SMOOTHING: f64 = 0.05;
+
+/// Size and brightness factors of particles at depth 0 and 1.
+const DEPTH_RANGE: Vec2 = Vec2::new(1.0, 0.3);
+
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 100.0;
+
+/// Resolution of the density texture the metaball splats are accumulated into. It covers the
+/// clip space, so it does not depend on the canvas size.
+const METABALL_RESOLUTION: u32 = 512;
+/// Half size of a metaball splat in clip space units.
+const METABALL_SPLAT_RADIUS: f32 = 0.01;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum VertexShaderId {
     Draw,
     Update,
     Partition,
+    BoundsDebug,
 }
 
 impl Default for VertexShaderId {
@@ -63,6 +238,20 @@ enum FragmentShaderId {
     Draw,
     Update,
     Partition,
+    PartitionSlots,
+    PartitionUnpack,
+    BoundsDebug,
+    MetaballSplat,
+    MetaballSurface,
+    Present,
+    Picking,
+    SortKeys,
+    BitonicSort,
+    CellRanges,
+    DebugView,
+    Hud,
+    Container,
+    Reduce,
 }
 
 impl Default for FragmentShaderId {
@@ -78,6 +267,20 @@ enum ProgramId {
     Draw,
     Update,
     Partition,
+    PartitionSlots,
+    PartitionUnpack,
+    BoundsDebug,
+    MetaballSplat,
+    MetaballSurface,
+    Present,
+    Picking,
+    SortKeys,
+    BitonicSort,
+    CellRanges,
+    DebugView,
+    Hud,
+    Container,
+    Reduce,
 }
 
 impl Default for ProgramId {
@@ -92,8 +295,27 @@ impl Id for ProgramId {}
 enum TextureId {
     OldData,
     NewData,
+    SeparatedData,
     Bins,
     PartitionIntermediate,
+    PartitionSlots,
+    MetaballDensity,
+    Palette,
+    MorphTargets,
+    Depths,
+    Masses,
+    Drags,
+    SpawnTimes,
+    Colors,
+    ForceField,
+    SortKeys,
+    SortKeysSwap,
+    CellRanges,
+    HudText,
+    HudFont,
+    ReductionA,
+    ReductionB,
+    Track,
 }
 
 impl Default for TextureId {
@@ -104,10 +326,112 @@ impl Default for TextureId {
 
 impl Id for TextureId {}
 
+impl TextureId {
+    const ALL: [TextureId; 23] = [
+        Self::OldData,
+        Self::NewData,
+        Self::SeparatedData,
+        Self::Bins,
+        Self::PartitionIntermediate,
+        Self::PartitionSlots,
+        Self::MetaballDensity,
+        Self::Palette,
+        Self::MorphTargets,
+        Self::Depths,
+        Self::Masses,
+        Self::Drags,
+        Self::SpawnTimes,
+        Self::Colors,
+        Self::ForceField,
+        Self::SortKeys,
+        Self::SortKeysSwap,
+        Self::CellRanges,
+        Self::HudText,
+        Self::HudFont,
+        Self::ReductionA,
+        Self::ReductionB,
+        Self::Track,
+    ];
+
+    /// Returns the size and format the texture is created with by its link in `Graphics::new`.
+    fn descriptor(&self, precision: Precision) -> TextureDescriptor {
+        let (data_format, _) = precision.texture_formats();
+        let (float_4_format, _) = Precision::Float32.texture_formats();
+
+        match self {
+            Self::OldData | Self::NewData | Self::SeparatedData =>
+                TextureDescriptor::new(DATA_TEXTURE_WIDTH, DATA_TEXTURE_HEIGHT, data_format),
+            Self::Bins => TextureDescriptor::new(GRID_COLUMNS, GRID_ROWS, GL::R32UI).with_layers(BIN_CAPACITY),
+            Self::PartitionIntermediate => TextureDescriptor::new(GRID_COLUMNS, GRID_ROWS, GL::R32UI),
+            Self::PartitionSlots => TextureDescriptor::new(GRID_COLUMNS, GRID_ROWS, float_4_format),
+            Self::MetaballDensity => TextureDescriptor::new(METABALL_RESOLUTION, METABALL_RESOLUTION, GL::R16F),
+            Self::Palette => TextureDescriptor::new(DEFAULT_PALETTE_SIZE, 1, GL::RGBA8),
+            Self::MorphTargets => TextureDescriptor::new(DATA_TEXTURE_WIDTH, DATA_TEXTURE_HEIGHT, float_4_format),
+            Self::Depths | Self::Masses | Self::Drags | Self::SpawnTimes =>
+                TextureDescriptor::new(DATA_TEXTURE_WIDTH, DATA_TEXTURE_HEIGHT, GL::R32F),
+            Self::Colors => TextureDescriptor::new(DATA_TEXTURE_WIDTH, DATA_TEXTURE_HEIGHT, GL::RGBA8),
+            // A single texel until a field is set, in the format without `OES_texture_float_linear`.
+            Self::ForceField => TextureDescriptor::new(1, 1, GL::RG16F),
+            Self::SortKeys | Self::SortKeysSwap => TextureDescriptor::new(SORT_TEXTURE_WIDTH, SORT_TEXTURE_HEIGHT, GL::RG32UI),
+            Self::CellRanges => TextureDescriptor::new(GRID_COLUMNS, GRID_ROWS, GL::RG32UI),
+            Self::HudText => TextureDescriptor::new(HUD_COLUMNS, HUD_LINES, GL::R8UI),
+            Self::HudFont => TextureDescriptor::new(FONT_ATLAS_WIDTH, GLYPH_HEIGHT, GL::R8),
+            Self::ReductionA | Self::ReductionB =>
+                TextureDescriptor::new(REDUCTION_TEXTURE_WIDTH, REDUCTION_TEXTURE_HEIGHT, float_4_format),
+            Self::Track => TextureDescriptor::new(MAX_TRACK_SEGMENTS, 1, float_4_format),
+        }
+    }
+}
+
+/// Size and internal format of a texture, from which it is both allocated and accounted for.
+#[derive(Debug, Clone, Copy)]
+struct TextureDescriptor {
+    width: u32,
+    height: u32,
+    layers: u32,
+    internal_format: u32,
+}
+
+impl TextureDescriptor {
+    fn new(width: u32, height: u32, internal_format: u32) -> Self {
+        Self { width, height, layers: 1, internal_format }
+    }
+
+    fn with_layers(self, layers: u32) -> Self {
+        Self { layers, ..self }
+    }
+
+    fn allocated_bytes(&self) -> u64 {
+        let bytes_per_texel = match self.internal_format {
+            GL::R8 | GL::R8UI => 1,
+            GL::R16F => 2,
+            GL::R32F | GL::R32UI | GL::RG16F | GL::RGBA8 => 4,
+            GL::RG32F | GL::RG32UI | GL::RGBA16F => 8,
+            GL::RGBA32F => 16,
+            format => unreachable!("no texel size for internal format {format:#x}"),
+        };
+
+        self.width as u64 * self.height as u64 * self.layers as u64 * bytes_per_texel
+    }
+}
+
+/// Returns the number of bytes of texture memory allocated by a new simulation with the given
+/// data texture precision. Canvas-sized render targets, such as the MSAA and picking buffers,
+/// and force fields set later on are not included.
+pub fn texture_memory_bytes(precision: Precision) -> u64 {
+    TextureId::ALL.iter()
+        .map(|id| id.descriptor(precision).allocated_bytes())
+        .sum()
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum FramebufferId {
     Partition,
     Update,
+    Metaball,
+    Msaa,
+    MsaaResolve,
+    Picking,
 }
 
 impl Default for FramebufferId {
@@ -120,7 +444,56 @@ impl Id for FramebufferId {}
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum UniformId {
-    DeltaTime
+    DeltaTime,
+    ImpulseMode,
+    Impulse,
+    ImpulseSeed,
+    Gravity,
+    Damping,
+    ColorMode,
+    ColorSpace,
+    RenderMode,
+    BoundaryMode,
+    WallRestitution,
+    ParticleRestitution,
+    CollisionRadiusScale,
+    VelocityScale,
+    PushRegion,
+    PushVelocity,
+    ForceOrder,
+    Drift,
+    BoundaryRange,
+    FalloffMode,
+    Opacity,
+    FreezeBrushCenter,
+    FreezeBrushRadius,
+    SinkCenter,
+    SinkRadius,
+    SinkProbability,
+    SinkSeed,
+    SpawnSoftTime,
+    RunningTime,
+    AgeColorLifetime,
+    Platforms,
+    PlatformCount,
+    GravityZoneBounds,
+    GravityZoneGravity,
+    GravityZoneCount,
+    Container,
+    ContainerCornerRadius,
+    TrackSegmentCount,
+    CollisionsEnabled,
+    MaxNeighborChecks,
+    MaxForce,
+    MorphProgress,
+    DepthEnabled,
+    DepthRange,
+    DrawStride,
+    DepthTest,
+    DragRange,
+    ForceFieldStrength,
+    SortedBinning,
+    View,
 }
 
 impl Default for UniformId {
@@ -134,7 +507,56 @@ impl Id for UniformId {}
 impl IdName for UniformId {
     fn name(&self) -> String {
         match self {
-            Self::DeltaTime => "dt"
+            Self::DeltaTime => "dt",
+            Self::ImpulseMode => "impulse_mode",
+            Self::Impulse => "impulse",
+            Self::ImpulseSeed => "impulse_seed",
+            Self::Gravity => "gravity",
+            Self::Damping => "damping",
+            Self::ColorMode => "color_mode",
+            Self::ColorSpace => "color_space",
+            Self::RenderMode => "render_mode",
+            Self::BoundaryMode => "boundary_mode",
+            Self::WallRestitution => "wall_restitution",
+            Self::ParticleRestitution => "particle_restitution",
+            Self::CollisionRadiusScale => "collision_radius_scale",
+            Self::VelocityScale => "velocity_scale",
+            Self::PushRegion => "push_region",
+            Self::PushVelocity => "push_velocity",
+            Self::ForceOrder => "force_order",
+            Self::Drift => "drift",
+            Self::BoundaryRange => "boundary_range",
+            Self::FalloffMode => "falloff_mode",
+            Self::Opacity => "opacity",
+            Self::FreezeBrushCenter => "freeze_brush_center",
+            Self::FreezeBrushRadius => "freeze_brush_radius",
+            Self::SinkCenter => "sink_center",
+            Self::SinkRadius => "sink_radius",
+            Self::SinkProbability => "sink_probability",
+            Self::SinkSeed => "sink_seed",
+            Self::SpawnSoftTime => "spawn_soft_time",
+            Self::RunningTime => "running_time",
+            Self::AgeColorLifetime => "age_color_lifetime",
+            Self::Platforms => "platforms",
+            Self::PlatformCount => "platform_count",
+            Self::GravityZoneBounds => "gravity_zone_bounds",
+            Self::GravityZoneGravity => "gravity_zone_gravity",
+            Self::GravityZoneCount => "gravity_zone_count",
+            Self::Container => "container",
+            Self::ContainerCornerRadius => "container_corner_radius",
+            Self::TrackSegmentCount => "track_segment_count",
+            Self::CollisionsEnabled => "collisions_enabled",
+            Self::MaxNeighborChecks => "max_neighbor_checks",
+            Self::MaxForce => "max_force",
+            Self::MorphProgress => "morph_progress",
+            Self::DepthEnabled => "depth_enabled",
+            Self::DepthRange => "depth_range",
+            Self::DrawStride => "draw_stride",
+            Self::DragRange => "drag_range",
+            Self::DepthTest => "depth_test",
+            Self::ForceFieldStrength => "force_field_strength",
+            Self::SortedBinning => "sorted_binning",
+            Self::View => "view",
         }.to_owned()
     }
 }
@@ -153,436 +575,5918 @@ type AppRenderData = RendererData<
     Rc<RefCell<RenderState>>
 >;
 
-#[derive(Debug, Clone)]
-struct RenderState {
-    delta_time_ms: f64,
-    particle_count: u32,
-    odd_frame: bool,
+#[derive(Debug, Error)]
+#[error("unknown {kind} \"{value}\"")]
+pub struct ParseModeError {
+    kind: &'static str,
+    value: String,
 }
 
-impl RenderState {
-    fn new(particle_count: u32) -> Self {
-        RenderState {
-            delta_time_ms: 0f64,
-            particle_count,
-            odd_frame: true,
+impl ParseModeError {
+    fn new(kind: &'static str, value: &str) -> Self {
+        ParseModeError {
+            kind,
+            value: value.to_owned(),
         }
     }
 }
 
-pub struct Graphics {
-    render_data: AppRenderData,
+/// Failure to build a program from shader source given at runtime, with the driver's log.
+#[derive(Debug, Error)]
+pub enum ShaderError {
+    #[error("could not compile the shader: {0}")]
+    Compile(String),
+    #[error("could not link the program: {0}")]
+    Link(String),
 }
 
-impl Graphics {
-    pub fn initialize_with_window(window: &Window) -> Self {
-        let particles = generate_particles(
-            PARTICLE_COUNT,
-            Vec2::splat(-1.0),
-            Vec2::splat(1.0),
-        );
-
-        let particle_count = particles.len() as u32;
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlendMode {
+    #[default]
+    Additive,
+    /// Draws the particles over each other with the particle opacity, see `Graphics::set_blend_mode`.
+    Alpha,
+    Opaque,
+}
 
-        let state = Rc::new(RefCell::new(RenderState::new(particle_count)));
+impl FromStr for BlendMode {
+    type Err = ParseModeError;
 
-        let canvas = window.canvas();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "additive" => Ok(Self::Additive),
+            "alpha" => Ok(Self::Alpha),
+            "opaque" => Ok(Self::Opaque),
+            _ => Err(ParseModeError::new("blend mode", s)),
+        }
+    }
+}
 
-        let draw_program_link = ProgramLink::new(
-            ProgramId::Draw,
-            VertexShaderId::Draw,
-            FragmentShaderId::Draw,
-        );
+/// Color space the draw pass blends particle colors in.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    /// Colors are written and blended as they are, i.e. in sRGB. Overlapping particles add up
+    /// too quickly, so dense areas look washed out.
+    #[default]
+    Srgb,
+    /// Colors are converted to linear, blended in an `SRGB8_ALPHA8` target and converted back
+    /// when presented. Overlaps brighten physically correctly, with smoother gradients and
+    /// darker, more saturated sparse areas.
+    Linear,
+}
 
-        let update_program_link = ProgramLink::new(
-            ProgramId::Update,
-            VertexShaderId::Update,
-            FragmentShaderId::Update,
-        );
+impl ColorSpace {
+    fn shader_value(self) -> i32 {
+        match self {
+            Self::Srgb => 0,
+            Self::Linear => 1,
+        }
+    }
 
-        let partition_program_link = ProgramLink::new(
-            ProgramId::Partition,
-            VertexShaderId::Partition,
-            FragmentShaderId::Partition,
-        );
+    /// Format of the offscreen color buffers the draw pass renders into.
+    fn target_format(self) -> u32 {
+        match self {
+            Self::Srgb => GL::RGBA8,
+            Self::Linear => GL::SRGB8_ALPHA8,
+        }
+    }
+}
 
-        let old_data_link = TextureLink::new(
-            TextureId::OldData,
-            move |ctx: &TextureCreateContext| {
-                create_data_texture_float32_4(
-                    ctx,
-                    DATA_TEXTURE_WIDTH,
-                    DATA_TEXTURE_HEIGHT,
-                    Some(bytemuck::cast_slice(particles.as_ref())),
-                )
-            },
-        );
+impl FromStr for ColorSpace {
+    type Err = ParseModeError;
 
-        let new_data_link = TextureLink::new(
-            TextureId::NewData,
-            |ctx: &TextureCreateContext| {
-                create_data_texture_float32_4(
-                    ctx,
-                    DATA_TEXTURE_WIDTH,
-                    DATA_TEXTURE_HEIGHT,
-                    None,
-                )
-            },
-        );
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srgb" => Ok(Self::Srgb),
+            "linear" => Ok(Self::Linear),
+            _ => Err(ParseModeError::new("color space", s)),
+        }
+    }
+}
 
-        let bins_link = TextureLink::new(
-            TextureId::Bins,
-            |ctx: &TextureCreateContext| create_data_texture_array_ui32_1(
-                ctx,
-                GRID_COLUMNS,
-                GRID_ROWS,
-                BIN_CAPACITY,
-            ),
-        );
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderMode {
+    /// One `GL::POINTS` sprite per particle. Fastest, but sprite sizes are capped by the driver.
+    #[default]
+    Points,
+    /// One instanced quad per particle, without a size limit.
+    Quads,
+    /// A smooth liquid surface: particles are splatted into a density texture as instanced quads,
+    /// which is then thresholded and shaded.
+    Metaball,
+}
 
-        let partition_intermediate_link = TextureLink::new(
-            TextureId::PartitionIntermediate,
-            |ctx: &TextureCreateContext| create_data_texture_integer(
-                ctx,
-                GRID_COLUMNS,
-                GRID_ROWS,
-            ),
-        );
+impl RenderMode {
+    fn shader_value(self) -> i32 {
+        match self {
+            Self::Points => 0,
+            Self::Quads | Self::Metaball => 1,
+        }
+    }
+}
 
-        let update_fb_link = FramebufferLink::new(
-            FramebufferId::Update,
-            |ctx: &FramebufferCreateContext| ctx.gl().create_framebuffer().unwrap(),
-            None,
-        );
+/// A simulation texture shown over the whole canvas in place of the particles.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DebugView {
+    #[default]
+    None,
+    /// Positions as red and green over the domain, blue where they are outside of it.
+    Positions,
+    /// Velocities as red and green around gray.
+    Velocities,
+    /// Occupied slots of every bin as brightness.
+    Bins,
+}
 
-        let binning_fb_link = FramebufferLink::new(
-            FramebufferId::Partition,
-            |ctx: &FramebufferCreateContext| {
-                let gl = ctx.gl();
+impl DebugView {
+    fn shader_value(self) -> i32 {
+        match self {
+            Self::None => 0,
+            Self::Positions => 1,
+            Self::Velocities => 2,
+            Self::Bins => 3,
+        }
+    }
+}
 
-                let fb = gl.create_framebuffer()
-                    .unwrap();
+impl FromStr for DebugView {
+    type Err = ParseModeError;
 
-                gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&fb));
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "positions" => Ok(Self::Positions),
+            "velocities" => Ok(Self::Velocities),
+            "bins" => Ok(Self::Bins),
+            _ => Err(ParseModeError::new("debug view", s)),
+        }
+    }
+}
 
-                // let depth_rb = gl.create_renderbuffer()
-                //     .unwrap();
-                //
-                // gl.bind_renderbuffer(
-                //     GL::RENDERBUFFER,
-                //     Some(&depth_rb),
-                // );
-                //
-                // gl.renderbuffer_storage(
-                //     GL::RENDERBUFFER,
-                //     GL::DEPTH_COMPONENT24,
-                //     GRID_ROWS as i32,
-                //     GRID_COLUMNS as i32,
-                // );
-                //
-                // gl.framebuffer_renderbuffer(
-                //     GL::FRAMEBUFFER,
-                //     GL::DEPTH_ATTACHMENT,
-                //     GL::RENDERBUFFER,
-                //     Some(&depth_rb),
-                // );
-                //
-                // gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+impl FromStr for RenderMode {
+    type Err = ParseModeError;
 
-                fb
-            },
-            None,
-        );
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "points" => Ok(Self::Points),
+            "quads" => Ok(Self::Quads),
+            "metaball" => Ok(Self::Metaball),
+            _ => Err(ParseModeError::new("render mode", s)),
+        }
+    }
+}
 
-        let mut delta_time_link = {
-            let state = state.clone();
+/// How the brightness of a particle falls off from its center to its edge.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FalloffMode {
+    /// Uniform brightness up to a hard edge.
+    #[default]
+    Flat,
+    Linear,
+    Gaussian,
+    InverseSquare,
+}
 
-            UniformLink::new(
-                ProgramId::Update,
-                UniformId::DeltaTime,
-                move |ctx: &UniformContext| {
-                    let gl = ctx.gl();
+impl FalloffMode {
+    fn shader_value(self) -> i32 {
+        match self {
+            Self::Flat => 0,
+            Self::Linear => 1,
+            Self::Gaussian => 2,
+            Self::InverseSquare => 3,
+        }
+    }
+}
 
-                    gl.uniform1f(
-                        Some(ctx.uniform_location()),
-                        (state.borrow().delta_time_ms / 1000.0 * TIME_SCALE) as f32,
-                    );
-                },
-            )
-        };
+impl FromStr for FalloffMode {
+    type Err = ParseModeError;
 
-        delta_time_link.set_use_init_callback_for_update(true);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(Self::Flat),
+            "linear" => Ok(Self::Linear),
+            "gaussian" => Ok(Self::Gaussian),
+            "inverse-square" => Ok(Self::InverseSquare),
+            _ => Err(ParseModeError::new("falloff mode", s)),
+        }
+    }
+}
 
-        let mut render_data_builder = RendererData::builder();
+/// What happens to particles that reach the edge of the simulation domain.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BoundaryMode {
+    /// Particles leave the domain.
+    #[default]
+    None,
+    /// Particles are reflected by the walls, losing velocity according to the wall restitution.
+    Bounce,
+    /// Particles leaving through one side re-enter through the opposite one.
+    Wrap,
+}
 
-        render_data_builder
-            .set_canvas(canvas)
-            .set_user_ctx(state)
-            .add_vertex_shader_src(VertexShaderId::Draw, DRAW_VERTEX)
-            .add_fragment_shader_src(FragmentShaderId::Draw, DRAW_FRAGMENT)
-            .add_vertex_shader_src(VertexShaderId::Update, UPDATE_VERTEX)
-            .add_fragment_shader_src(FragmentShaderId::Update, UPDATE_FRAGMENT)
-            .add_vertex_shader_src(VertexShaderId::Partition, PARTITION_VERTEX)
-            .add_fragment_shader_src(FragmentShaderId::Partition, PARTITION_FRAGMENT)
-            .add_program_link(draw_program_link)
-            .add_program_link(update_program_link)
-            .add_program_link(partition_program_link)
-            .add_uniform_link(delta_time_link)
-            .add_texture_link(old_data_link)
-            .add_texture_link(new_data_link)
-            .add_texture_link(partition_intermediate_link)
-            .add_texture_link(bins_link)
-            .add_framebuffer_link(update_fb_link)
-            .add_framebuffer_link(binning_fb_link)
-            .set_render_callback(Graphics::render_callback);
+impl BoundaryMode {
+    fn shader_value(self) -> i32 {
+        match self {
+            Self::None => 0,
+            Self::Bounce => 1,
+            Self::Wrap => 2,
+        }
+    }
+}
 
-        let render_data = render_data_builder.build_renderer_data().unwrap();
+impl FromStr for BoundaryMode {
+    type Err = ParseModeError;
 
-        let gl = render_data.gl();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "bounce" => Ok(Self::Bounce),
+            "wrap" => Ok(Self::Wrap),
+            _ => Err(ParseModeError::new("boundary mode", s)),
+        }
+    }
+}
 
-        gl.get_extension("EXT_color_buffer_float")
-            .unwrap();
+/// An axis of the simulation domain.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    X,
+    Y,
+}
 
-        gl.clear_depth(1.0);
-        gl.clear_color(0.0, 0.0, 0.0, 1.0);
+impl Axis {
+    /// Index of the component along the axis.
+    fn index(self) -> usize {
+        match self {
+            Self::X => 0,
+            Self::Y => 1,
+        }
+    }
+}
 
-        gl.depth_func(GL::LESS);
+/// The order in which the update pass applies gravity and damping, collisions and the movement
+/// with its boundary handling within a step.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForceOrder {
+    /// Collisions and movement first, then forces. Contacts are resolved with last step's
+    /// velocity, so dense piles slowly sink and jitter, but sparse scenes are unaffected.
+    #[default]
+    CollisionsFirst,
+    /// Forces first, then collisions and movement (semi-implicit Euler). Collisions see the
+    /// velocity gained during the step, which keeps dense, resting scenes the most stable.
+    GravityFirst,
+    /// Half of the forces before and half after collisions and movement. Tracks free-flight
+    /// trajectories most accurately, which suits sparse and orbital scenes best.
+    Split,
+}
 
-        Self {
-            render_data
+impl ForceOrder {
+    fn shader_value(self) -> i32 {
+        match self {
+            Self::CollisionsFirst => 0,
+            Self::GravityFirst => 1,
+            Self::Split => 2,
         }
     }
+}
 
-    pub fn frame(&self, delta_time_ms: f64) {
-        debug!("Time elapsed since previous frame (ms): {}", delta_time_ms);
+impl FromStr for ForceOrder {
+    type Err = ParseModeError;
 
-        self.update(delta_time_ms);
-        self.render_data.render();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "collisions-first" => Ok(Self::CollisionsFirst),
+            "gravity-first" => Ok(Self::GravityFirst),
+            "split" => Ok(Self::Split),
+            _ => Err(ParseModeError::new("force order", s)),
+        }
     }
+}
 
-    pub fn event(&self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::Resized(new_size) => self.on_resize(*new_size),
-            WindowEvent::ScaleFactorChanged { new_inner_size, .. } =>
-                self.on_resize(**new_inner_size),
-            _ => {}
-        }
+/// How the update pass resolves collisions between particles. Every particle is updated in
+/// parallel from the positions and velocities of the previous step.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionScheme {
+    /// Overlaps and velocities are resolved together in a single pass. Both particles of a pair
+    /// correct their position from the same stale overlap, which may add energy in dense piles.
+    #[default]
+    Combined,
+    /// Overlaps are pushed apart along the contact normal in a first pass, and velocities are
+    /// exchanged from the separated positions in a second one. Costs an extra pass, but pairs
+    /// are resolved symmetrically and dense piles jitter less.
+    Split,
+}
 
-        false
+impl FromStr for CollisionScheme {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "combined" => Ok(Self::Combined),
+            "split" => Ok(Self::Split),
+            _ => Err(ParseModeError::new("collision scheme", s)),
+        }
     }
+}
 
-    fn update(&self, delta_time_ms: f64) {
-        {
-            let mut ctx = self.render_data.user_ctx()
-                .unwrap().borrow_mut();
+/// Part of the collision response run by an update pass, see [`CollisionScheme`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum CollisionStage {
+    /// The whole step with the combined collision response.
+    Full,
+    /// Only separates overlapping particles, the rest of the step is left to `Velocities`.
+    Positions,
+    /// The whole step, with collisions only exchanging velocities.
+    Velocities,
+}
 
-            ctx.delta_time_ms = delta_time_ms;
-            ctx.odd_frame = !ctx.odd_frame;
+impl CollisionStage {
+    fn shader_value(self) -> i32 {
+        match self {
+            Self::Full => 0,
+            Self::Positions => 1,
+            Self::Velocities => 2,
         }
+    }
+}
 
-        self.render_data.update_uniforms();
+/// How the binning pass distributes particles among the `BIN_CAPACITY` slots of a grid cell.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BinningMode {
+    /// Fills the slots one at a time with the highest ids below those already binned.
+    /// Takes `BIN_CAPACITY` point draws and texture copies per step.
+    #[default]
+    Layered,
+    /// Assigns each particle to the slot given by its id modulo `BIN_CAPACITY` and keeps the
+    /// highest id per slot using `MAX` blending, then writes all slots with a single MRT pass.
+    /// Takes two draws per step, but a cell may drop particles before it is full.
+    /// Requires `EXT_float_blend`.
+    Slotted,
+    /// Sorts (cell, id) keys for all particles with a bitonic sort and finds the range of keys of
+    /// every cell with a binary search, which gives the offsets a prefix sum over the cell counts
+    /// would. Never drops particles, but takes `log2(n) * (log2(n) + 1) / 2` passes over the keys.
+    /// The bins texture is left untouched, so occupancy statistics and density colors are stale.
+    Sorted,
+}
+
+impl FromStr for BinningMode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "layered" => Ok(Self::Layered),
+            "slotted" => Ok(Self::Slotted),
+            "sorted" => Ok(Self::Sorted),
+            _ => Err(ParseModeError::new("binning mode", s)),
+        }
     }
+}
 
-    fn render_callback(render_data: &AppRenderData) {
-        let gl = render_data.gl();
+/// When the binning pass clears its intermediate texture. Anything but the default leaves stale
+/// ids in the bins and exists to measure the cost of the clears.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BinClearMode {
+    /// Before every layer of layered binning, and before slotted binning.
+    #[default]
+    PerLayer,
+    /// Only before the first layer. Same as `PerLayer` for slotted binning.
+    Once,
+    /// Never, so ids from previous frames persist.
+    Never,
+}
 
-        let state = render_data.user_ctx()
-            .unwrap()
-            .borrow();
+impl FromStr for BinClearMode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per-layer" => Ok(Self::PerLayer),
+            "once" => Ok(Self::Once),
+            "never" => Ok(Self::Never),
+            _ => Err(ParseModeError::new("bin clear mode", s)),
+        }
+    }
+}
+
+/// How the domain is fitted into a canvas of a different aspect ratio.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewportMode {
+    /// Covers the whole canvas, distorting the domain.
+    #[default]
+    Stretch,
+    /// Keeps the aspect ratio and shows the whole domain centered, with bars on two sides.
+    Fit,
+    /// Keeps the aspect ratio and covers the whole canvas, cropping two sides of the domain.
+    Fill,
+}
+
+impl ViewportMode {
+    fn viewport(self, canvas_width: u32, canvas_height: u32) -> Viewport {
+        let canvas_size = Vec2::new(canvas_width as f32, canvas_height as f32);
+        let domain_size = DOMAIN_MAX - DOMAIN_MIN;
+
+        let scale = canvas_size / domain_size;
+
+        let size = match self {
+            Self::Stretch => canvas_size,
+            Self::Fit => domain_size * scale.min_element(),
+            Self::Fill => domain_size * scale.max_element(),
+        }.round();
+
+        let origin = ((canvas_size - size) / 2.0).round();
+
+        Viewport {
+            x: origin.x as i32,
+            y: origin.y as i32,
+            width: size.x as i32,
+            height: size.y as i32,
+        }
+    }
+}
+
+impl FromStr for ViewportMode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stretch" => Ok(Self::Stretch),
+            "fit" => Ok(Self::Fit),
+            "fill" => Ok(Self::Fill),
+            _ => Err(ParseModeError::new("viewport mode", s)),
+        }
+    }
+}
+
+/// Mirror images of the particles drawn on top of each other for kaleidoscope effects. Only the
+/// rendering is replicated, the particles are simulated once.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MirrorMode {
+    #[default]
+    None,
+    /// Mirrored at the vertical axis through the center of the domain.
+    Horizontal,
+    /// Mirrored at both axes through the center of the domain.
+    Quad,
+    /// Turned by 120 and 240 degrees and mirrored, like in a kaleidoscope with three mirrors at
+    /// 60 degrees.
+    Hex,
+}
+
+impl MirrorMode {
+    /// Transforms of the domain around its center, one per drawn copy.
+    fn transforms(self) -> Vec<Mat2> {
+        let flip_x = Mat2::from_diagonal(Vec2::new(-1.0, 1.0));
+
+        match self {
+            Self::None => vec![Mat2::IDENTITY],
+            Self::Horizontal => vec![Mat2::IDENTITY, flip_x],
+            Self::Quad => vec![Mat2::IDENTITY, flip_x, -flip_x, -Mat2::IDENTITY],
+            Self::Hex => (0..3)
+                .map(|i| Mat2::from_angle(i as f32 * TAU / 3.0))
+                .flat_map(|rotation| [rotation, rotation * flip_x])
+                .collect(),
+        }
+    }
+}
+
+impl FromStr for MirrorMode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "horizontal" => Ok(Self::Horizontal),
+            "quad" => Ok(Self::Quad),
+            "hex" => Ok(Self::Hex),
+            _ => Err(ParseModeError::new("mirror mode", s)),
+        }
+    }
+}
+
+/// Rectangle of the canvas the domain is drawn into, in pixels from the bottom left corner.
+/// Extends past the canvas with `ViewportMode::Fill`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Viewport {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Viewport {
+    fn origin(self) -> Vec2 {
+        Vec2::new(self.x as f32, self.y as f32)
+    }
+
+    fn size(self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32)
+    }
+
+    /// Size of a pixel in clip space units along the longer side, which particle sizes are
+    /// based on.
+    fn pixel_size(self) -> f32 {
+        1.0 / self.size().max_element()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    #[default]
+    Solid,
+    /// Colors particles by the length of their velocity.
+    Speed,
+    /// Colors particles by how full their grid cell was during the last binning pass.
+    Density,
+    /// Colors particles by their index in the data texture.
+    Index,
+    /// Colors particles by the color they were spawned with, see `Graphics::set_spawn_color`.
+    Spawn,
+    /// Colors particles by their drag coefficient within the range it was drawn from.
+    Drag,
+}
+
+impl ColorMode {
+    fn shader_value(self) -> i32 {
+        match self {
+            Self::Solid => 0,
+            Self::Speed => 1,
+            Self::Density => 2,
+            Self::Index => 3,
+            Self::Spawn => 4,
+            Self::Drag => 5,
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "solid" => Ok(Self::Solid),
+            "speed" => Ok(Self::Speed),
+            "density" => Ok(Self::Density),
+            "index" => Ok(Self::Index),
+            "spawn" => Ok(Self::Spawn),
+            "drag" => Ok(Self::Drag),
+            _ => Err(ParseModeError::new("color mode", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImpulseMode {
+    /// Adds the impulse vector to every particle.
+    Uniform,
+    /// Pushes particles away from the domain center, scaling the direction by the impulse.
+    Radial,
+    /// Pushes every particle in its own random direction, scaled by the impulse.
+    Random,
+}
+
+impl ImpulseMode {
+    fn shader_value(self) -> i32 {
+        match self {
+            Self::Uniform => 1,
+            Self::Radial => 2,
+            Self::Random => 3,
+        }
+    }
+}
+
+impl FromStr for ImpulseMode {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Self::Uniform),
+            "radial" => Ok(Self::Radial),
+            "random" => Ok(Self::Random),
+            _ => Err(ParseModeError::new("impulse mode", s)),
+        }
+    }
+}
+
+/// A two-component field of the particle data that can be read back separately.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParticleField {
+    Position,
+    Velocity,
+}
+
+impl ParticleField {
+    /// Index of the first channel of the field in an `RGBA` data texel.
+    fn channel(self) -> usize {
+        match self {
+            Self::Position => 0,
+            Self::Velocity => 2,
+        }
+    }
+}
+
+/// Maps the simulation domain onto the canvas: `center` is shown in the middle of the canvas,
+/// magnified by `zoom`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Camera {
+    center: Vec2,
+    zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            center: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    fn clip_to_domain(self, position: Vec2) -> Vec2 {
+        self.center + position / self.zoom
+    }
+
+    /// Zooms by the change of distance between two touch points and pans so that the domain point
+    /// under their midpoint follows it. Positions are in clip space.
+    fn pinch(&mut self, before: [Vec2; 2], after: [Vec2; 2]) {
+        let old_distance = before[0].distance(before[1]);
+        let new_distance = after[0].distance(after[1]);
+
+        let anchor = self.clip_to_domain((before[0] + before[1]) / 2.0);
+
+        if old_distance > 0.0 && new_distance > 0.0 {
+            self.zoom = (self.zoom * new_distance / old_distance).clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+
+        self.center = anchor - (after[0] + after[1]) / 2.0 / self.zoom;
+    }
+
+    /// Camera `t` of the way from `self` to `other`. The zoom changes by the same factor in
+    /// equal steps, so that zooming in and out feel alike.
+    fn lerp(self, other: Camera, t: f32) -> Camera {
+        Camera {
+            center: self.center.lerp(other.center, t),
+            zoom: self.zoom * (other.zoom / self.zoom).powf(t),
+        }
+    }
+}
+
+/// Progress curve of animated camera moves.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    /// Starts and ends slowly.
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps the share of the elapsed time in [0, 1] to the share of the way covered.
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Self::Linear => t,
+            Self::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+impl FromStr for Easing {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "ease-in-out" => Ok(Self::EaseInOut),
+            _ => Err(ParseModeError::new("easing", s)),
+        }
+    }
+}
+
+/// Animated move of the camera, see [`Graphics::set_camera`].
+#[derive(Debug, Copy, Clone)]
+struct CameraTransition {
+    from: Camera,
+    to: Camera,
+    easing: Easing,
+    duration_ms: f64,
+    elapsed_ms: f64,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Impulse {
+    mode: ImpulseMode,
+    strength: Vec2,
+    seed: f32,
+}
+
+/// Velocity added once to the particles inside an axis-aligned rectangle.
+#[derive(Debug, Copy, Clone)]
+struct RegionPush {
+    min: Vec2,
+    max: Vec2,
+    velocity: Vec2,
+}
+
+/// Circular region that removes the particles entering it.
+#[derive(Debug, Copy, Clone)]
+struct Sink {
+    center: Vec2,
+    radius: f32,
+    /// Chance of a particle inside the sink to be absorbed in each simulation step.
+    probability: f32,
+}
+
+/// Gravity of constant magnitude turning around at a constant rate, see
+/// [`Graphics::set_gravity_rotation`].
+#[derive(Debug, Copy, Clone)]
+struct GravityRotation {
+    /// Counterclockwise.
+    degrees_per_second: f32,
+    magnitude: f32,
+    /// Current direction in radians, counterclockwise from the positive x axis.
+    angle: f32,
+}
+
+/// Camera panning towards the densest region of the domain, see [`Graphics::set_auto_camera`].
+#[derive(Debug, Copy, Clone)]
+struct AutoCamera {
+    /// Time constant of the panning, the camera covers about 63% of the way in that time.
+    smoothing_ms: f32,
+    /// Region the camera heads for and the center of the particles in it.
+    target: Option<(usize, Vec2)>,
+    last_measurement_ms: f64,
+}
+
+/// Line segment particles collide with from both sides, like with the walls.
+#[derive(Debug, Copy, Clone)]
+struct Platform {
+    start: Vec2,
+    end: Vec2,
+}
+
+/// Axis-aligned rectangle in which `gravity` takes the place of the global gravity.
+#[derive(Debug, Copy, Clone)]
+struct GravityZone {
+    min: Vec2,
+    max: Vec2,
+    gravity: Vec2,
+}
+
+/// Shape the particles are kept inside of, within the domain. Outside of it, nothing is drawn and
+/// the page shows through the canvas.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Container {
+    Circle { center: Vec2, radius: f32 },
+    RoundedRect { center: Vec2, half_size: Vec2, corner_radius: f32 },
+}
+
+impl Container {
+    /// Scales the shape about the origin, e.g. from API units to domain coordinates.
+    pub fn scaled(self, factor: f32) -> Self {
+        match self {
+            Self::Circle { center, radius } => Self::Circle { center: center * factor, radius: radius * factor },
+            Self::RoundedRect { center, half_size, corner_radius } => Self::RoundedRect {
+                center: center * factor,
+                half_size: half_size * factor,
+                corner_radius: corner_radius * factor,
+            },
+        }
+    }
+
+    /// Center, half size and corner radius of the shape as a rounded rectangle. A circle is a
+    /// square with fully rounded corners.
+    fn rounded_rect(self) -> (Vec2, Vec2, f32) {
+        match self {
+            Self::Circle { center, radius } => (center, Vec2::splat(radius), radius),
+            Self::RoundedRect { center, half_size, corner_radius } =>
+                (center, half_size, corner_radius.clamp(0.0, half_size.min_element())),
+        }
+    }
+}
+
+/// Storage format of the particle data textures.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Precision {
+    /// `RGBA32F`, 16 bytes per particle.
+    #[default]
+    Float32,
+    /// `RGBA16F`, 8 bytes per particle. Halves the bandwidth of every pass, but positions near
+    /// the domain edges only resolve to about 5e-4 and small per-step velocity changes are
+    /// rounded away, so long-running simulations slowly lose energy and particles may stall
+    /// or drift onto a visible lattice.
+    Float16,
+}
+
+impl Precision {
+    /// Returns the internal format and the upload data type of a data texture.
+    fn texture_formats(self) -> (u32, u32) {
+        match self {
+            Self::Float32 => (GL::RGBA32F, GL::FLOAT),
+            Self::Float16 => (GL::RGBA16F, GL::HALF_FLOAT),
+        }
+    }
+
+    /// Copies `data` into a typed array matching the upload data type.
+    fn data_array(self, data: &[f32]) -> Object {
+        match self {
+            Self::Float32 => Float32Array::from(data).into(),
+            Self::Float16 => {
+                let half_data = data.iter()
+                    .map(|&val| f16::from_f32(val).to_bits())
+                    .collect::<Vec<u16>>();
+
+                Uint16Array::from(half_data.as_slice()).into()
+            }
+        }
+    }
+}
+
+impl FromStr for Precision {
+    type Err = ParseModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f32" => Ok(Self::Float32),
+            "f16" => Ok(Self::Float16),
+            _ => Err(ParseModeError::new("precision", s)),
+        }
+    }
+}
+
+/// Settings that are fixed once the graphics resources have been created.
+#[derive(Debug, Clone)]
+pub struct GraphicsOptions {
+    pub precision: Precision,
+    /// Number of simulation steps run without gravity and with strong damping before the first
+    /// frame, so that overlapping initial particles relax instead of exploding.
+    pub settle_steps: u32,
+    /// Range the velocity components of generated particles are drawn from, also on resets.
+    pub min_velocity: Vec2,
+    pub max_velocity: Vec2,
+    /// How the initial velocities of the particles follow from their positions, also on resets.
+    pub initial_velocity: VelocityField,
+    /// Range the particle masses are drawn from. Masses are kept on resets.
+    pub min_mass: f32,
+    pub max_mass: f32,
+    /// Range the drag coefficients of the particles are drawn from, per unit of time. Kept on
+    /// resets like the masses.
+    pub min_drag: f32,
+    pub max_drag: f32,
+    /// World units per simulation unit. The simulation domain spans 2 simulation units, so with
+    /// a scale of 5, it is 10 API units across.
+    pub api_unit_scale: f32,
+    /// Minimum distance between the centers of particles generated on startup and resets, in
+    /// particle diameters, or `None` to place them independently of each other.
+    pub start_spacing: Option<f32>,
+}
+
+impl Default for GraphicsOptions {
+    fn default() -> Self {
+        GraphicsOptions {
+            precision: Precision::default(),
+            settle_steps: 0,
+            min_velocity: MIN_VELOCITY,
+            max_velocity: MAX_VELOCITY,
+            initial_velocity: VelocityField::default(),
+            min_mass: MIN_MASS,
+            max_mass: MAX_MASS,
+            min_drag: 0.0,
+            max_drag: 0.0,
+            api_unit_scale: 1.0,
+            start_spacing: None,
+        }
+    }
+}
+
+/// Color buffer the draw pass renders into when MSAA or linear blending is enabled, multisampled
+/// with MSAA, its depth buffer for the depth test, and the texture it is resolved into before
+/// being drawn onto the canvas. All of them match the canvas size.
+#[derive(Debug, Clone)]
+struct MsaaTargets {
+    color: WebGlRenderbuffer,
+    depth: WebGlRenderbuffer,
+    resolved: WebGlTexture,
+}
+
+/// Locations of the active uniforms of every program, looked up once after linking so that
+/// the passes do not query them by name every frame.
+#[derive(Debug, Clone, Default)]
+struct UniformLocations {
+    locations: HashMap<ProgramId, HashMap<String, WebGlUniformLocation>>,
+}
+
+impl UniformLocations {
+    fn new(render_data: &AppRenderData) -> Self {
+        let gl = render_data.gl();
+
+        let locations = render_data.programs()
+            .iter()
+            .map(|(program_id, program)| (program_id.clone(), active_uniform_locations(gl, program)))
+            .collect();
+
+        UniformLocations { locations }
+    }
+
+    /// Replaces the locations of `program_id` by those of `program`, which is used in its place.
+    fn set_program(&mut self, gl: &GL, program_id: ProgramId, program: &WebGlProgram) {
+        self.locations.insert(program_id, active_uniform_locations(gl, program));
+    }
+
+    /// Like `get`, but for uniforms a program replaced at runtime may not have.
+    fn find(&self, program_id: &ProgramId, name: &str) -> Option<&WebGlUniformLocation> {
+        self.locations.get(program_id)?.get(name)
+    }
+
+    /// Panics if `name` is not an active uniform of the program, like an unwrapped lookup would.
+    fn get(&self, program_id: &ProgramId, name: &str) -> &WebGlUniformLocation {
+        self.locations.get(program_id)
+            .and_then(|program_locations| program_locations.get(name))
+            .unwrap_or_else(|| panic!("{name} is not an active uniform of {program_id:?}"))
+    }
+}
+
+/// What a velocity reduction read back asynchronously is used for.
+#[derive(Debug, Clone)]
+enum ReductionUse {
+    /// Removing the drift in the next update pass.
+    Drift,
+    /// Resolving a promise with the maximum speed, in API units.
+    MaxSpeed(Function),
+}
+
+/// Animation moving every particle onto its target position, see [`Graphics::morph_to`].
+#[derive(Debug, Copy, Clone)]
+struct Morph {
+    elapsed_ms: f64,
+    duration_ms: f64,
+}
+
+impl Morph {
+    fn progress(self) -> f32 {
+        if self.duration_ms > 0.0 {
+            (self.elapsed_ms / self.duration_ms).min(1.0) as f32
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Parts of the render state that `Graphics::update` advances, which are saved to replay
+/// simulation steps from the same starting point.
+#[derive(Debug, Copy, Clone)]
+struct StepSnapshot {
+    elapsed_ms: f64,
+    frame_count: u64,
+    running_ms: f64,
+    fps: f64,
+    last_occupancy_stats_ms: f64,
+    odd_frame: bool,
+    paused: bool,
+    gravity: Vec2,
+    gravity_rotation: Option<GravityRotation>,
+    camera: Camera,
+    camera_transition: Option<CameraTransition>,
+    morph: Option<Morph>,
+    pending_impulse: Option<Impulse>,
+    pending_velocity_scale: Option<f32>,
+    pending_region_push: Option<RegionPush>,
+    pending_drift: Option<Vec2>,
+}
+
+impl StepSnapshot {
+    fn save(state: &RenderState) -> Self {
+        StepSnapshot {
+            elapsed_ms: state.elapsed_ms,
+            frame_count: state.frame_count,
+            running_ms: state.running_ms,
+            fps: state.fps,
+            last_occupancy_stats_ms: state.last_occupancy_stats_ms,
+            odd_frame: state.odd_frame,
+            paused: state.paused,
+            gravity: state.gravity,
+            gravity_rotation: state.gravity_rotation,
+            camera: state.camera,
+            camera_transition: state.camera_transition,
+            morph: state.morph,
+            pending_impulse: state.pending_impulse,
+            pending_velocity_scale: state.pending_velocity_scale,
+            pending_region_push: state.pending_region_push,
+            pending_drift: state.pending_drift,
+        }
+    }
+
+    fn restore(self, state: &mut RenderState) {
+        state.elapsed_ms = self.elapsed_ms;
+        state.frame_count = self.frame_count;
+        state.running_ms = self.running_ms;
+        state.fps = self.fps;
+        state.last_occupancy_stats_ms = self.last_occupancy_stats_ms;
+        state.odd_frame = self.odd_frame;
+        state.paused = self.paused;
+        state.gravity = self.gravity;
+        state.gravity_rotation = self.gravity_rotation;
+        state.camera = self.camera;
+        state.camera_transition = self.camera_transition;
+        state.morph = self.morph;
+        state.pending_impulse = self.pending_impulse;
+        state.pending_velocity_scale = self.pending_velocity_scale;
+        state.pending_region_push = self.pending_region_push;
+        state.pending_drift = self.pending_drift;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RenderState {
+    delta_time_ms: f64,
+    elapsed_ms: f64,
+    frame_count: u64,
+    draw_every: u32,
+    /// Draws only every `draw_stride`-th particle, while all of them are simulated.
+    draw_stride: u32,
+    time_scale: f64,
+    particle_count: u32,
+    precision: Precision,
+    api_unit_scale: f32,
+    min_velocity: Vec2,
+    max_velocity: Vec2,
+    initial_velocity: VelocityField,
+    /// Minimum distance between the centers of generated particles on startup and resets.
+    min_start_distance: Option<f32>,
+    masses: Rc<[f32]>,
+    /// Range the drag coefficients were drawn from, which the drag color mode spans.
+    drag_range: Vec2,
+    uniform_locations: UniformLocations,
+    /// Base seed of the seeded resets, and the number of seeded resets done since it was set.
+    seed: u64,
+    seeded_reset_count: u64,
+    odd_frame: bool,
+    paused: bool,
+    pause_on_blur: bool,
+    /// Whether the simulation was paused by losing focus, and should resume on regaining it.
+    paused_on_blur: bool,
+    blend_mode: BlendMode,
+    /// Opacity of the particles in the `Alpha` blend mode.
+    particle_opacity: f32,
+    render_mode: RenderMode,
+    color_mode: ColorMode,
+    /// Colors the particles by their age on the palette instead of by `color_mode`, reaching
+    /// its end at this age.
+    age_color_lifetime_ms: Option<f32>,
+    color_space: ColorSpace,
+    falloff_mode: FalloffMode,
+    show_bounds: bool,
+    show_hud: bool,
+    /// Frame rate averaged over the last frames, as shown by the parameter readout.
+    fps: f64,
+    debug_view: DebugView,
+    viewport_mode: ViewportMode,
+    mirror_mode: MirrorMode,
+    /// Draws every frame regardless of `draw_every`, so that each one can be read back.
+    capturing: bool,
+    depth_enabled: bool,
+    /// Size and brightness factors of particles at depth 0 and 1.
+    depth_range: Vec2,
+    depth_test: bool,
+    picking_enabled: bool,
+    msaa_samples: u32,
+    msaa_targets: Option<MsaaTargets>,
+    /// `R32UI` texture of the canvas size holding the index plus one of the particle drawn
+    /// at each pixel, if picking is enabled.
+    picking_texture: Option<WebGlTexture>,
+    /// Program built from a custom update shader, used instead of the built-in one.
+    custom_update_program: Option<WebGlProgram>,
+    binning_mode: BinningMode,
+    bin_clear_mode: BinClearMode,
+    float_blend_supported: bool,
+    float_linear_supported: bool,
+    keyboard_controls_enabled: bool,
+    freeze_brush_enabled: bool,
+    /// Radius of the freeze brush in canvas pixels.
+    freeze_brush_radius: f32,
+    spawn_on_click_enabled: bool,
+    spawn_burst_count: u32,
+    /// Color the particles of the next bursts are spawned with.
+    spawn_color: [u8; 4],
+    /// How long the collisions of spawned particles are softened for, 0 if they are not.
+    spawn_soft_time_ms: f32,
+    /// Time the simulation has been running for, without the pauses. Spawn times refer to it.
+    running_ms: f64,
+    /// Id of the first particle the next burst replaces. Bursts take over the particles in
+    /// order and start over at the first one, as the particle count is fixed.
+    next_spawn_id: u32,
+    sink: Option<Sink>,
+    platforms: [Option<Platform>; MAX_PLATFORMS],
+    platforms_enabled: bool,
+    /// At most `MAX_GRAVITY_ZONES`, in the order they were added.
+    gravity_zones: Vec<GravityZone>,
+    container: Option<Container>,
+    /// Clears to transparent instead of black, so that the page shows through around the particles.
+    transparent_background: bool,
+    /// Number of segments written to the track texture.
+    track_segment_count: u32,
+    track_enabled: bool,
+    /// Position of the mouse cursor in the simulation domain, if it is over the canvas.
+    cursor_position: Option<Vec2>,
+    mouse_pressed: bool,
+    camera: Camera,
+    camera_transition: Option<CameraTransition>,
+    auto_camera: Option<AutoCamera>,
+    touch_controls_enabled: bool,
+    /// Ids and clip space positions of the touch points tracked for the touch controls, at most two.
+    touches: Vec<(u64, Vec2)>,
+    gravity: Vec2,
+    gravity_rotation: Option<GravityRotation>,
+    /// Time of the last resize whose canvas-sized targets have not been reallocated yet.
+    pending_resize_ms: Option<f64>,
+    damping: f32,
+    /// Boundary modes of the x and y axes.
+    boundary_modes: [BoundaryMode; 2],
+    force_order: ForceOrder,
+    collisions_enabled: bool,
+    /// Upper bound on the neighbors tested for collisions per particle and step, if any.
+    max_neighbor_checks: Option<u32>,
+    collision_scheme: CollisionScheme,
+    wall_restitution: f32,
+    particle_restitution: f32,
+    /// Factor the radius used in collisions between particles is multiplied by.
+    collision_radius_scale: f32,
+    /// Upper bound on the magnitude of the force applied to a single particle.
+    max_force: f32,
+    /// Factor the accelerations sampled from the force field are multiplied by.
+    force_field_strength: f32,
+    pending_impulse: Option<Impulse>,
+    impulse: Option<Impulse>,
+    pending_velocity_scale: Option<f32>,
+    velocity_scale: f32,
+    pending_region_push: Option<RegionPush>,
+    region_push: Option<RegionPush>,
+    remove_drift: bool,
+    pending_drift: Option<Vec2>,
+    drift: Vec2,
+    /// Indices of the boundary particles, from the first to one past the last.
+    boundary_range: UVec2,
+    morph: Option<Morph>,
+    /// Morph progress before and after the current step, or zero when no morph is running.
+    morph_progress: Vec2,
+    occupancy_stats_enabled: bool,
+    last_occupancy_stats_ms: f64,
+    collect_occupancy_stats: bool,
+    conservation_check_enabled: bool,
+    lost_particle_check_enabled: bool,
+    error_callback: Option<Function>,
+    /// Fences of the GPU work issued before `Graphics::sync` was called, and the callbacks to
+    /// call once the GPU has passed them.
+    pending_syncs: Vec<(WebGlSync, Function)>,
+    /// Fences of the reductions copied into pixel buffers but not read back yet, see
+    /// `Graphics::request_reduction`.
+    pending_reductions: Vec<(WebGlSync, WebGlBuffer, ReductionUse)>,
+    reset_on_error: bool,
+    last_corruption_check_ms: f64,
+    stats: SharedStats,
+}
+
+impl RenderState {
+    fn new(particle_count: u32, masses: Rc<[f32]>, options: &GraphicsOptions) -> Self {
+        RenderState {
+            delta_time_ms: 0f64,
+            elapsed_ms: 0f64,
+            frame_count: 0,
+            draw_every: 1,
+            draw_stride: 1,
+            time_scale: TIME_SCALE,
+            particle_count,
+            precision: options.precision,
+            api_unit_scale: options.api_unit_scale,
+            min_velocity: options.min_velocity,
+            max_velocity: options.max_velocity,
+            initial_velocity: options.initial_velocity,
+            min_start_distance: options.start_spacing.map(|spacing| spacing * 2.0 * PARTICLE_RADIUS_SCALED),
+            masses,
+            drag_range: Vec2::new(options.min_drag, options.max_drag),
+            uniform_locations: UniformLocations::default(),
+            seed: 0,
+            seeded_reset_count: 0,
+            odd_frame: true,
+            paused: false,
+            pause_on_blur: false,
+            paused_on_blur: false,
+            blend_mode: BlendMode::default(),
+            particle_opacity: DEFAULT_PARTICLE_OPACITY,
+            render_mode: RenderMode::default(),
+            falloff_mode: FalloffMode::default(),
+            color_mode: ColorMode::default(),
+            age_color_lifetime_ms: None,
+            color_space: ColorSpace::default(),
+            show_bounds: false,
+            show_hud: false,
+            fps: 0.0,
+            debug_view: DebugView::default(),
+            viewport_mode: ViewportMode::default(),
+            mirror_mode: MirrorMode::default(),
+            capturing: false,
+            depth_enabled: false,
+            depth_range: DEPTH_RANGE,
+            depth_test: false,
+            picking_enabled: false,
+            msaa_samples: 1,
+            msaa_targets: None,
+            picking_texture: None,
+            custom_update_program: None,
+            binning_mode: BinningMode::default(),
+            bin_clear_mode: BinClearMode::default(),
+            float_blend_supported: false,
+            float_linear_supported: false,
+            keyboard_controls_enabled: false,
+            freeze_brush_enabled: false,
+            freeze_brush_radius: FREEZE_BRUSH_RADIUS,
+            spawn_on_click_enabled: false,
+            spawn_burst_count: SPAWN_BURST_COUNT,
+            spawn_color: SOLID_COLOR,
+            spawn_soft_time_ms: 0.0,
+            running_ms: 0.0,
+            next_spawn_id: 0,
+            sink: None,
+            platforms: [None; MAX_PLATFORMS],
+            platforms_enabled: true,
+            gravity_zones: Vec::new(),
+            container: None,
+            transparent_background: false,
+            track_segment_count: 0,
+            track_enabled: true,
+            cursor_position: None,
+            mouse_pressed: false,
+            camera: Camera::default(),
+            camera_transition: None,
+            auto_camera: None,
+            touch_controls_enabled: false,
+            touches: Vec::new(),
+            gravity: GRAVITY,
+            gravity_rotation: None,
+            pending_resize_ms: None,
+            damping: 0.0,
+            boundary_modes: [BoundaryMode::default(); 2],
+            force_order: ForceOrder::default(),
+            collisions_enabled: true,
+            max_neighbor_checks: None,
+            collision_scheme: CollisionScheme::default(),
+            wall_restitution: 1.0,
+            particle_restitution: 0.0,
+            collision_radius_scale: 1.0,
+            max_force: f32::INFINITY,
+            force_field_strength: 1.0,
+            pending_impulse: None,
+            impulse: None,
+            pending_velocity_scale: None,
+            velocity_scale: 1.0,
+            pending_region_push: None,
+            region_push: None,
+            remove_drift: false,
+            pending_drift: None,
+            drift: Vec2::ZERO,
+            boundary_range: UVec2::ZERO,
+            morph: None,
+            morph_progress: Vec2::ZERO,
+            occupancy_stats_enabled: false,
+            last_occupancy_stats_ms: 0f64,
+            collect_occupancy_stats: false,
+            conservation_check_enabled: false,
+            lost_particle_check_enabled: false,
+            error_callback: None,
+            pending_syncs: Vec::new(),
+            pending_reductions: Vec::new(),
+            reset_on_error: false,
+            last_corruption_check_ms: 0f64,
+            stats: SharedStats::default(),
+        }
+    }
+
+    /// Number of particles drawn with `draw_stride`.
+    fn drawn_particle_count(&self) -> u32 {
+        self.particle_count.div_ceil(self.draw_stride)
+    }
+}
+
+/// A numeric simulation parameter that can be inspected and changed by name, so that debug
+/// panels can be built without a dedicated setter per parameter.
+pub struct Tunable {
+    pub name: &'static str,
+    pub min: f32,
+    pub max: f32,
+    get: fn(&RenderState) -> f32,
+    set: fn(&mut RenderState, f32),
+}
+
+const TUNABLES: &[Tunable] = &[
+    Tunable {
+        name: "gravityX",
+        min: -5.0,
+        max: 5.0,
+        get: |state| state.gravity.x,
+        set: |state, value| state.gravity.x = value,
+    },
+    Tunable {
+        name: "gravityY",
+        min: -5.0,
+        max: 5.0,
+        get: |state| state.gravity.y,
+        set: |state, value| state.gravity.y = value,
+    },
+    Tunable {
+        name: "damping",
+        min: 0.0,
+        max: 5.0,
+        get: |state| state.damping,
+        set: |state, value| state.damping = value,
+    },
+    Tunable {
+        name: "wallRestitution",
+        min: 0.0,
+        max: 1.0,
+        get: |state| state.wall_restitution,
+        set: |state, value| state.wall_restitution = value,
+    },
+    Tunable {
+        name: "particleRestitution",
+        min: 0.0,
+        max: 1.0,
+        get: |state| state.particle_restitution,
+        set: |state, value| state.particle_restitution = value,
+    },
+    Tunable {
+        name: "forceFieldStrength",
+        min: 0.0,
+        max: 5.0,
+        get: |state| state.force_field_strength,
+        set: |state, value| state.force_field_strength = value,
+    },
+    Tunable {
+        name: "timeScale",
+        min: MIN_TIME_SCALE as f32,
+        max: MAX_TIME_SCALE as f32,
+        get: |state| state.time_scale as f32,
+        set: |state, value| state.time_scale = value as f64,
+    },
+    Tunable {
+        name: "freezeBrushRadius",
+        min: 0.0,
+        max: 200.0,
+        get: |state| state.freeze_brush_radius,
+        set: |state, value| state.freeze_brush_radius = value,
+    },
+    Tunable {
+        name: "depthNear",
+        min: 0.0,
+        max: 2.0,
+        get: |state| state.depth_range.x,
+        set: |state, value| state.depth_range.x = value,
+    },
+    Tunable {
+        name: "depthFar",
+        min: 0.0,
+        max: 2.0,
+        get: |state| state.depth_range.y,
+        set: |state, value| state.depth_range.y = value,
+    },
+];
+
+/// Looks up a tunable parameter by its name.
+pub fn tunable(name: &str) -> Option<&'static Tunable> {
+    TUNABLES.iter().find(|tunable| tunable.name == name)
+}
+
+/// Change of the render state that could not be applied right away, see `Graphics::update_state`.
+type DeferredUpdate = Box<dyn FnOnce(&mut RenderState)>;
+
+pub struct Graphics {
+    render_data: AppRenderData,
+    deferred_updates: RefCell<Vec<DeferredUpdate>>,
+}
+
+impl Graphics {
+    pub fn initialize_with_window(window: &Window, options: &GraphicsOptions) -> Self {
+        let precision = options.precision;
+
+        let min_start_distance = options.start_spacing.map(|spacing| spacing * 2.0 * PARTICLE_RADIUS_SCALED);
+
+        let (particles, placed_count) = park_unplaced(generate_particles(
+            PARTICLE_COUNT,
+            DOMAIN_MIN,
+            DOMAIN_MAX,
+            options.min_velocity,
+            options.max_velocity,
+            options.initial_velocity,
+            min_start_distance,
+        ), PARTICLE_COUNT);
+
+        let particle_count = particles.len() as u32;
+
+        let masses: Rc<[f32]> = generate_masses(PARTICLE_COUNT, options.min_mass, options.max_mass).into();
+
+        let state = Rc::new(RefCell::new(RenderState::new(particle_count, masses.clone(), options)));
+        state.borrow().stats.borrow_mut().placed_particles = min_start_distance.map(|_| placed_count);
+
+        let canvas = window.canvas();
+
+        let draw_program_link = ProgramLink::new(
+            ProgramId::Draw,
+            VertexShaderId::Draw,
+            FragmentShaderId::Draw,
+        );
+
+        let update_program_link = ProgramLink::new(
+            ProgramId::Update,
+            VertexShaderId::Update,
+            FragmentShaderId::Update,
+        );
+
+        let partition_program_link = ProgramLink::new(
+            ProgramId::Partition,
+            VertexShaderId::Partition,
+            FragmentShaderId::Partition,
+        );
+
+        let partition_slots_program_link = ProgramLink::new(
+            ProgramId::PartitionSlots,
+            VertexShaderId::Partition,
+            FragmentShaderId::PartitionSlots,
+        );
+
+        let partition_unpack_program_link = ProgramLink::new(
+            ProgramId::PartitionUnpack,
+            VertexShaderId::Update,
+            FragmentShaderId::PartitionUnpack,
+        );
+
+        let sort_keys_program_link = ProgramLink::new(
+            ProgramId::SortKeys,
+            VertexShaderId::Update,
+            FragmentShaderId::SortKeys,
+        );
+
+        let bitonic_sort_program_link = ProgramLink::new(
+            ProgramId::BitonicSort,
+            VertexShaderId::Update,
+            FragmentShaderId::BitonicSort,
+        );
+
+        let cell_ranges_program_link = ProgramLink::new(
+            ProgramId::CellRanges,
+            VertexShaderId::Update,
+            FragmentShaderId::CellRanges,
+        );
+
+        let debug_view_program_link = ProgramLink::new(
+            ProgramId::DebugView,
+            VertexShaderId::Update,
+            FragmentShaderId::DebugView,
+        );
+
+        let metaball_splat_program_link = ProgramLink::new(
+            ProgramId::MetaballSplat,
+            VertexShaderId::Draw,
+            FragmentShaderId::MetaballSplat,
+        );
+
+        let picking_program_link = ProgramLink::new(
+            ProgramId::Picking,
+            VertexShaderId::Draw,
+            FragmentShaderId::Picking,
+        );
+
+        let metaball_surface_program_link = ProgramLink::new(
+            ProgramId::MetaballSurface,
+            VertexShaderId::Update,
+            FragmentShaderId::MetaballSurface,
+        );
+
+        let present_program_link = ProgramLink::new(
+            ProgramId::Present,
+            VertexShaderId::Update,
+            FragmentShaderId::Present,
+        );
+
+        let hud_program_link = ProgramLink::new(
+            ProgramId::Hud,
+            VertexShaderId::Update,
+            FragmentShaderId::Hud,
+        );
+
+        let container_program_link = ProgramLink::new(
+            ProgramId::Container,
+            VertexShaderId::Update,
+            FragmentShaderId::Container,
+        );
+
+        let reduce_program_link = ProgramLink::new(
+            ProgramId::Reduce,
+            VertexShaderId::Update,
+            FragmentShaderId::Reduce,
+        );
+
+        let bounds_debug_program_link = ProgramLink::new(
+            ProgramId::BoundsDebug,
+            VertexShaderId::BoundsDebug,
+            FragmentShaderId::BoundsDebug,
+        );
+
+        let old_data_link = TextureLink::new(
+            TextureId::OldData,
+            move |ctx: &TextureCreateContext| {
+                create_data_texture_float_4(
+                    ctx,
+                    TextureId::OldData.descriptor(precision),
+                    precision,
+                    Some(bytemuck::cast_slice(particles.as_ref())),
+                )
+            },
+        );
+
+        let new_data_link = TextureLink::new(
+            TextureId::NewData,
+            move |ctx: &TextureCreateContext| {
+                create_data_texture_float_4(
+                    ctx,
+                    TextureId::NewData.descriptor(precision),
+                    precision,
+                    None,
+                )
+            },
+        );
+
+        // Particles with separated positions, between the two passes of the split collision scheme.
+        let separated_data_link = TextureLink::new(
+            TextureId::SeparatedData,
+            move |ctx: &TextureCreateContext| {
+                create_data_texture_float_4(
+                    ctx,
+                    TextureId::SeparatedData.descriptor(precision),
+                    precision,
+                    None,
+                )
+            },
+        );
+
+        let bins_link = TextureLink::new(
+            TextureId::Bins,
+            move |ctx: &TextureCreateContext| create_data_texture_array_ui32_1(
+                ctx,
+                TextureId::Bins.descriptor(precision),
+            ),
+        );
+
+        let partition_intermediate_link = TextureLink::new(
+            TextureId::PartitionIntermediate,
+            move |ctx: &TextureCreateContext| create_data_texture_integer(
+                ctx,
+                TextureId::PartitionIntermediate.descriptor(precision),
+            ),
+        );
+
+        let partition_slots_link = TextureLink::new(
+            TextureId::PartitionSlots,
+            move |ctx: &TextureCreateContext| create_data_texture_float_4(
+                ctx,
+                TextureId::PartitionSlots.descriptor(precision),
+                Precision::Float32,
+                None,
+            ),
+        );
+
+        let [reduction_a_link, reduction_b_link] = [TextureId::ReductionA, TextureId::ReductionB].map(|texture_id| {
+            let descriptor = texture_id.descriptor(precision);
+
+            TextureLink::new(
+                texture_id,
+                move |ctx: &TextureCreateContext| create_data_texture_float_4(
+                    ctx,
+                    descriptor,
+                    Precision::Float32,
+                    None,
+                ),
+            )
+        });
+
+        let sort_keys_link = TextureLink::new(
+            TextureId::SortKeys,
+            move |ctx: &TextureCreateContext| create_data_texture_ui32_2(
+                ctx,
+                TextureId::SortKeys.descriptor(precision),
+            ),
+        );
+
+        let sort_keys_swap_link = TextureLink::new(
+            TextureId::SortKeysSwap,
+            move |ctx: &TextureCreateContext| create_data_texture_ui32_2(
+                ctx,
+                TextureId::SortKeysSwap.descriptor(precision),
+            ),
+        );
+
+        let cell_ranges_link = TextureLink::new(
+            TextureId::CellRanges,
+            move |ctx: &TextureCreateContext| create_data_texture_ui32_2(
+                ctx,
+                TextureId::CellRanges.descriptor(precision),
+            ),
+        );
+
+        let metaball_density_link = TextureLink::new(
+            TextureId::MetaballDensity,
+            move |ctx: &TextureCreateContext| create_filtered_texture_f16_1(
+                ctx,
+                TextureId::MetaballDensity.descriptor(precision),
+            ),
+        );
+
+        let hud_text_link = TextureLink::new(
+            TextureId::HudText,
+            move |ctx: &TextureCreateContext| create_byte_texture(ctx, TextureId::HudText.descriptor(precision), GL::RED_INTEGER, None),
+        );
+
+        let hud_font_link = TextureLink::new(
+            TextureId::HudFont,
+            move |ctx: &TextureCreateContext| create_byte_texture(ctx, TextureId::HudFont.descriptor(precision), GL::RED, Some(&font_atlas())),
+        );
+
+        let palette_link = TextureLink::new(
+            TextureId::Palette,
+            |ctx: &TextureCreateContext| {
+                let texture = ctx.gl().create_texture().unwrap();
+                upload_palette(ctx.gl(), &texture, &default_palette());
+                texture
+            },
+        );
+
+        let morph_targets_link = TextureLink::new(
+            TextureId::MorphTargets,
+            move |ctx: &TextureCreateContext| create_data_texture_float_4(
+                ctx,
+                TextureId::MorphTargets.descriptor(precision),
+                Precision::Float32,
+                None,
+            ),
+        );
+
+        let depths_link = TextureLink::new(
+            TextureId::Depths,
+            move |ctx: &TextureCreateContext| create_data_texture_float_1(
+                ctx,
+                TextureId::Depths.descriptor(precision),
+                &generate_depths(DATA_TEXTURE_WIDTH * DATA_TEXTURE_HEIGHT),
+            ),
+        );
+
+        let masses_link = TextureLink::new(
+            TextureId::Masses,
+            move |ctx: &TextureCreateContext| create_data_texture_float_1(
+                ctx,
+                TextureId::Masses.descriptor(precision),
+                &masses,
+            ),
+        );
+
+        let drags = generate_drags(PARTICLE_COUNT, options.min_drag, options.max_drag);
+
+        let drags_link = TextureLink::new(
+            TextureId::Drags,
+            move |ctx: &TextureCreateContext| create_data_texture_float_1(
+                ctx,
+                TextureId::Drags.descriptor(precision),
+                &drags,
+            ),
+        );
+
+        let spawn_times_link = TextureLink::new(
+            TextureId::SpawnTimes,
+            move |ctx: &TextureCreateContext| create_data_texture_float_1(
+                ctx,
+                TextureId::SpawnTimes.descriptor(precision),
+                &vec![NEVER_SPAWNED; (DATA_TEXTURE_WIDTH * DATA_TEXTURE_HEIGHT) as usize],
+            ),
+        );
+
+        let colors_link = TextureLink::new(
+            TextureId::Colors,
+            move |ctx: &TextureCreateContext| create_byte_texture(
+                ctx,
+                TextureId::Colors.descriptor(precision),
+                GL::RGBA,
+                Some(&SOLID_COLOR.repeat((DATA_TEXTURE_WIDTH * DATA_TEXTURE_HEIGHT) as usize)),
+            ),
+        );
+
+        // Starts out as a single texel without force, which is filterable in any format.
+        let force_field_link = TextureLink::new(
+            TextureId::ForceField,
+            |ctx: &TextureCreateContext| {
+                let texture = ctx.gl().create_texture().unwrap();
+                upload_force_field(ctx.gl(), &texture, &[0.0, 0.0], 1, 1, false);
+                texture
+            },
+        );
+
+        let track_link = TextureLink::new(
+            TextureId::Track,
+            move |ctx: &TextureCreateContext| create_data_texture_float_4(
+                ctx,
+                TextureId::Track.descriptor(precision),
+                Precision::Float32,
+                None,
+            ),
+        );
+
+        let metaball_fb_link = FramebufferLink::new(
+            FramebufferId::Metaball,
+            |ctx: &FramebufferCreateContext| ctx.gl().create_framebuffer().unwrap(),
+            None,
+        );
+
+        let msaa_fb_link = FramebufferLink::new(
+            FramebufferId::Msaa,
+            |ctx: &FramebufferCreateContext| ctx.gl().create_framebuffer().unwrap(),
+            None,
+        );
+
+        let msaa_resolve_fb_link = FramebufferLink::new(
+            FramebufferId::MsaaResolve,
+            |ctx: &FramebufferCreateContext| ctx.gl().create_framebuffer().unwrap(),
+            None,
+        );
+
+        let picking_fb_link = FramebufferLink::new(
+            FramebufferId::Picking,
+            |ctx: &FramebufferCreateContext| ctx.gl().create_framebuffer().unwrap(),
+            None,
+        );
+
+        let update_fb_link = FramebufferLink::new(
+            FramebufferId::Update,
+            |ctx: &FramebufferCreateContext| ctx.gl().create_framebuffer().unwrap(),
+            None,
+        );
+
+        let binning_fb_link = FramebufferLink::new(
+            FramebufferId::Partition,
+            |ctx: &FramebufferCreateContext| {
+                let gl = ctx.gl();
+
+                let fb = gl.create_framebuffer()
+                    .unwrap();
+
+                gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&fb));
+
+                // let depth_rb = gl.create_renderbuffer()
+                //     .unwrap();
+                //
+                // gl.bind_renderbuffer(
+                //     GL::RENDERBUFFER,
+                //     Some(&depth_rb),
+                // );
+                //
+                // gl.renderbuffer_storage(
+                //     GL::RENDERBUFFER,
+                //     GL::DEPTH_COMPONENT24,
+                //     GRID_ROWS as i32,
+                //     GRID_COLUMNS as i32,
+                // );
+                //
+                // gl.framebuffer_renderbuffer(
+                //     GL::FRAMEBUFFER,
+                //     GL::DEPTH_ATTACHMENT,
+                //     GL::RENDERBUFFER,
+                //     Some(&depth_rb),
+                // );
+                //
+                // gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+                fb
+            },
+            None,
+        );
+
+        let delta_time_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::DeltaTime,
+            |gl, location, state| gl.uniform1f(
+                location,
+                (state.delta_time_ms / 1000.0 * state.time_scale) as f32,
+            ),
+        );
+
+        let impulse_mode_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::ImpulseMode,
+            |gl, location, state| gl.uniform1i(
+                location,
+                state.impulse.map_or(0, |impulse| impulse.mode.shader_value()),
+            ),
+        );
+
+        let impulse_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::Impulse,
+            |gl, location, state| {
+                let strength = state.impulse.map_or(Vec2::ZERO, |impulse| impulse.strength);
+                gl.uniform2f(location, strength.x, strength.y);
+            },
+        );
+
+        let impulse_seed_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::ImpulseSeed,
+            |gl, location, state| gl.uniform1f(
+                location,
+                state.impulse.map_or(0.0, |impulse| impulse.seed),
+            ),
+        );
+
+        let gravity_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::Gravity,
+            |gl, location, state| gl.uniform2f(location, state.gravity.x, state.gravity.y),
+        );
+
+        let damping_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::Damping,
+            |gl, location, state| gl.uniform1f(location, state.damping),
+        );
+
+        let velocity_scale_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::VelocityScale,
+            |gl, location, state| gl.uniform1f(location, state.velocity_scale),
+        );
+
+        let push_region_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::PushRegion,
+            |gl, location, state| {
+                let (min, max) = state.region_push.map_or((Vec2::ZERO, Vec2::ZERO), |push| (push.min, push.max));
+                gl.uniform4f(location, min.x, min.y, max.x, max.y);
+            },
+        );
+
+        let push_velocity_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::PushVelocity,
+            |gl, location, state| {
+                let velocity = state.region_push.map_or(Vec2::ZERO, |push| push.velocity);
+                gl.uniform2f(location, velocity.x, velocity.y);
+            },
+        );
+
+        let drift_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::Drift,
+            |gl, location, state| gl.uniform2f(location, state.drift.x, state.drift.y),
+        );
+
+        let boundary_range_link = state_uniform_link(
+            &state,
+            (ProgramId::Update, ProgramId::Reduce),
+            UniformId::BoundaryRange,
+            |gl, location, state| gl.uniform2ui(location, state.boundary_range.x, state.boundary_range.y),
+        );
+
+        let boundary_mode_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::BoundaryMode,
+            |gl, location, state| gl.uniform2i(
+                location,
+                state.boundary_modes[0].shader_value(),
+                state.boundary_modes[1].shader_value(),
+            ),
+        );
+
+        let collisions_enabled_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::CollisionsEnabled,
+            |gl, location, state| gl.uniform1i(location, state.collisions_enabled as i32),
+        );
+
+        let max_neighbor_checks_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::MaxNeighborChecks,
+            |gl, location, state| gl.uniform1i(
+                location,
+                state.max_neighbor_checks.map_or(i32::MAX, |checks| checks.min(i32::MAX as u32) as i32),
+            ),
+        );
+
+        let sorted_binning_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::SortedBinning,
+            |gl, location, state| gl.uniform1i(location, (state.binning_mode == BinningMode::Sorted) as i32),
+        );
+
+        let wall_restitution_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::WallRestitution,
+            |gl, location, state| gl.uniform1f(location, state.wall_restitution),
+        );
+
+        let particle_restitution_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::ParticleRestitution,
+            |gl, location, state| gl.uniform1f(location, state.particle_restitution),
+        );
+
+        let collision_radius_scale_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::CollisionRadiusScale,
+            |gl, location, state| gl.uniform1f(location, state.collision_radius_scale),
+        );
+
+        let max_force_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::MaxForce,
+            |gl, location, state| gl.uniform1f(location, state.max_force),
+        );
+
+        let force_field_strength_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::ForceFieldStrength,
+            |gl, location, state| gl.uniform1f(location, state.force_field_strength),
+        );
+
+        let morph_progress_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::MorphProgress,
+            |gl, location, state| gl.uniform2f(location, state.morph_progress.x, state.morph_progress.y),
+        );
+
+        let force_order_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::ForceOrder,
+            |gl, location, state| gl.uniform1i(location, state.force_order.shader_value()),
+        );
+
+        let color_mode_link = state_uniform_link(
+            &state,
+            ProgramId::Draw,
+            UniformId::ColorMode,
+            |gl, location, state| gl.uniform1i(location, state.color_mode.shader_value()),
+        );
+
+        let color_space_link = state_uniform_link(
+            &state,
+            [
+                ProgramId::Draw,
+                ProgramId::MetaballSurface,
+                ProgramId::DebugView,
+                ProgramId::BoundsDebug,
+                ProgramId::Hud,
+            ],
+            UniformId::ColorSpace,
+            // Everything drawn into the offscreen target of linear blending is converted to linear
+            // colors. Without the target, e.g. while resizing, the canvas is drawn to directly.
+            |gl, location, state| {
+                let color_space = if state.msaa_targets.is_some() { state.color_space } else { ColorSpace::Srgb };
+                gl.uniform1i(location, color_space.shader_value());
+            },
+        );
+
+        let render_mode_link = state_uniform_link(
+            &state,
+            ProgramId::Draw,
+            UniformId::RenderMode,
+            |gl, location, state| gl.uniform1i(location, state.render_mode.shader_value()),
+        );
+
+        let freeze_brush_center_link = state_uniform_link(
+            &state,
+            (ProgramId::Update, ProgramId::Reduce),
+            UniformId::FreezeBrushCenter,
+            |gl, location, state| {
+                let center = state.cursor_position.unwrap_or_default();
+                gl.uniform2f(location, center.x, center.y);
+            },
+        );
+
+        // A zero radius disables the brush. The radius is given per axis, since the domain is
+        // stretched over the canvas and a circle on screen is an ellipse in the domain.
+        let freeze_brush_radius_link = state_uniform_link(
+            &state,
+            (ProgramId::Update, ProgramId::Reduce),
+            UniformId::FreezeBrushRadius,
+            |gl, location, state| {
+                let active = state.freeze_brush_enabled && state.mouse_pressed && state.cursor_position.is_some();
+
+                let radius = if active {
+                    let viewport = state.viewport_mode.viewport(gl.drawing_buffer_width() as u32, gl.drawing_buffer_height() as u32);
+                    2.0 * state.freeze_brush_radius / (viewport.size() * state.camera.zoom)
+                } else {
+                    Vec2::ZERO
+                };
+
+                gl.uniform2f(location, radius.x, radius.y);
+            },
+        );
+
+        let sink_center_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::SinkCenter,
+            |gl, location, state| {
+                let center = state.sink.map_or(Vec2::ZERO, |sink| sink.center);
+                gl.uniform2f(location, center.x, center.y);
+            },
+        );
+
+        // A zero radius disables the sink.
+        let sink_radius_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::SinkRadius,
+            |gl, location, state| gl.uniform1f(location, state.sink.map_or(0.0, |sink| sink.radius)),
+        );
+
+        let sink_probability_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::SinkProbability,
+            |gl, location, state| gl.uniform1f(location, state.sink.map_or(0.0, |sink| sink.probability)),
+        );
+
+        // Varies per frame, so that a particle which survived one step may be absorbed in the next.
+        let sink_seed_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::SinkSeed,
+            |gl, location, state| gl.uniform1f(location, (state.frame_count % 65536) as f32),
+        );
+
+        let spawn_soft_time_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::SpawnSoftTime,
+            |gl, location, state| gl.uniform1f(location, state.spawn_soft_time_ms),
+        );
+
+        let running_time_link = state_uniform_link(
+            &state,
+            (ProgramId::Update, ProgramId::Draw),
+            UniformId::RunningTime,
+            |gl, location, state| gl.uniform1f(location, state.running_ms as f32),
+        );
+
+        let age_color_lifetime_link = state_uniform_link(
+            &state,
+            ProgramId::Draw,
+            UniformId::AgeColorLifetime,
+            |gl, location, state| gl.uniform1f(location, state.age_color_lifetime_ms.unwrap_or(0.0)),
+        );
+
+        // The placed platforms are packed to the front of the array as (start, end).
+        let platforms_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::Platforms,
+            |gl, location, state| {
+                let mut data = [0.0; MAX_PLATFORMS * 4];
+
+                for (chunk, platform) in data.chunks_exact_mut(4).zip(state.platforms.iter().flatten()) {
+                    chunk.copy_from_slice(&[platform.start.x, platform.start.y, platform.end.x, platform.end.y]);
+                }
+
+                gl.uniform4fv_with_f32_array(location, &data);
+            },
+        );
+
+        let platform_count_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::PlatformCount,
+            |gl, location, state| {
+                let count = if state.platforms_enabled {
+                    state.platforms.iter().flatten().count()
+                } else {
+                    0
+                };
+
+                gl.uniform1i(location, count as i32);
+            },
+        );
+
+        let gravity_zone_bounds_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::GravityZoneBounds,
+            |gl, location, state| {
+                let mut data = [0.0; MAX_GRAVITY_ZONES * 4];
+
+                for (chunk, zone) in data.chunks_exact_mut(4).zip(&state.gravity_zones) {
+                    chunk.copy_from_slice(&[zone.min.x, zone.min.y, zone.max.x, zone.max.y]);
+                }
+
+                gl.uniform4fv_with_f32_array(location, &data);
+            },
+        );
+
+        let gravity_zone_gravity_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::GravityZoneGravity,
+            |gl, location, state| {
+                let mut data = [0.0; MAX_GRAVITY_ZONES * 2];
+
+                for (chunk, zone) in data.chunks_exact_mut(2).zip(&state.gravity_zones) {
+                    chunk.copy_from_slice(&zone.gravity.to_array());
+                }
+
+                gl.uniform2fv_with_f32_array(location, &data);
+            },
+        );
+
+        let gravity_zone_count_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::GravityZoneCount,
+            |gl, location, state| gl.uniform1i(location, state.gravity_zones.len() as i32),
+        );
+
+        let track_segment_count_link = state_uniform_link(
+            &state,
+            ProgramId::Update,
+            UniformId::TrackSegmentCount,
+            |gl, location, state| {
+                let count = if state.track_enabled { state.track_segment_count } else { 0 };
+                gl.uniform1i(location, count as i32);
+            },
+        );
+
+        let container_link = state_uniform_link(
+            &state,
+            (ProgramId::Update, ProgramId::Container),
+            UniformId::Container,
+            |gl, location, state| {
+                let (center, half_size, _) = state.container.map_or(Default::default(), Container::rounded_rect);
+                gl.uniform4f(location, center.x, center.y, half_size.x, half_size.y);
+            },
+        );
+
+        let container_corner_radius_link = state_uniform_link(
+            &state,
+            (ProgramId::Update, ProgramId::Container),
+            UniformId::ContainerCornerRadius,
+            |gl, location, state| {
+                let (_, _, corner_radius) = state.container.map_or(Default::default(), Container::rounded_rect);
+                gl.uniform1f(location, corner_radius);
+            },
+        );
+
+        let view_link = state_uniform_link(
+            &state,
+            (ProgramId::Draw, ProgramId::MetaballSplat, ProgramId::Picking, ProgramId::BoundsDebug, ProgramId::Container),
+            UniformId::View,
+            |gl, location, state| {
+                let camera = state.camera;
+                gl.uniform3f(location, camera.center.x, camera.center.y, camera.zoom);
+            },
+        );
+
+        let draw_stride_link = state_uniform_link(
+            &state,
+            (ProgramId::Draw, ProgramId::MetaballSplat, ProgramId::Picking),
+            UniformId::DrawStride,
+            |gl, location, state| gl.uniform1i(location, state.draw_stride as i32),
+        );
+
+        let depth_enabled_link = state_uniform_link(
+            &state,
+            (ProgramId::Draw, ProgramId::Picking),
+            UniformId::DepthEnabled,
+            |gl, location, state| gl.uniform1i(location, state.depth_enabled as i32),
+        );
+
+        let depth_test_link = state_uniform_link(
+            &state,
+            ProgramId::Draw,
+            UniformId::DepthTest,
+            |gl, location, state| gl.uniform1i(location, state.depth_test as i32),
+        );
+
+        let depth_range_link = state_uniform_link(
+            &state,
+            (ProgramId::Draw, ProgramId::Picking),
+            UniformId::DepthRange,
+            |gl, location, state| gl.uniform2f(location, state.depth_range.x, state.depth_range.y),
+        );
+
+        let drag_range_link = state_uniform_link(
+            &state,
+            ProgramId::Draw,
+            UniformId::DragRange,
+            |gl, location, state| gl.uniform2f(location, state.drag_range.x, state.drag_range.y),
+        );
+
+        let falloff_mode_link = state_uniform_link(
+            &state,
+            ProgramId::Draw,
+            UniformId::FalloffMode,
+            |gl, location, state| gl.uniform1i(location, state.falloff_mode.shader_value()),
+        );
+
+        let opacity_link = state_uniform_link(
+            &state,
+            ProgramId::Draw,
+            UniformId::Opacity,
+            |gl, location, state| gl.uniform1f(
+                location,
+                if state.blend_mode == BlendMode::Alpha { state.particle_opacity } else { 1.0 },
+            ),
+        );
+
+        let mut render_data_builder = RendererData::builder();
+
+        render_data_builder
+            .set_canvas(canvas)
+            .set_user_ctx(state)
+            .add_vertex_shader_src(VertexShaderId::Draw, DRAW_VERTEX)
+            .add_fragment_shader_src(FragmentShaderId::Draw, DRAW_FRAGMENT)
+            .add_vertex_shader_src(VertexShaderId::Update, UPDATE_VERTEX)
+            .add_fragment_shader_src(FragmentShaderId::Update, UPDATE_FRAGMENT)
+            .add_vertex_shader_src(VertexShaderId::Partition, PARTITION_VERTEX)
+            .add_fragment_shader_src(FragmentShaderId::Partition, PARTITION_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::PartitionSlots, PARTITION_SLOTS_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::PartitionUnpack, PARTITION_UNPACK_FRAGMENT)
+            .add_vertex_shader_src(VertexShaderId::BoundsDebug, BOUNDS_VERTEX)
+            .add_fragment_shader_src(FragmentShaderId::BoundsDebug, BOUNDS_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::MetaballSplat, METABALL_SPLAT_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::MetaballSurface, METABALL_SURFACE_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::Present, PRESENT_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::Picking, PICKING_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::SortKeys, SORT_KEYS_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::BitonicSort, BITONIC_SORT_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::CellRanges, CELL_RANGES_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::DebugView, DEBUG_VIEW_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::Hud, HUD_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::Container, CONTAINER_FRAGMENT)
+            .add_fragment_shader_src(FragmentShaderId::Reduce, REDUCE_FRAGMENT)
+            .add_program_link(draw_program_link)
+            .add_program_link(update_program_link)
+            .add_program_link(partition_program_link)
+            .add_program_link(partition_slots_program_link)
+            .add_program_link(partition_unpack_program_link)
+            .add_program_link(sort_keys_program_link)
+            .add_program_link(bitonic_sort_program_link)
+            .add_program_link(cell_ranges_program_link)
+            .add_program_link(debug_view_program_link)
+            .add_program_link(hud_program_link)
+            .add_program_link(container_program_link)
+            .add_program_link(reduce_program_link)
+            .add_program_link(bounds_debug_program_link)
+            .add_program_link(metaball_splat_program_link)
+            .add_program_link(metaball_surface_program_link)
+            .add_program_link(present_program_link)
+            .add_program_link(picking_program_link)
+            .add_uniform_link(delta_time_link)
+            .add_uniform_link(impulse_mode_link)
+            .add_uniform_link(impulse_link)
+            .add_uniform_link(impulse_seed_link)
+            .add_uniform_link(velocity_scale_link)
+            .add_uniform_link(push_region_link)
+            .add_uniform_link(push_velocity_link)
+            .add_uniform_link(drift_link)
+            .add_uniform_link(boundary_range_link)
+            .add_uniform_link(gravity_link)
+            .add_uniform_link(damping_link)
+            .add_uniform_link(boundary_mode_link)
+            .add_uniform_link(collisions_enabled_link)
+            .add_uniform_link(max_neighbor_checks_link)
+            .add_uniform_link(sorted_binning_link)
+            .add_uniform_link(wall_restitution_link)
+            .add_uniform_link(particle_restitution_link)
+            .add_uniform_link(collision_radius_scale_link)
+            .add_uniform_link(max_force_link)
+            .add_uniform_link(morph_progress_link)
+            .add_uniform_link(force_field_strength_link)
+            .add_uniform_link(force_order_link)
+            .add_uniform_link(freeze_brush_center_link)
+            .add_uniform_link(freeze_brush_radius_link)
+            .add_uniform_link(sink_center_link)
+            .add_uniform_link(sink_radius_link)
+            .add_uniform_link(sink_probability_link)
+            .add_uniform_link(sink_seed_link)
+            .add_uniform_link(spawn_soft_time_link)
+            .add_uniform_link(running_time_link)
+            .add_uniform_link(age_color_lifetime_link)
+            .add_uniform_link(platforms_link)
+            .add_uniform_link(platform_count_link)
+            .add_uniform_link(gravity_zone_bounds_link)
+            .add_uniform_link(gravity_zone_gravity_link)
+            .add_uniform_link(gravity_zone_count_link)
+            .add_uniform_link(track_segment_count_link)
+            .add_uniform_link(container_link)
+            .add_uniform_link(container_corner_radius_link)
+            .add_uniform_link(color_mode_link)
+            .add_uniform_link(color_space_link)
+            .add_uniform_link(render_mode_link)
+            .add_uniform_link(falloff_mode_link)
+            .add_uniform_link(opacity_link)
+            .add_uniform_link(depth_enabled_link)
+            .add_uniform_link(depth_range_link)
+            .add_uniform_link(draw_stride_link)
+            .add_uniform_link(drag_range_link)
+            .add_uniform_link(depth_test_link)
+            .add_uniform_link(view_link)
+            .add_texture_link(old_data_link)
+            .add_texture_link(new_data_link)
+            .add_texture_link(separated_data_link)
+            .add_texture_link(partition_intermediate_link)
+            .add_texture_link(partition_slots_link)
+            .add_texture_link(reduction_a_link)
+            .add_texture_link(reduction_b_link)
+            .add_texture_link(sort_keys_link)
+            .add_texture_link(sort_keys_swap_link)
+            .add_texture_link(cell_ranges_link)
+            .add_texture_link(bins_link)
+            .add_texture_link(palette_link)
+            .add_texture_link(hud_text_link)
+            .add_texture_link(hud_font_link)
+            .add_texture_link(morph_targets_link)
+            .add_texture_link(depths_link)
+            .add_texture_link(masses_link)
+            .add_texture_link(drags_link)
+            .add_texture_link(spawn_times_link)
+            .add_texture_link(colors_link)
+            .add_texture_link(force_field_link)
+            .add_texture_link(track_link)
+            .add_texture_link(metaball_density_link)
+            .add_framebuffer_link(update_fb_link)
+            .add_framebuffer_link(binning_fb_link)
+            .add_framebuffer_link(metaball_fb_link)
+            .add_framebuffer_link(msaa_fb_link)
+            .add_framebuffer_link(msaa_resolve_fb_link)
+            .add_framebuffer_link(picking_fb_link)
+            .set_render_callback(Graphics::render_callback);
+
+        let render_data = render_data_builder.build_renderer_data().unwrap();
+
+        let gl = render_data.gl();
+
+        gl.get_extension("EXT_color_buffer_float")
+            .unwrap();
+
+        let float_blend_supported = gl.get_extension("EXT_float_blend")
+            .unwrap()
+            .is_some();
+
+        let float_linear_supported = gl.get_extension("OES_texture_float_linear")
+            .unwrap()
+            .is_some();
+
+        gl.clear_depth(1.0);
+        gl.clear_color(0.0, 0.0, 0.0, 1.0);
+
+        gl.depth_func(GL::LESS);
+
+        let graphics = Self {
+            render_data,
+            deferred_updates: RefCell::default(),
+        };
+
+        let uniform_locations = UniformLocations::new(&graphics.render_data);
+
+        {
+            let mut state = graphics.state_mut();
+
+            state.uniform_locations = uniform_locations;
+            state.float_blend_supported = float_blend_supported;
+            state.float_linear_supported = float_linear_supported;
+        }
+
+        graphics.settle(options.settle_steps);
+        graphics
+    }
+
+    pub fn frame(&self, delta_time_ms: f64) {
+        debug!("Time elapsed since previous frame (ms): {}", delta_time_ms);
+
+        self.resolve_finished_syncs();
+        self.resolve_finished_reductions();
+
+        // A canvas that has not been laid out yet has no pixels to draw into. Skipping the whole
+        // frame, and not just rendering, keeps the data textures in step with `odd_frame`.
+        if self.canvas_is_degenerate() {
+            return;
+        }
+
+        self.apply_deferred_updates();
+        self.finish_resize();
+        self.update(delta_time_ms);
+        self.render_data.render();
+
+        self.measure_drift();
+        self.track_densest_region();
+        self.check_corruption();
+    }
+
+    pub fn event(&self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::Resized(new_size) => self.on_resize(*new_size),
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } =>
+                self.on_resize(**new_inner_size),
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => return self.on_key_pressed(*key),
+            WindowEvent::CursorMoved { position, .. } => self.on_cursor_moved(*position),
+            WindowEvent::CursorLeft { .. } => self.state_mut().cursor_position = None,
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } =>
+                self.on_mouse_input(*state),
+            WindowEvent::Touch(touch) => self.on_touch(touch),
+            WindowEvent::Focused(focused) => self.on_focus_changed(*focused),
+            _ => {}
+        }
+
+        false
+    }
+
+    /// Draws only every `draw_every`-th frame while still simulating every frame.
+    pub fn set_draw_every(&self, draw_every: u32) {
+        self.update_state(move |state| state.draw_every = draw_every.max(1));
+    }
+
+    /// Draws only every `draw_stride`-th particle while still simulating all of them.
+    pub fn set_draw_stride(&self, draw_stride: u32) {
+        self.update_state(move |state| state.draw_stride = draw_stride.max(1));
+    }
+
+    /// Selects how the particles are blended. In the `Alpha` mode, each of them covers what lies
+    /// behind it by `opacity`, which is clamped to `[0, 1]`; other modes ignore it.
+    pub fn set_blend_mode(&self, blend_mode: BlendMode, opacity: f32) {
+        self.update_state(move |state| {
+            state.blend_mode = blend_mode;
+            state.particle_opacity = opacity.clamp(0.0, 1.0);
+        });
+    }
+
+    /// Switches between blending in sRGB and in linear space. Linear blending renders into
+    /// an offscreen sRGB target, even without MSAA.
+    pub fn set_color_space(&self, color_space: ColorSpace) {
+        // A deferred change rebuilds the targets once it is applied.
+        if self.update_state(move |state| state.color_space = color_space) {
+            self.update_msaa_targets();
+        }
+    }
+
+    pub fn set_render_mode(&self, render_mode: RenderMode) {
+        self.update_state(move |state| state.render_mode = render_mode);
+    }
+
+    pub fn set_color_mode(&self, color_mode: ColorMode) {
+        self.update_state(move |state| state.color_mode = color_mode);
+    }
+
+    /// Colors the particles by the simulation time since they were spawned, from the start of
+    /// the palette when spawned to its end at `lifetime_ms`, which overrides the color mode.
+    /// Particles of the initial distribution count as old. `None` restores the color mode.
+    pub fn set_age_color(&self, lifetime_ms: Option<f32>) {
+        let lifetime_ms = lifetime_ms.filter(|&lifetime_ms| lifetime_ms > 0.0);
+        self.update_state(move |state| state.age_color_lifetime_ms = lifetime_ms);
+    }
+
+    pub fn set_falloff_mode(&self, falloff_mode: FalloffMode) {
+        self.update_state(move |state| state.falloff_mode = falloff_mode);
+    }
+
+    /// Replaces the palette the color modes look their values up in with `palette`,
+    /// which holds one RGBA entry per 4 bytes.
+    pub fn set_palette(&self, palette: &[u8]) {
+        let texture = self.render_data.texture(&TextureId::Palette)
+            .unwrap()
+            .webgl_texture();
+
+        upload_palette(self.render_data.gl(), texture, palette);
+    }
+
+    /// Replaces the force field with a `width` by `height` grid of accelerations stretched over
+    /// the domain, two floats per cell, row by row from the bottom. The field is sampled with
+    /// bilinear filtering, in half precision if 32-bit float textures cannot be filtered.
+    pub fn set_force_field(&self, field: &[f32], width: u32, height: u32) {
+        let float_linear_supported = self.state().float_linear_supported;
+
+        if !float_linear_supported {
+            warn!("OES_texture_float_linear is not supported, the force field is stored in half precision");
+        }
+
+        let texture = self.render_data.texture(&TextureId::ForceField)
+            .unwrap()
+            .webgl_texture();
+
+        upload_force_field(self.render_data.gl(), texture, field, width, height, float_linear_supported);
+    }
+
+    /// Returns the API units per simulation unit the instance was started with.
+    pub fn api_unit_scale(&self) -> f32 {
+        self.state().api_unit_scale
+    }
+
+    pub fn set_force_field_strength(&self, strength: f32) {
+        self.update_state(move |state| state.force_field_strength = strength);
+    }
+
+    /// Reads back the current particle data and returns `field` of every particle,
+    /// two floats per particle.
+    pub fn read_particle_field(&self, field: ParticleField) -> Vec<f32> {
+        let (pixels, particle_count) = {
+            let state = self.state();
+            let (_, new_data_texture) = data_textures(&self.render_data, &state);
+
+            let pixels = read_data_texture_rows(&self.render_data, new_data_texture, 0, DATA_TEXTURE_HEIGHT);
+            (pixels, state.particle_count)
+        };
+
+        self.render_data.gl().bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        let channel = field.channel();
+
+        pixels.chunks_exact(4)
+            .take(particle_count as usize)
+            .flat_map(|particle| [particle[channel], particle[channel + 1]])
+            .collect()
+    }
+
+    /// Replaces the state of every particle. `particles` must hold exactly one entry per particle.
+    /// The replaced particles lose their spawn colors and times, so they show the solid color
+    /// again and their collisions are not softened.
+    pub fn set_particles(&self, particles: &[Particle]) {
+        let gl = self.render_data.gl();
+        let state = self.state();
+
+        assert_eq!(particles.len(), state.particle_count as usize, "particle count mismatch");
+
+        let (_, data_type) = state.precision.texture_formats();
+        let data_array = state.precision.data_array(bytemuck::cast_slice(particles));
+
+        // The simulation may be paused, so both buffers are written to keep them interchangeable.
+        for texture_id in [TextureId::OldData, TextureId::NewData] {
+            let texture = self.render_data.texture(&texture_id)
+                .unwrap()
+                .webgl_texture();
+
+            bind_texture(gl, 0, texture, GL::TEXTURE_2D);
+
+            gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view(
+                GL::TEXTURE_2D,
+                0,
+                0,
+                0,
+                DATA_TEXTURE_WIDTH as i32,
+                DATA_TEXTURE_HEIGHT as i32,
+                GL::RGBA,
+                data_type,
+                Some(&data_array),
+            ).unwrap();
+        }
+
+        self.write_colors(0, &SOLID_COLOR.repeat(particles.len()));
+        self.write_spawn_times(0, &vec![NEVER_SPAWNED; particles.len()]);
+    }
+
+    /// Enables the built-in key bindings: space pauses, R resets the particles, the arrow keys
+    /// nudge gravity and +/- change the time scale. Disabled by default.
+    pub fn set_keyboard_controls_enabled(&self, enabled: bool) {
+        self.update_state(move |state| state.keyboard_controls_enabled = enabled);
+    }
+
+    /// Enables freezing the particles under a circular brush around the cursor
+    /// while the left mouse button is held.
+    pub fn set_freeze_brush_enabled(&self, enabled: bool) {
+        self.update_state(move |state| state.freeze_brush_enabled = enabled);
+    }
+
+    /// Shows the domain around `center` magnified by `zoom`, which is clamped to a sensible range.
+    /// With a positive `duration_ms`, the camera moves there from the current view over that
+    /// time along `easing` instead of snapping to it.
+    pub fn set_camera(&self, center: Vec2, zoom: f32, duration_ms: f64, easing: Easing) {
+        let mut state = self.state_mut();
+        let camera = Camera {
+            center,
+            zoom: zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+        };
+
+        if duration_ms > 0.0 {
+            state.camera_transition = Some(CameraTransition {
+                from: state.camera,
+                to: camera,
+                easing,
+                duration_ms,
+                elapsed_ms: 0.0,
+            });
+        } else {
+            state.camera = camera;
+            state.camera_transition = None;
+        }
+    }
+
+    /// Keeps panning the camera towards the region of the domain holding the most particles,
+    /// with the given time constant, or stops with `None`. The zoom is left as it is.
+    /// Enabling it cancels any camera transition in progress.
+    pub fn set_auto_camera(&self, smoothing_ms: Option<f32>) {
+        self.update_state(move |state| {
+            if smoothing_ms.is_some() {
+                state.camera_transition = None;
+            }
+
+            state.auto_camera = smoothing_ms.map(|smoothing_ms| AutoCamera {
+                smoothing_ms: smoothing_ms.max(1.0),
+                target: None,
+                last_measurement_ms: f64::NEG_INFINITY,
+            });
+        });
+    }
+
+    /// Enables pinching with two fingers to zoom and pan the camera. A single finger acts like
+    /// the mouse with the left button held.
+    pub fn set_touch_controls_enabled(&self, enabled: bool) {
+        let mut state = self.state_mut();
+
+        state.touch_controls_enabled = enabled;
+        state.touches.clear();
+    }
+
+    /// Sets the radius of the freeze brush in canvas pixels.
+    pub fn set_freeze_brush_radius(&self, radius: f32) {
+        self.update_state(move |state| state.freeze_brush_radius = radius.max(0.0));
+    }
+
+    /// Enables spawning a burst of particles at the cursor on every left click.
+    pub fn set_spawn_on_click_enabled(&self, enabled: bool) {
+        self.update_state(move |state| state.spawn_on_click_enabled = enabled);
+    }
+
+    /// Softens the collisions of spawned particles for `soft_time_ms` of simulation time, so that
+    /// those overlapping the particles around them ease apart instead of being ejected. The
+    /// response grows linearly from none to full over that time. 0 disables it.
+    pub fn set_spawn_soft_time(&self, soft_time_ms: f32) {
+        self.update_state(move |state| state.spawn_soft_time_ms = soft_time_ms.max(0.0));
+    }
+
+    /// Sets the number of particles spawned per click, at most the particle count.
+    pub fn set_spawn_burst_count(&self, count: u32) {
+        let mut state = self.state_mut();
+        state.spawn_burst_count = count.min(state.particle_count);
+    }
+
+    /// Places a circular sink of `radius` around `center` in domain coordinates, which absorbs
+    /// the particles inside of it with the given chance per step. A radius of zero removes it.
+    pub fn set_sink(&self, center: Vec2, radius: f32, probability: f32) {
+        self.state_mut().sink = (radius > 0.0).then_some(Sink {
+            center,
+            radius,
+            probability: probability.clamp(0.0, 1.0),
+        });
+    }
+
+    /// Places platform `index` as the segment from `start` to `end` in domain coordinates,
+    /// replacing the platform at that index if there is one.
+    pub fn set_platform(&self, index: usize, start: Vec2, end: Vec2) {
+        self.update_state(move |state| state.platforms[index] = Some(Platform { start, end }));
+    }
+
+    pub fn remove_platform(&self, index: usize) {
+        self.update_state(move |state| state.platforms[index] = None);
+    }
+
+    /// Lets the particles pass through the platforms while disabled, which keeps them placed.
+    pub fn set_platforms_enabled(&self, enabled: bool) {
+        self.update_state(move |state| state.platforms_enabled = enabled);
+    }
+
+    /// Adds a zone spanning `min` to `max` in domain coordinates, in which `gravity` applies
+    /// instead of the global gravity. Where zones overlap, the one added first wins. Once
+    /// `MAX_GRAVITY_ZONES` are placed, further zones are ignored.
+    pub fn add_gravity_zone(&self, min: Vec2, max: Vec2, gravity: Vec2) {
+        self.update_state(move |state| {
+            if state.gravity_zones.len() < MAX_GRAVITY_ZONES {
+                state.gravity_zones.push(GravityZone { min: min.min(max), max: min.max(max), gravity });
+            } else {
+                warn!("All {MAX_GRAVITY_ZONES} gravity zones are placed, ignoring the new one");
+            }
+        });
+    }
+
+    pub fn clear_gravity_zones(&self) {
+        self.update_state(|state| state.gravity_zones.clear());
+    }
+
+    /// Threads the particles onto the polyline through `points`, in domain coordinates: after
+    /// every step, each particle is moved onto the closest point of it and keeps only the part
+    /// of its velocity along it. Fewer than two points remove the track. At most
+    /// `MAX_TRACK_SEGMENTS + 1` points are used.
+    pub fn set_track(&self, points: &[Vec2]) {
+        let gl = self.render_data.gl();
+
+        let segments: Vec<f32> = points.windows(2)
+            .take(MAX_TRACK_SEGMENTS as usize)
+            .flat_map(|pair| [pair[0].x, pair[0].y, pair[1].x, pair[1].y])
+            .collect();
+
+        let segment_count = segments.len() as u32 / 4;
+
+        if segment_count > 0 {
+            let texture = self.render_data.texture(&TextureId::Track)
+                .unwrap()
+                .webgl_texture();
+
+            bind_texture(gl, TRACK_TEXTURE_UNIT, texture, GL::TEXTURE_2D);
+
+            gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view(
+                GL::TEXTURE_2D,
+                0,
+                0,
+                0,
+                segment_count as i32,
+                1,
+                GL::RGBA,
+                GL::FLOAT,
+                Some(&Float32Array::from(segments.as_slice())),
+            ).unwrap();
+        }
+
+        self.update_state(move |state| state.track_segment_count = segment_count);
+    }
+
+    /// Lets the particles move freely while disabled, which keeps the track set.
+    pub fn set_track_enabled(&self, enabled: bool) {
+        self.update_state(move |state| state.track_enabled = enabled);
+    }
+
+    /// Keeps the particles inside of `container` in addition to the domain, treating its boundary
+    /// like a wall, or removes the container with `None`.
+    pub fn set_container(&self, container: Option<Container>) {
+        self.update_state(move |state| state.container = container);
+    }
+
+    /// Sets the RGBA color the particles of the next bursts are spawned with, which the `Spawn`
+    /// color mode shows.
+    pub fn set_spawn_color(&self, color: [u8; 4]) {
+        self.update_state(move |state| state.spawn_color = color);
+    }
+
+    /// Replaces the next `spawn_burst_count` particles with new ones around `center`, with
+    /// velocities drawn from the initial range.
+    fn spawn_burst(&self, center: Vec2) {
+        let (first_id, particles, spawn_time, spawn_color) = {
+            let mut state = self.state_mut();
+
+            let first_id = state.next_spawn_id;
+            state.next_spawn_id = (first_id + state.spawn_burst_count) % state.particle_count;
+
+            let particles = generate_particles(
+                state.spawn_burst_count,
+                center - SPAWN_BURST_SPREAD,
+                center + SPAWN_BURST_SPREAD,
+                state.min_velocity,
+                state.max_velocity,
+                VelocityField::Random,
+                None,
+            );
+
+            (first_id, particles, state.running_ms as f32, state.spawn_color)
+        };
+
+        let particle_count = self.state().particle_count;
+        let (head, tail) = particles.split_at(particles.len().min((particle_count - first_id) as usize));
+
+        self.write_particles(first_id, head);
+        self.write_particles(0, tail);
+
+        self.write_spawn_times(first_id, &vec![spawn_time; head.len()]);
+        self.write_spawn_times(0, &vec![spawn_time; tail.len()]);
+
+        self.write_colors(first_id, &spawn_color.repeat(head.len()));
+        self.write_colors(0, &spawn_color.repeat(tail.len()));
+    }
+
+    /// Overwrites the RGBA colors of the particles with consecutive ids starting at `first_id`.
+    fn write_colors(&self, first_id: u32, colors: &[u8]) {
+        let texture = self.render_data.texture(&TextureId::Colors)
+            .unwrap()
+            .webgl_texture();
+
+        write_texel_rows(self.render_data.gl(), texture, first_id, colors, 4, GL::RGBA, GL::UNSIGNED_BYTE, |row| {
+            Uint8Array::from(row).into()
+        });
+    }
+
+    /// Overwrites the spawn times of the particles with consecutive ids starting at `first_id`.
+    fn write_spawn_times(&self, first_id: u32, spawn_times: &[f32]) {
+        let texture = self.render_data.texture(&TextureId::SpawnTimes)
+            .unwrap()
+            .webgl_texture();
+
+        write_texel_rows(self.render_data.gl(), texture, first_id, spawn_times, 1, GL::RED, GL::FLOAT, |row| {
+            Float32Array::from(row).into()
+        });
+    }
+
+    /// Overwrites the particles with consecutive ids starting at `first_id`, row by row.
+    fn write_particles(&self, first_id: u32, particles: &[Particle]) {
+        let state = self.state();
+        let (_, data_type) = state.precision.texture_formats();
+
+        // The simulation may be paused, so both buffers are written to keep them interchangeable.
+        for texture_id in [TextureId::OldData, TextureId::NewData] {
+            let texture = self.render_data.texture(&texture_id)
+                .unwrap()
+                .webgl_texture();
+
+            write_texel_rows(self.render_data.gl(), texture, first_id, particles, 1, GL::RGBA, data_type, |row| {
+                state.precision.data_array(bytemuck::cast_slice(row))
+            });
+        }
+    }
+
+    /// Scatters the particles over the domain again, as on startup.
+    pub fn reset(&self) {
+        let particles = {
+            let state = self.state();
+            let (particles, placed_count) = park_unplaced(
+                generate_particles(state.particle_count, DOMAIN_MIN, DOMAIN_MAX, state.min_velocity, state.max_velocity, state.initial_velocity, state.min_start_distance),
+                state.particle_count,
+            );
+
+            state.stats.borrow_mut().placed_particles = state.min_start_distance.map(|_| placed_count);
+            particles
+        };
+
+        self.set_particles(&particles);
+    }
+
+    /// Moves every particle onto its entry of `targets` over `duration_ms`, not counting the time
+    /// spent paused, overriding the physics until it arrives. Particles start with a small random delay and
+    /// ease in and out; they are at rest afterwards. `targets` must hold one entry per particle.
+    pub fn morph_to(&self, targets: &[Vec2], duration_ms: f64) {
+        let gl = self.render_data.gl();
+        let mut state = self.state_mut();
+
+        assert_eq!(targets.len(), state.particle_count as usize, "particle count mismatch");
+
+        let mut data = targets.iter()
+            .flat_map(|target| [target.x, target.y, 0.0, 0.0])
+            .collect::<Vec<f32>>();
+
+        data.resize((DATA_TEXTURE_WIDTH * DATA_TEXTURE_HEIGHT * 4) as usize, 0.0);
+
+        let texture = self.render_data.texture(&TextureId::MorphTargets)
+            .unwrap()
+            .webgl_texture();
+
+        bind_texture(gl, MORPH_TARGETS_TEXTURE_UNIT, texture, GL::TEXTURE_2D);
+
+        gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view(
+            GL::TEXTURE_2D,
+            0,
+            0,
+            0,
+            DATA_TEXTURE_WIDTH as i32,
+            DATA_TEXTURE_HEIGHT as i32,
+            GL::RGBA,
+            GL::FLOAT,
+            Some(&Precision::Float32.data_array(&data)),
+        ).unwrap();
+
+        state.morph = Some(Morph { elapsed_ms: 0.0, duration_ms });
+    }
+
+    /// Resets the particles to the next layout of the series derived from the base seed.
+    pub fn reset_seeded(&self) {
+        let particles = {
+            let mut state = self.state_mut();
+            let seed = reset_seed(state.seed, state.seeded_reset_count);
+            state.seeded_reset_count += 1;
+
+            let (particles, placed_count) = park_unplaced(
+                generate_seeded_particles(state.particle_count, DOMAIN_MIN, DOMAIN_MAX, state.min_velocity, state.max_velocity, state.initial_velocity, state.min_start_distance, seed),
+                state.particle_count,
+            );
+
+            state.stats.borrow_mut().placed_particles = state.min_start_distance.map(|_| placed_count);
+            particles
+        };
+
+        self.set_particles(&particles);
+    }
+
+    /// Returns every tunable parameter together with its current value.
+    pub fn tunables(&self) -> Vec<(&'static Tunable, f32)> {
+        let state = self.state();
+
+        TUNABLES.iter()
+            .map(|tunable| (tunable, (tunable.get)(&state)))
+            .collect()
+    }
+
+    /// Sets a tunable parameter, clamping `value` to its range.
+    pub fn set_tunable(&self, tunable: &Tunable, value: f32) {
+        (tunable.set)(&mut self.state_mut(), value.clamp(tunable.min, tunable.max));
+    }
+
+    /// Sets the base seed of the seeded resets and restarts their series.
+    pub fn set_seed(&self, seed: u64) {
+        let mut state = self.state_mut();
+        state.seed = seed;
+        state.seeded_reset_count = 0;
+    }
+
+    /// Selects the binning algorithm. Slotted binning falls back to layered binning
+    /// when float blending is not supported. Switching clears the bins, so that no ids
+    /// binned by the previous algorithm are left behind.
+    pub fn set_binning_mode(&self, binning_mode: BinningMode) {
+        let changed = {
+            let mut state = self.state_mut();
+            let previous = state.binning_mode;
+
+            state.binning_mode = if binning_mode == BinningMode::Slotted && !state.float_blend_supported {
+                warn!("EXT_float_blend is not supported, falling back to layered binning");
+                BinningMode::Layered
+            } else {
+                binning_mode
+            };
+
+            state.binning_mode != previous
+        };
+
+        if changed {
+            self.clear_bins();
+        }
+    }
+
+    /// Clears every layer of the bins texture. Sorted binning leaves the bins alone, so they
+    /// would otherwise keep the ids of the last frame binned by another algorithm, and clear
+    /// modes other than the default would keep them afterwards.
+    fn clear_bins(&self) {
+        let gl = self.render_data.gl();
+
+        let binning_fb = self.render_data.framebuffer(&FramebufferId::Partition)
+            .unwrap()
+            .webgl_framebuffer();
+
+        let bins_texture = self.render_data.texture(&TextureId::Bins)
+            .unwrap()
+            .webgl_texture();
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(binning_fb));
+
+        for layer in 0..BIN_CAPACITY {
+            gl.framebuffer_texture_layer(
+                GL::FRAMEBUFFER,
+                GL::COLOR_ATTACHMENT0,
+                Some(bins_texture),
+                0,
+                layer as i32,
+            );
+
+            debug_assert_framebuffer_complete(gl);
+
+            gl.clear_bufferuiv_with_u32_array(GL::COLOR, 0, &[0, 0, 0, 0]);
+        }
+
+        detach_color_attachment(gl);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+    }
+
+    pub fn set_bin_clear_mode(&self, bin_clear_mode: BinClearMode) {
+        if bin_clear_mode != BinClearMode::PerLayer {
+            warn!("Bins are no longer fully cleared, collisions will use stale particle ids");
+        }
+
+        self.update_state(move |state| state.bin_clear_mode = bin_clear_mode);
+    }
+
+    /// Renders the draw pass into a multisampled buffer with `samples` samples per pixel,
+    /// clamped to what the device supports. 0 or 1 renders directly onto the canvas.
+    pub fn set_msaa_samples(&self, samples: u32) {
+        let max_samples = self.render_data.gl()
+            .get_parameter(GL::MAX_SAMPLES)
+            .unwrap()
+            .as_f64()
+            .unwrap_or(0.0) as u32;
+
+        if samples > max_samples {
+            warn!("{} MSAA samples requested, but at most {} are supported", samples, max_samples);
+        }
+
+        if self.update_state(move |state| state.msaa_samples = samples.min(max_samples)) {
+            self.update_msaa_targets();
+        }
+    }
+
+    /// Enables drawing the particle indices into an offscreen texture along with every drawn frame,
+    /// which `pick_particle` reads from.
+    pub fn set_picking_enabled(&self, enabled: bool) {
+        if self.update_state(move |state| state.picking_enabled = enabled) {
+            self.update_picking_texture();
+        }
+    }
+
+    /// Returns the index of the particle drawn at canvas pixel (`x`, `y`), counted from the
+    /// top left corner, in the last drawn frame. `None` if there is none or picking is disabled.
+    pub fn pick_particle(&self, x: u32, y: u32) -> Option<u32> {
+        let state = self.state();
+        let picking_texture = state.picking_texture.as_ref()?;
+
+        let canvas = self.render_data.canvas();
+
+        if x >= canvas.width() || y >= canvas.height() {
+            return None;
+        }
+
+        let gl = self.render_data.gl();
+
+        let picking_fb = self.render_data.framebuffer(&FramebufferId::Picking)
+            .unwrap()
+            .webgl_framebuffer();
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(picking_fb));
+
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(picking_texture),
+            0,
+        );
+
+        let pixel = Uint32Array::new_with_length(4);
+
+        gl.read_pixels_with_opt_array_buffer_view(
+            x as i32,
+            (canvas.height() - 1 - y) as i32,
+            1,
+            1,
+            GL::RGBA_INTEGER,
+            GL::UNSIGNED_INT,
+            Some(&pixel),
+        ).unwrap();
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        pixel.get_index(0).checked_sub(1)
+    }
+
+    pub fn set_capturing(&self, capturing: bool) {
+        self.update_state(move |state| state.capturing = capturing);
+    }
+
+    /// Reads back the canvas as RGBA rows from the top. Only meaningful right after a drawn
+    /// frame, as the drawing buffer is not preserved once it has been presented.
+    pub fn read_canvas_pixels(&self) -> Vec<u8> {
+        let gl = self.render_data.gl();
+
+        let width = gl.drawing_buffer_width();
+        let height = gl.drawing_buffer_height();
+
+        let mut pixels = vec![0u8; 4 * width as usize * height as usize];
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        gl.read_pixels_with_opt_u8_array(
+            0,
+            0,
+            width,
+            height,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            Some(&mut pixels),
+        ).unwrap();
+
+        // GL counts rows from the bottom.
+        pixels.chunks_exact(4 * width as usize)
+            .rev()
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    pub fn set_show_bounds(&self, show_bounds: bool) {
+        self.update_state(move |state| state.show_bounds = show_bounds);
+    }
+
+    /// Replaces the update shader by one built from `fragment_source`, which gets the same
+    /// uniforms and textures as the built-in one and may use any of them. On failure, the
+    /// current update shader is kept.
+    pub fn set_update_shader_source(&self, fragment_source: &str) -> Result<(), ShaderError> {
+        let program = build_update_program(&self.render_data, fragment_source)?;
+        self.replace_update_program(Some(program));
+        Ok(())
+    }
+
+    /// Switches back to the built-in update shader.
+    pub fn reset_update_shader(&self) {
+        self.replace_update_program(None);
+    }
+
+    fn replace_update_program(&self, program: Option<WebGlProgram>) {
+        let gl = self.render_data.gl();
+        let mut state = self.state_mut();
+
+        if let Some(old_program) = state.custom_update_program.take() {
+            gl.delete_program(Some(&old_program));
+        }
+
+        let used_program = program.as_ref()
+            .or_else(|| self.render_data.program(&ProgramId::Update))
+            .unwrap();
+
+        state.uniform_locations.set_program(gl, ProgramId::Update, used_program);
+        state.custom_update_program = program;
+    }
+
+    /// Clears the canvas to transparent instead of black, so that the particles are composited
+    /// over the page content behind the canvas.
+    pub fn set_transparent_background(&self, transparent: bool) {
+        self.update_state(move |state| state.transparent_background = transparent);
+    }
+
+    /// Shows the frame rate and the main simulation parameters as text in the top left corner.
+    pub fn set_show_hud(&self, show_hud: bool) {
+        self.update_state(move |state| state.show_hud = show_hud);
+    }
+
+    pub fn set_debug_view(&self, debug_view: DebugView) {
+        self.update_state(move |state| state.debug_view = debug_view);
+    }
+
+    pub fn set_viewport_mode(&self, viewport_mode: ViewportMode) {
+        self.update_state(move |state| state.viewport_mode = viewport_mode);
+    }
+
+    pub fn set_mirror_mode(&self, mirror_mode: MirrorMode) {
+        self.update_state(move |state| state.mirror_mode = mirror_mode);
+    }
+
+    /// Sets gravity to a fixed vector, which stops its rotation.
+    pub fn set_gravity(&self, gravity: Vec2) {
+        let mut state = self.state_mut();
+
+        state.gravity = gravity;
+        state.gravity_rotation = None;
+    }
+
+    /// Turns gravity around by `degrees_per_second` counterclockwise while the simulation runs,
+    /// starting from its current direction, with its strength set to `magnitude`. Gravity set
+    /// in other ways is overwritten on every step until `set_gravity` stops the rotation.
+    pub fn set_gravity_rotation(&self, degrees_per_second: f32, magnitude: f32) {
+        let mut state = self.state_mut();
+
+        let direction = if state.gravity != Vec2::ZERO { state.gravity } else { GRAVITY };
+        let angle = direction.y.atan2(direction.x);
+
+        state.gravity = Vec2::from_angle(angle) * magnitude;
+        state.gravity_rotation = Some(GravityRotation { degrees_per_second, magnitude, angle });
+    }
+
+    pub fn set_boundary_mode(&self, boundary_mode: BoundaryMode) {
+        self.update_state(move |state| state.boundary_modes = [boundary_mode; 2]);
+    }
+
+    /// Sets the boundary mode of a single axis.
+    pub fn set_axis_boundary_mode(&self, axis: Axis, boundary_mode: BoundaryMode) {
+        self.update_state(move |state| state.boundary_modes[axis.index()] = boundary_mode);
+    }
+
+    /// Enables the binning pass and the collisions between particles and with the static
+    /// colliders. Without them, particles only move under forces and the domain boundary.
+    pub fn set_collisions_enabled(&self, enabled: bool) {
+        self.update_state(move |state| state.collisions_enabled = enabled);
+    }
+
+    /// Stops testing a particle for collisions after `max_checks` neighbors per step, trading
+    /// missed collisions in crowded cells for speed. `None` tests all of them.
+    pub fn set_max_neighbor_checks(&self, max_checks: Option<u32>) {
+        self.update_state(move |state| state.max_neighbor_checks = max_checks);
+    }
+
+    pub fn set_collision_scheme(&self, scheme: CollisionScheme) {
+        self.update_state(move |state| state.collision_scheme = scheme);
+    }
+
+    pub fn set_force_order(&self, force_order: ForceOrder) {
+        self.update_state(move |state| state.force_order = force_order);
+    }
+
+    /// Sets the fraction of the normal velocity kept when bouncing off a wall, clamped to `[0, 1]`.
+    pub fn set_wall_restitution(&self, restitution: f32) {
+        self.update_state(move |state| state.wall_restitution = restitution.clamp(0.0, 1.0));
+    }
+
+    /// Sets the fraction of the normal relative velocity kept when two particles collide,
+    /// clamped to `[0, 1]`.
+    pub fn set_particle_restitution(&self, restitution: f32) {
+        self.update_state(move |state| state.particle_restitution = restitution.clamp(0.0, 1.0));
+    }
+
+    /// Makes particles collide as if their radius was `scale` times the drawn one. The scale is
+    /// clamped to `(0, MAX_COLLISION_RADIUS_SCALE]`, since only neighboring cells are searched.
+    /// Non-positive or NaN values reset it to 1.
+    pub fn set_collision_radius_scale(&self, scale: f32) {
+        let scale = if scale > 0.0 { scale.min(MAX_COLLISION_RADIUS_SCALE) } else { 1.0 };
+        self.update_state(move |state| state.collision_radius_scale = scale);
+    }
+
+    /// Limits the magnitude of the force a single particle receives per step, keeping its
+    /// direction. The limit is clamped to at least 0; `None` removes it, which is the default.
+    pub fn set_max_force(&self, max_force: Option<f32>) {
+        self.update_state(move |state| state.max_force = max_force.map_or(f32::INFINITY, |max_force| max_force.max(0.0)));
+    }
+
+    /// Enables the pseudo-3D depth effect: every particle has a fixed random depth in `[0, 1]`,
+    /// which scales its size and brightness between the factors of the depth range.
+    pub fn set_depth_enabled(&self, enabled: bool) {
+        self.update_state(move |state| state.depth_enabled = enabled);
+    }
+
+    /// Lets nearer particles hide the farther ones behind them, by their depth of the depth
+    /// effect, instead of blending with them. Particles are drawn opaquely while it is enabled.
+    pub fn set_depth_test(&self, enabled: bool) {
+        self.update_state(move |state| state.depth_test = enabled);
+    }
+
+    /// Sets the size and brightness factors of particles at depth 0 (`near`) and 1 (`far`).
+    pub fn set_depth_range(&self, near: f32, far: f32) {
+        self.update_state(move |state| state.depth_range = Vec2::new(near.max(0.0), far.max(0.0)));
+    }
+
+    /// Freezes the simulation passes. The draw pass and window event handling keep running,
+    /// so the canvas stays responsive while paused.
+    pub fn set_paused(&self, paused: bool) {
+        let mut state = self.state_mut();
+
+        state.paused = paused;
+        state.paused_on_blur = false;
+    }
+
+    /// Pauses the simulation while the canvas does not have focus. A simulation paused
+    /// explicitly stays paused when the focus returns.
+    pub fn set_pause_on_blur(&self, enabled: bool) {
+        let mut state = self.state_mut();
+
+        state.pause_on_blur = enabled;
+
+        if !enabled && state.paused_on_blur {
+            state.paused = false;
+            state.paused_on_blur = false;
+        }
+    }
+
+    /// Queues a velocity change that is applied to every particle by the next update pass.
+    pub fn apply_impulse(&self, mode: ImpulseMode, strength: Vec2) {
+        self.state_mut().pending_impulse = Some(Impulse {
+            mode,
+            strength,
+            seed: random() as f32,
+        });
+    }
+
+    /// Multiplies the velocity of every particle by `factor` in the next update pass.
+    /// Scales requested before that pass are combined.
+    pub fn scale_velocities(&self, factor: f32) {
+        let mut state = self.state_mut();
+        state.pending_velocity_scale = Some(state.pending_velocity_scale.unwrap_or(1.0) * factor);
+    }
+
+    /// Adds `velocity` to the particles inside the rectangle from `min` to `max` in domain
+    /// coordinates in the next update pass. A push requested earlier for the same pass is replaced.
+    pub fn push_region(&self, min: Vec2, max: Vec2, velocity: Vec2) {
+        self.state_mut().pending_region_push = Some(RegionPush {
+            min: min.min(max),
+            max: min.max(max),
+            velocity,
+        });
+    }
+
+    /// Turns the `count` particles from index `start` on into boundary particles, which stay in
+    /// place while the others collide with them as with particles of infinite mass, e.g. to line
+    /// a container for a fluid. A count of zero turns them back into regular particles.
+    pub fn set_boundary_particles(&self, start: u32, count: u32) {
+        self.update_state(move |state| {
+            let start = start.min(state.particle_count);
+            state.boundary_range = UVec2::new(start, start + count.min(state.particle_count - start));
+        });
+    }
+
+    /// Enables reducing the velocities of the particles to their mean on every frame and
+    /// subtracting it from every particle, which keeps the center of mass in place.
+    pub fn set_remove_drift(&self, remove_drift: bool) {
+        let mut state = self.state_mut();
+
+        state.remove_drift = remove_drift;
+
+        if !remove_drift {
+            state.pending_drift = None;
+        }
+    }
+
+    /// Enables periodic read-back of the bins texture to build an occupancy histogram.
+    pub fn set_occupancy_stats_enabled(&self, enabled: bool) {
+        let mut state = self.state_mut();
+
+        state.occupancy_stats_enabled = enabled;
+
+        if !enabled {
+            state.stats.borrow_mut().occupancy_histogram = None;
+        }
+    }
+
+    /// Enables reading back the particle velocities around every update pass to measure
+    /// how well momentum and kinetic energy are conserved.
+    pub fn set_conservation_check_enabled(&self, enabled: bool) {
+        let mut state = self.state_mut();
+
+        state.conservation_check_enabled = enabled;
+
+        if !enabled {
+            state.stats.borrow_mut().conservation_drift = None;
+        }
+    }
+
+    /// Enables reading back the particle positions after every update pass to count the
+    /// particles that got lost, e.g. to NaNs or by escaping through a wall.
+    pub fn set_lost_particle_check_enabled(&self, enabled: bool) {
+        let mut state = self.state_mut();
+
+        state.lost_particle_check_enabled = enabled;
+
+        if !enabled {
+            let mut stats = state.stats.borrow_mut();
+
+            stats.lost_particles = None;
+            stats.absorbed_particles = None;
+        }
+    }
+
+    /// Registers a callback invoked with a message when the periodic check finds NaN or infinite
+    /// values in the particle data, optionally followed by a reset. `None` disables the check.
+    pub fn set_error_callback(&self, callback: Option<Function>, reset_on_error: bool) {
+        let mut state = self.state_mut();
+
+        state.error_callback = callback;
+        state.reset_on_error = reset_on_error;
+    }
+
+    pub fn stats(&self) -> SharedStats {
+        self.render_data.user_ctx()
+            .unwrap()
+            .borrow()
+            .stats
+            .clone()
+    }
+
+    fn settle(&self, steps: u32) {
+        if steps == 0 {
+            return;
+        }
+
+        debug!("Settling particles for {} steps", steps);
+
+        let (gravity, damping) = {
+            let mut state = self.state_mut();
+            let saved = (state.gravity, state.damping);
+
+            state.gravity = Vec2::ZERO;
+            state.damping = SETTLE_DAMPING;
+
+            saved
+        };
+
+        for _ in 0..steps {
+            self.update(SETTLE_TIME_STEP_MS);
+
+            Graphics::simulation_step(
+                &self.render_data,
+                &self.state(),
+            );
+        }
+
+        let mut state = self.state_mut();
+
+        state.gravity = gravity;
+        state.damping = damping;
+    }
+
+    /// Runs `steps` simulation steps of `SETTLE_TIME_STEP_MS` twice from the current particles
+    /// and returns the particle data after each run, four floats per particle. Everything the
+    /// steps advance is put back before each run and afterwards, so the simulation continues
+    /// where it was. Only the particle data is written back, so spawn colors and times are kept.
+    pub fn run_twice(&self, steps: u32) -> [Vec<f32>; 2] {
+        let (snapshot, particles) = {
+            let state = self.state();
+            let (_, new_data_texture) = data_textures(&self.render_data, &state);
+            let pixels = read_data_texture_rows(&self.render_data, new_data_texture, 0, DATA_TEXTURE_HEIGHT);
+
+            let particles: Vec<Particle> = bytemuck::cast_slice(&pixels[..state.particle_count as usize * 4]).to_vec();
+            (StepSnapshot::save(&state), particles)
+        };
+
+        let run = || {
+            {
+                let mut state = self.state_mut();
+                snapshot.restore(&mut state);
+                state.paused = false;
+            }
+
+            self.write_particles(0, &particles);
+
+            for _ in 0..steps {
+                self.update(SETTLE_TIME_STEP_MS);
+                Graphics::simulation_step(&self.render_data, &self.state());
+            }
+
+            let state = self.state();
+            let (_, new_data_texture) = data_textures(&self.render_data, &state);
+
+            let mut pixels = read_data_texture_rows(&self.render_data, new_data_texture, 0, DATA_TEXTURE_HEIGHT);
+            pixels.truncate(state.particle_count as usize * 4);
+            pixels
+        };
+
+        let runs = [run(), run()];
+
+        snapshot.restore(&mut self.state_mut());
+        self.write_particles(0, &particles);
+        self.render_data.gl().bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        runs
+    }
+
+    fn state(&self) -> Ref<'_, RenderState> {
+        self.render_data.user_ctx()
+            .unwrap()
+            .try_borrow()
+            .expect("the render state is already mutably borrowed, see `Graphics::update_state`")
+    }
+
+    fn state_mut(&self) -> RefMut<'_, RenderState> {
+        self.render_data.user_ctx()
+            .unwrap()
+            .try_borrow_mut()
+            .expect("the render state is already borrowed, see `Graphics::update_state`")
+    }
+
+    /// Applies `update` to the render state, or at the start of the next frame if the state is
+    /// borrowed at the moment, e.g. by a setter called while a frame is in progress.
+    /// Applies `update` to the render state, or defers it to the next frame while the state is in
+    /// use. Returns whether it was applied right away.
+    fn update_state(&self, update: impl FnOnce(&mut RenderState) + 'static) -> bool {
+        match self.render_data.user_ctx().unwrap().try_borrow_mut() {
+            Ok(mut state) => {
+                update(&mut state);
+                true
+            }
+            Err(_) => {
+                warn!("The render state is in use, deferring the change to the next frame");
+                self.deferred_updates.borrow_mut().push(Box::new(update));
+                false
+            }
+        }
+    }
+
+    /// Applies the deferred changes and rebuilds the render targets depending on the changed
+    /// fields, which the setters leave to this when deferring.
+    fn apply_deferred_updates(&self) {
+        let updates = self.deferred_updates.take();
+
+        if updates.is_empty() {
+            return;
+        }
+
+        let (msaa_changed, picking_changed) = {
+            let mut state = self.state_mut();
+            let msaa_settings = (state.msaa_samples, state.color_space);
+            let picking_enabled = state.picking_enabled;
+
+            for update in updates {
+                update(&mut state);
+            }
+
+            (
+                msaa_settings != (state.msaa_samples, state.color_space),
+                picking_enabled != state.picking_enabled,
+            )
+        };
+
+        if msaa_changed {
+            self.update_msaa_targets();
+        }
+
+        if picking_changed {
+            self.update_picking_texture();
+        }
+    }
+
+    fn update(&self, delta_time_ms: f64) {
+        {
+            let mut ctx = self.state_mut();
+
+            ctx.delta_time_ms = delta_time_ms;
+            ctx.elapsed_ms += delta_time_ms;
+            ctx.frame_count += 1;
+
+            if delta_time_ms > 0.0 {
+                let fps = 1000.0 / delta_time_ms;
+                ctx.fps = if ctx.fps > 0.0 { ctx.fps + FPS_SMOOTHING * (fps - ctx.fps) } else { fps };
+            }
+
+            if let Some(AutoCamera { smoothing_ms, target: Some((_, target)), .. }) = ctx.auto_camera {
+                let step = 1.0 - (-delta_time_ms as f32 / smoothing_ms).exp();
+                let center = ctx.camera.center;
+                ctx.camera.center = center + (target - center) * step;
+                ctx.camera_transition = None;
+            }
+
+            if let Some(transition) = ctx.camera_transition {
+                let elapsed_ms = transition.elapsed_ms + delta_time_ms;
+                let t = (elapsed_ms / transition.duration_ms).min(1.0) as f32;
+
+                ctx.camera = transition.from.lerp(transition.to, transition.easing.apply(t));
+                ctx.camera_transition = (t < 1.0).then_some(CameraTransition { elapsed_ms, ..transition });
+            }
+
+            ctx.collect_occupancy_stats = ctx.occupancy_stats_enabled
+                && ctx.elapsed_ms - ctx.last_occupancy_stats_ms >= OCCUPANCY_STATS_INTERVAL_MS;
+
+            if ctx.collect_occupancy_stats {
+                ctx.last_occupancy_stats_ms = ctx.elapsed_ms;
+            }
+
+            if !ctx.paused {
+                ctx.running_ms += delta_time_ms;
+
+                if let Some(rotation) = ctx.gravity_rotation {
+                    let turned = rotation.degrees_per_second.to_radians() * (delta_time_ms / 1000.0) as f32;
+                    let angle = (rotation.angle + turned) % TAU;
+
+                    ctx.gravity = Vec2::from_angle(angle) * rotation.magnitude;
+                    ctx.gravity_rotation = Some(GravityRotation { angle, ..rotation });
+                }
+
+                ctx.odd_frame = !ctx.odd_frame;
+                ctx.impulse = ctx.pending_impulse.take();
+                ctx.velocity_scale = ctx.pending_velocity_scale.take().unwrap_or(1.0);
+                ctx.region_push = ctx.pending_region_push.take();
+                ctx.drift = ctx.pending_drift.take().unwrap_or(Vec2::ZERO);
+                ctx.morph_progress = Vec2::ZERO;
+
+                if let Some(morph) = ctx.morph {
+                    let advanced = Morph { elapsed_ms: morph.elapsed_ms + delta_time_ms, ..morph };
+
+                    ctx.morph_progress = Vec2::new(morph.progress(), advanced.progress());
+                    ctx.morph = (advanced.progress() < 1.0).then_some(advanced);
+                }
+            }
+        }
+
+        self.render_data.update_uniforms();
+    }
+
+    fn render_callback(render_data: &AppRenderData) {
+        let state = render_data.user_ctx()
+            .unwrap()
+            .borrow();
+
+        // Skipped frames leave the default framebuffer untouched, so the canvas keeps showing
+        // the last drawn frame.
+        if state.capturing || state.frame_count.is_multiple_of(state.draw_every as u64) {
+            Graphics::draw_pass(render_data, &state);
+            debug_check_gl_errors(render_data.gl(), "draw pass");
+
+            if let Some(picking_texture) = &state.picking_texture {
+                Graphics::picking_pass(render_data, &state, picking_texture);
+                debug_check_gl_errors(render_data.gl(), "picking pass");
+            }
+        }
+
+        if !state.paused {
+            Graphics::simulation_step(render_data, &state);
+            debug_check_gl_errors(render_data.gl(), "simulation step");
+        }
+    }
+
+    fn simulation_step(render_data: &AppRenderData, state: &RenderState) {
+        let gl = render_data.gl();
+
+        let (old_data_texture, new_data_texture) = data_textures(render_data, state);
+
+        let bins_texture = render_data.texture(&TextureId::Bins)
+            .unwrap()
+            .webgl_texture();
+
+        bind_texture(gl, 0, old_data_texture, GL::TEXTURE_2D);
+
+        let conservation_before = state.conservation_check_enabled
+            .then(|| measure_conservation(render_data, old_data_texture, state.particle_count, &state.masses));
+
+        let sorted_keys_texture = render_data.texture(&TextureId::SortKeys)
+            .unwrap()
+            .webgl_texture();
+
+        let cell_ranges_texture = render_data.texture(&TextureId::CellRanges)
+            .unwrap()
+            .webgl_texture();
+
+        // The update pass declares the samplers in every binning mode, so they need integer
+        // textures bound even when sorted binning does not run.
+        bind_texture(gl, SORTED_KEYS_TEXTURE_UNIT, sorted_keys_texture, GL::TEXTURE_2D);
+        bind_texture(gl, CELL_RANGES_TEXTURE_UNIT, cell_ranges_texture, GL::TEXTURE_2D);
+
+        // Without collisions the update pass does not read the bins, so they are left untouched.
+        if state.collisions_enabled {
+            bind_texture(gl, 1, bins_texture, GL::TEXTURE_2D_ARRAY);
+            Graphics::binning_pass(render_data, state);
+        }
+
+        let morph_targets_texture = render_data.texture(&TextureId::MorphTargets)
+            .unwrap()
+            .webgl_texture();
+
+        let force_field_texture = render_data.texture(&TextureId::ForceField)
+            .unwrap()
+            .webgl_texture();
+
+        let masses_texture = render_data.texture(&TextureId::Masses)
+            .unwrap()
+            .webgl_texture();
+
+        let drags_texture = render_data.texture(&TextureId::Drags)
+            .unwrap()
+            .webgl_texture();
+
+        let spawn_times_texture = render_data.texture(&TextureId::SpawnTimes)
+            .unwrap()
+            .webgl_texture();
+
+        let track_texture = render_data.texture(&TextureId::Track)
+            .unwrap()
+            .webgl_texture();
+
+        bind_texture(gl, MORPH_TARGETS_TEXTURE_UNIT, morph_targets_texture, GL::TEXTURE_2D);
+        bind_texture(gl, FORCE_FIELD_TEXTURE_UNIT, force_field_texture, GL::TEXTURE_2D);
+        bind_texture(gl, MASS_TEXTURE_UNIT, masses_texture, GL::TEXTURE_2D);
+        bind_texture(gl, DRAG_TEXTURE_UNIT, drags_texture, GL::TEXTURE_2D);
+        bind_texture(gl, SPAWN_TIME_TEXTURE_UNIT, spawn_times_texture, GL::TEXTURE_2D);
+        bind_texture(gl, TRACK_TEXTURE_UNIT, track_texture, GL::TEXTURE_2D);
+
+        let split_collisions = state.collision_scheme == CollisionScheme::Split
+            && state.collisions_enabled
+            // A custom update shader without the stages would run the whole step twice.
+            && state.uniform_locations.find(&ProgramId::Update, "collision_stage").is_some();
+
+        if split_collisions {
+            let separated_data_texture = render_data.texture(&TextureId::SeparatedData)
+                .unwrap()
+                .webgl_texture();
+
+            Graphics::update_pass(render_data, state, separated_data_texture, CollisionStage::Positions);
+
+            // The bins are kept from the unseparated positions, which moved by a fraction of
+            // the particle radius at most.
+            bind_texture(gl, 0, separated_data_texture, GL::TEXTURE_2D);
+            Graphics::update_pass(render_data, state, new_data_texture, CollisionStage::Velocities);
+        } else {
+            Graphics::update_pass(render_data, state, new_data_texture, CollisionStage::Full);
+        }
+
+        if let Some(before) = conservation_before {
+            let after = measure_conservation(render_data, new_data_texture, state.particle_count, &state.masses);
+            let drift = ConservationDrift::between(before, after, state.particle_count);
+
+            debug!("Conservation drift: {:?}", drift);
+
+            state.stats.borrow_mut().conservation_drift = Some(drift);
+        }
+
+        if state.lost_particle_check_enabled {
+            let pixels = read_data_texture_rows(render_data, new_data_texture, 0, DATA_TEXTURE_HEIGHT);
+            let lost = count_lost_particles(&pixels, state.particle_count, state.boundary_modes);
+            let absorbed = count_absorbed_particles(&pixels, state.particle_count);
+
+            if lost > 0 {
+                debug!("Lost particles: {}", lost);
+            }
+
+            let mut stats = state.stats.borrow_mut();
+
+            stats.lost_particles = Some(lost);
+            stats.absorbed_particles = Some(absorbed);
+        }
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+    }
+
+    fn draw_pass(render_data: &AppRenderData, state: &RenderState) {
+        let gl = render_data.gl();
+
+        let (old_data_texture, _) = data_textures(render_data, state);
+
+        let bins_texture = render_data.texture(&TextureId::Bins)
+            .unwrap()
+            .webgl_texture();
+
+        let palette_texture = render_data.texture(&TextureId::Palette)
+            .unwrap()
+            .webgl_texture();
+
+        let depths_texture = render_data.texture(&TextureId::Depths)
+            .unwrap()
+            .webgl_texture();
+
+        let colors_texture = render_data.texture(&TextureId::Colors)
+            .unwrap()
+            .webgl_texture();
+
+        let drags_texture = render_data.texture(&TextureId::Drags)
+            .unwrap()
+            .webgl_texture();
+
+        let spawn_times_texture = render_data.texture(&TextureId::SpawnTimes)
+            .unwrap()
+            .webgl_texture();
+
+        bind_texture(gl, 0, old_data_texture, GL::TEXTURE_2D);
+        bind_texture(gl, 1, bins_texture, GL::TEXTURE_2D_ARRAY);
+        bind_texture(gl, PALETTE_TEXTURE_UNIT, palette_texture, GL::TEXTURE_2D);
+        bind_texture(gl, DEPTH_TEXTURE_UNIT, depths_texture, GL::TEXTURE_2D);
+        bind_texture(gl, COLOR_TEXTURE_UNIT, colors_texture, GL::TEXTURE_2D);
+        bind_texture(gl, DRAG_TEXTURE_UNIT, drags_texture, GL::TEXTURE_2D);
+        bind_texture(gl, SPAWN_TIME_TEXTURE_UNIT, spawn_times_texture, GL::TEXTURE_2D);
+
+        match state.blend_mode {
+            BlendMode::Additive => {
+                gl.enable(GL::BLEND);
+                gl.blend_func(GL::ONE, GL::ONE);
+            }
+            BlendMode::Alpha => {
+                gl.enable(GL::BLEND);
+                // The draw shader writes premultiplied colors scaled by the opacity, which also
+                // keeps the accumulated alpha valid for compositing the canvas over the page.
+                gl.blend_func(GL::ONE, GL::ONE_MINUS_SRC_ALPHA);
+            }
+            BlendMode::Opaque => gl.disable(GL::BLEND),
+        }
+
+        bind_draw_target(render_data, state);
+
+        gl.viewport(
+            0,
+            0,
+            render_data.canvas().width() as i32,
+            render_data.canvas().height() as i32,
+        );
+
+        // Clears the bars around a letterboxed viewport as well. With a container, the background
+        // is only filled in inside of it, the rest stays transparent.
+        if state.transparent_background || state.container.is_some() {
+            gl.clear_color(0.0, 0.0, 0.0, 0.0);
+        }
+
+        gl.clear(GL::COLOR_BUFFER_BIT);
+        gl.clear_color(0.0, 0.0, 0.0, 1.0);
+
+        let viewport = draw_viewport(render_data, state);
+        gl.viewport(viewport.x, viewport.y, viewport.width, viewport.height);
+
+        if state.container.is_some() && !state.transparent_background {
+            Graphics::container_pass(render_data, &state.uniform_locations, viewport);
+        }
+
+        match (state.debug_view, state.render_mode) {
+            (DebugView::None, RenderMode::Metaball) => Graphics::metaball_pass(render_data, state),
+            (DebugView::None, _) => Graphics::particles_pass(render_data, state),
+            (debug_view, _) => Graphics::debug_view_pass(render_data, &state.uniform_locations, debug_view, viewport),
+        }
+
+        gl.disable(GL::BLEND);
+
+        if state.show_bounds {
+            Graphics::bounds_debug_pass(render_data, &state.uniform_locations);
+        }
+
+        if state.show_hud {
+            Graphics::hud_pass(render_data, state);
+        }
+
+        if let Some(targets) = &state.msaa_targets {
+            Graphics::msaa_resolve_pass(render_data, &state.uniform_locations, targets, state.color_space);
+        }
+    }
+
+    /// Draws the index of every particle as a point into the picking texture.
+    fn picking_pass(render_data: &AppRenderData, state: &RenderState, picking_texture: &WebGlTexture) {
+        let gl = render_data.gl();
+        let locations = &state.uniform_locations;
+
+        let picking_fb = render_data.framebuffer(&FramebufferId::Picking)
+            .unwrap()
+            .webgl_framebuffer();
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(picking_fb));
+
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(picking_texture),
+            0,
+        );
+
+        debug_assert_framebuffer_complete(gl);
+
+        gl.clear_bufferuiv_with_u32_array(GL::COLOR, 0, &[0, 0, 0, 0]);
+
+        let (old_data_texture, _) = data_textures(render_data, state);
+
+        let bins_texture = render_data.texture(&TextureId::Bins)
+            .unwrap()
+            .webgl_texture();
+
+        let depths_texture = render_data.texture(&TextureId::Depths)
+            .unwrap()
+            .webgl_texture();
+
+        bind_texture(gl, 0, old_data_texture, GL::TEXTURE_2D);
+        bind_texture(gl, 1, bins_texture, GL::TEXTURE_2D_ARRAY);
+        bind_texture(gl, DEPTH_TEXTURE_UNIT, depths_texture, GL::TEXTURE_2D);
+
+        render_data.use_program(&ProgramId::Picking);
+
+        // Matches the draw pass, which the MSAA resolve may have reset the viewport after.
+        let viewport = draw_viewport(render_data, state);
+        let pixel_size = viewport.pixel_size();
+
+        gl.viewport(viewport.x, viewport.y, viewport.width, viewport.height);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Picking, "render_mode")),
+            RenderMode::Points.shader_value(),
+        );
+
+        gl.uniform1f(
+            Some(locations.get(&ProgramId::Picking, "point_size")),
+            PARTICLE_RADIUS_SCALED / pixel_size
+        );
+
+        let quad_half_size = PARTICLE_RADIUS_SCALED / pixel_size / Vec2::new(viewport.width as f32, viewport.height as f32);
+
+        gl.uniform2f(
+            Some(locations.get(&ProgramId::Picking, "quad_half_size")),
+            quad_half_size.x,
+            quad_half_size.y,
+        );
+
+        set_density_color_uniforms(gl, locations, &ProgramId::Picking);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Picking, "depths")),
+            DEPTH_TEXTURE_UNIT as i32,
+        );
+
+        draw_copies(gl, locations, &ProgramId::Picking, state, quad_half_size, || {
+            gl.draw_arrays(GL::POINTS, 0, state.drawn_particle_count() as i32);
+        });
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+    }
+
+    /// Resolves the multisampled draw target and copies it onto the canvas. The canvas itself
+    /// may be multisampled, so it cannot be the destination of the resolve blit. Linear colors
+    /// are converted back to sRGB on the way, as the canvas is not an sRGB target.
+    fn msaa_resolve_pass(render_data: &AppRenderData, locations: &UniformLocations, targets: &MsaaTargets, color_space: ColorSpace) {
+        let gl = render_data.gl();
+
+        let width = render_data.canvas().width() as i32;
+        let height = render_data.canvas().height() as i32;
+
+        let msaa_fb = render_data.framebuffer(&FramebufferId::Msaa)
+            .unwrap()
+            .webgl_framebuffer();
+
+        let resolve_fb = render_data.framebuffer(&FramebufferId::MsaaResolve)
+            .unwrap()
+            .webgl_framebuffer();
+
+        gl.bind_framebuffer(GL::READ_FRAMEBUFFER, Some(msaa_fb));
+        gl.bind_framebuffer(GL::DRAW_FRAMEBUFFER, Some(resolve_fb));
+
+        gl.blit_framebuffer(0, 0, width, height, 0, 0, width, height, GL::COLOR_BUFFER_BIT, GL::NEAREST);
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        bind_texture(gl, 2, &targets.resolved, GL::TEXTURE_2D);
+
+        // The draw pass may have left a letterboxed viewport, but the bars need copying too.
+        gl.viewport(0, 0, width, height);
+
+        render_data.use_program(&ProgramId::Present);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Present, "image")),
+            2,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Present, "encode_srgb")),
+            (color_space == ColorSpace::Linear) as i32,
+        );
+
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+    }
+
+    fn particles_pass(render_data: &AppRenderData, state: &RenderState) {
+        let gl = render_data.gl();
+        let locations = &state.uniform_locations;
+
+        if state.depth_test {
+            gl.disable(GL::BLEND);
+            gl.enable(GL::DEPTH_TEST);
+            gl.clear(GL::DEPTH_BUFFER_BIT);
+        }
+
+        render_data.use_program(&ProgramId::Draw);
+
+        let viewport = draw_viewport(render_data, state);
+        let pixel_size = viewport.pixel_size();
+
+        gl.uniform1f(
+            Some(locations.get(&ProgramId::Draw, "point_size")),
+            PARTICLE_RADIUS_SCALED / pixel_size
+        );
+
+        let quad_half_size = PARTICLE_RADIUS_SCALED / pixel_size / Vec2::new(viewport.width as f32, viewport.height as f32);
+
+        gl.uniform2f(
+            Some(locations.get(&ProgramId::Draw, "quad_half_size")),
+            quad_half_size.x,
+            quad_half_size.y,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Draw, "bins")),
+            1,
+        );
+
+        set_cell_uniforms(gl, locations, &ProgramId::Draw);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Draw, "palette")),
+            PALETTE_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Draw, "depths")),
+            DEPTH_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Draw, "colors")),
+            COLOR_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Draw, "drags")),
+            DRAG_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Draw, "spawn_times")),
+            SPAWN_TIME_TEXTURE_UNIT as i32,
+        );
+
+        draw_copies(gl, locations, &ProgramId::Draw, state, quad_half_size, || match state.render_mode {
+            RenderMode::Points => gl.draw_arrays(GL::POINTS, 0, state.drawn_particle_count() as i32),
+            RenderMode::Quads | RenderMode::Metaball =>
+                gl.draw_arrays_instanced(GL::TRIANGLES, 0, 6, state.drawn_particle_count() as i32),
+        });
+
+        gl.disable(GL::DEPTH_TEST);
+    }
+
+    /// Accumulates a Gaussian splat per particle into the density texture, then shades
+    /// the regions where the density exceeds a threshold onto the canvas.
+    fn metaball_pass(render_data: &AppRenderData, state: &RenderState) {
+        let gl = render_data.gl();
+        let locations = &state.uniform_locations;
+
+        let metaball_fb = render_data.framebuffer(&FramebufferId::Metaball)
+            .unwrap()
+            .webgl_framebuffer();
+
+        let density_texture = render_data.texture(&TextureId::MetaballDensity)
+            .unwrap()
+            .webgl_texture();
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(metaball_fb));
+        gl.viewport(0, 0, METABALL_RESOLUTION as i32, METABALL_RESOLUTION as i32);
+
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(density_texture),
+            0,
+        );
+
+        debug_assert_framebuffer_complete(gl);
+
+        gl.clear_bufferfv_with_f32_array(GL::COLOR, 0, &[0.0, 0.0, 0.0, 0.0]);
+
+        gl.enable(GL::BLEND);
+        gl.blend_func(GL::ONE, GL::ONE);
+
+        render_data.use_program(&ProgramId::MetaballSplat);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::MetaballSplat, "render_mode")),
+            RenderMode::Metaball.shader_value(),
+        );
+
+        gl.uniform2f(
+            Some(locations.get(&ProgramId::MetaballSplat, "quad_half_size")),
+            METABALL_SPLAT_RADIUS,
+            METABALL_SPLAT_RADIUS,
+        );
+
+        set_density_color_uniforms(gl, locations, &ProgramId::MetaballSplat);
+
+        draw_copies(gl, locations, &ProgramId::MetaballSplat, state, Vec2::splat(METABALL_SPLAT_RADIUS), || {
+            gl.draw_arrays_instanced(GL::TRIANGLES, 0, 6, state.drawn_particle_count() as i32);
+        });
+
+        // The density texture is sampled by the surface pass below.
+        detach_color_attachment(gl);
+
+        bind_draw_target(render_data, state);
+
+        let viewport = draw_viewport(render_data, state);
+        gl.viewport(viewport.x, viewport.y, viewport.width, viewport.height);
+
+        bind_texture(gl, 2, density_texture, GL::TEXTURE_2D);
+
+        gl.blend_func_separate(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA, GL::ONE, GL::ONE_MINUS_SRC_ALPHA);
+
+        render_data.use_program(&ProgramId::MetaballSurface);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::MetaballSurface, "density")),
+            2,
+        );
+
+        gl.uniform4f(
+            Some(locations.get(&ProgramId::MetaballSurface, "viewport")),
+            viewport.x as f32,
+            viewport.y as f32,
+            viewport.width as f32,
+            viewport.height as f32,
+        );
+
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+    }
+
+    /// Shows the texture picked by `debug_view` stretched over the canvas. Expects the data
+    /// texture on unit 0 and the bins on unit 1, as bound by the draw pass.
+    fn debug_view_pass(render_data: &AppRenderData, locations: &UniformLocations, debug_view: DebugView, viewport: Viewport) {
+        let gl = render_data.gl();
+
+        // The view is opaque, so blending would only mix in the cleared background.
+        gl.disable(GL::BLEND);
+
+        render_data.use_program(&ProgramId::DebugView);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::DebugView, "particles")),
+            0,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::DebugView, "bins")),
+            1,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::DebugView, "debug_view")),
+            debug_view.shader_value(),
+        );
+
+        gl.uniform2f(
+            Some(locations.get(&ProgramId::DebugView, "domain_min")),
+            DOMAIN_MIN.x,
+            DOMAIN_MIN.y,
+        );
+
+        gl.uniform2f(
+            Some(locations.get(&ProgramId::DebugView, "domain_max")),
+            DOMAIN_MAX.x,
+            DOMAIN_MAX.y,
+        );
+
+        gl.uniform4f(
+            Some(locations.get(&ProgramId::DebugView, "viewport")),
+            viewport.x as f32,
+            viewport.y as f32,
+            viewport.width as f32,
+            viewport.height as f32,
+        );
+
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+    }
+
+    /// Fills the inside of the container with the opaque background.
+    fn container_pass(render_data: &AppRenderData, locations: &UniformLocations, viewport: Viewport) {
+        let gl = render_data.gl();
+
+        render_data.use_program(&ProgramId::Container);
+
+        gl.uniform4f(
+            Some(locations.get(&ProgramId::Container, "viewport")),
+            viewport.x as f32,
+            viewport.y as f32,
+            viewport.width as f32,
+            viewport.height as f32,
+        );
+
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+    }
+
+    fn bounds_debug_pass(render_data: &AppRenderData, locations: &UniformLocations) {
+        let gl = render_data.gl();
+
+        render_data.use_program(&ProgramId::BoundsDebug);
+
+        gl.uniform2f(
+            Some(locations.get(&ProgramId::BoundsDebug, "min_bounds")),
+            DOMAIN_MIN.x,
+            DOMAIN_MIN.y,
+        );
+
+        gl.uniform2f(
+            Some(locations.get(&ProgramId::BoundsDebug, "max_bounds")),
+            DOMAIN_MAX.x,
+            DOMAIN_MAX.y,
+        );
+
+        gl.draw_arrays(GL::LINE_LOOP, 0, 4);
+    }
+
+    /// Writes the frame rate and the main simulation parameters into the top left corner.
+    fn hud_pass(render_data: &AppRenderData, state: &RenderState) {
+        let gl = render_data.gl();
+        let locations = &state.uniform_locations;
+
+        let text_texture = render_data.texture(&TextureId::HudText)
+            .unwrap()
+            .webgl_texture();
+
+        let font_texture = render_data.texture(&TextureId::HudFont)
+            .unwrap()
+            .webgl_texture();
+
+        let text = hud_text(state);
+        let lines = text.lines().count().min(HUD_LINES as usize) as u32;
+
+        bind_texture(gl, HUD_TEXT_TEXTURE_UNIT, text_texture, GL::TEXTURE_2D);
+        bind_texture(gl, HUD_FONT_TEXTURE_UNIT, font_texture, GL::TEXTURE_2D);
+
+        gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            0,
+            0,
+            HUD_COLUMNS as i32,
+            HUD_LINES as i32,
+            GL::RED_INTEGER,
+            GL::UNSIGNED_BYTE,
+            Some(&layout(&text)),
+        ).unwrap();
+
+        // The cells are one font pixel wider and two taller than the glyphs.
+        let width = HUD_COLUMNS * (GLYPH_WIDTH + 1) * HUD_SCALE;
+        let height = lines * (GLYPH_HEIGHT + 2) * HUD_SCALE;
+        let top = render_data.canvas().height().saturating_sub(HUD_MARGIN);
+
+        gl.viewport(HUD_MARGIN as i32, top as i32 - height as i32, width as i32, height as i32);
+
+        gl.enable(GL::BLEND);
+        gl.blend_func_separate(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA, GL::ONE, GL::ONE_MINUS_SRC_ALPHA);
+
+        render_data.use_program(&ProgramId::Hud);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Hud, "text")),
+            HUD_TEXT_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Hud, "font")),
+            HUD_FONT_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform2f(
+            Some(locations.get(&ProgramId::Hud, "origin")),
+            HUD_MARGIN as f32,
+            top as f32,
+        );
+
+        gl.uniform1f(
+            Some(locations.get(&ProgramId::Hud, "scale")),
+            HUD_SCALE as f32,
+        );
+
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+
+        gl.disable(GL::BLEND);
+    }
+
+    fn binning_pass(render_data: &AppRenderData, state: &RenderState) {
+        let gl = render_data.gl();
+
+        let binning_fb = render_data.framebuffer(&FramebufferId::Partition)
+            .unwrap()
+            .webgl_framebuffer();
+
+        let bins_texture = render_data.texture(&TextureId::Bins)
+            .unwrap()
+            .webgl_texture();
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(binning_fb));
+        gl.viewport(0, 0, GRID_COLUMNS as i32, GRID_ROWS as i32);
+
+        gl.read_buffer(GL::COLOR_ATTACHMENT0);
+
+        match state.binning_mode {
+            BinningMode::Layered => Graphics::layered_binning(render_data, &state.uniform_locations, state.bin_clear_mode),
+            BinningMode::Slotted => Graphics::slotted_binning(render_data, &state.uniform_locations, state.bin_clear_mode),
+            BinningMode::Sorted => Graphics::sorted_binning(render_data, &state.uniform_locations, state.particle_count),
+        }
+
+        let layers = (state.collect_occupancy_stats && state.binning_mode != BinningMode::Sorted)
+            .then(|| read_bin_layers(gl, bins_texture));
+
+        // Skipping clears leaves stale ids behind on purpose, so only the default is checked.
+        let check_bins = state.bin_clear_mode == BinClearMode::PerLayer;
+        // Slotted binning may drop particles from cells that are not full, which the check forbids.
+        let check_cells = check_bins && state.binning_mode == BinningMode::Layered;
+
+        if let Some(layers) = &layers {
+            if check_bins {
+                debug_assert_bins_consistent(layers);
+            }
+
+            state.stats.borrow_mut().occupancy_histogram = Some(occupancy_histogram(layers));
+        }
+
+        gl.read_buffer(GL::NONE);
+
+        detach_color_attachment(gl);
+
+        // Reading the positions binds the update framebuffer, so this waits for the bins to be detached.
+        if let Some(layers) = layers.filter(|_| check_cells && cfg!(debug_assertions)) {
+            let (old_data_texture, _) = data_textures(render_data, state);
+            let pixels = read_data_texture_rows(render_data, old_data_texture, 0, DATA_TEXTURE_HEIGHT);
+
+            if let Err(message) = check_bins_match_cells(&layers, &pixels, state.particle_count) {
+                panic!("{message}");
+            }
+        }
+    }
+
+    fn layered_binning(render_data: &AppRenderData, locations: &UniformLocations, clear_mode: BinClearMode) {
+        let gl = render_data.gl();
+
+        let partition_intermediate_texture = render_data.texture(&TextureId::PartitionIntermediate)
+            .unwrap()
+            .webgl_texture();
+
+        render_data.use_program(&ProgramId::Partition);
+
+        let pass_uniform_loc = locations.get(&ProgramId::Partition, "pass");
+
+        gl.uniform2ui(
+            Some(locations.get(&ProgramId::Partition, "grid_size")),
+            GRID_COLUMNS,
+            GRID_ROWS,
+        );
+
+        set_cell_uniforms(gl, locations, &ProgramId::Partition);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Partition, "particles")),
+            0,
+        );
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Partition, "bins")),
+            1,
+        );
+
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(partition_intermediate_texture),
+            0,
+        );
+
+        debug_assert_framebuffer_complete(gl);
+
+        gl.active_texture(GL::TEXTURE1);
+
+        for i in 0..BIN_CAPACITY {
+            if clear_mode == BinClearMode::PerLayer || (clear_mode == BinClearMode::Once && i == 0) {
+                gl.clear_bufferuiv_with_u32_array(GL::COLOR, 0, &[0, 0, 0, 0]);
+            }
+
+            gl.uniform1ui(Some(pass_uniform_loc), i);
+
+            gl.draw_arrays(GL::POINTS, 0, PARTICLE_COUNT as i32);
+
+            gl.copy_tex_sub_image_3d(
+                GL::TEXTURE_2D_ARRAY,
+                0,
+                0,
+                0,
+                i as i32,
+                0,
+                0,
+                GRID_COLUMNS as i32,
+                GRID_ROWS as i32,
+            );
+        };
+    }
+
+    fn slotted_binning(render_data: &AppRenderData, locations: &UniformLocations, clear_mode: BinClearMode) {
+        let gl = render_data.gl();
+
+        let bins_texture = render_data.texture(&TextureId::Bins)
+            .unwrap()
+            .webgl_texture();
+
+        let partition_slots_texture = render_data.texture(&TextureId::PartitionSlots)
+            .unwrap()
+            .webgl_texture();
+
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(partition_slots_texture),
+            0,
+        );
+
+        debug_assert_framebuffer_complete(gl);
+
+        if clear_mode != BinClearMode::Never {
+            gl.clear_bufferfv_with_f32_array(GL::COLOR, 0, &[0.0, 0.0, 0.0, 0.0]);
+        }
+
+        render_data.use_program(&ProgramId::PartitionSlots);
+
+        gl.uniform2ui(
+            Some(locations.get(&ProgramId::PartitionSlots, "grid_size")),
+            GRID_COLUMNS,
+            GRID_ROWS,
+        );
+
+        set_cell_uniforms(gl, locations, &ProgramId::PartitionSlots);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::PartitionSlots, "particles")),
+            0,
+        );
+
+        gl.enable(GL::BLEND);
+        gl.blend_equation(GL::MAX);
+
+        gl.draw_arrays(GL::POINTS, 0, PARTICLE_COUNT as i32);
+
+        gl.blend_equation(GL::FUNC_ADD);
+        gl.disable(GL::BLEND);
+
+        // The bins texture is not sampled by the unpack pass, so all of its layers
+        // can be attached at once while the slots texture is read.
+        bind_texture(gl, 2, partition_slots_texture, GL::TEXTURE_2D);
+
+        let draw_buffers = js_sys::Array::new();
+
+        for layer in 0..BIN_CAPACITY {
+            gl.framebuffer_texture_layer(
+                GL::FRAMEBUFFER,
+                GL::COLOR_ATTACHMENT0 + layer,
+                Some(bins_texture),
+                0,
+                layer as i32,
+            );
+
+            draw_buffers.push(&(GL::COLOR_ATTACHMENT0 + layer).into());
+        }
+
+        gl.draw_buffers(&draw_buffers);
+
+        debug_assert_framebuffer_complete(gl);
+
+        render_data.use_program(&ProgramId::PartitionUnpack);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::PartitionUnpack, "slots")),
+            2,
+        );
+
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+
+        for layer in 1..BIN_CAPACITY {
+            gl.framebuffer_texture_layer(GL::FRAMEBUFFER, GL::COLOR_ATTACHMENT0 + layer, None, 0, 0);
+        }
+
+        gl.draw_buffers(&js_sys::Array::of1(&GL::COLOR_ATTACHMENT0.into()));
+    }
+
+    /// Leaves the sorted keys bound to `SORTED_KEYS_TEXTURE_UNIT` and the cell ranges to
+    /// `CELL_RANGES_TEXTURE_UNIT`.
+    fn sorted_binning(render_data: &AppRenderData, locations: &UniformLocations, particle_count: u32) {
+        let gl = render_data.gl();
+
+        let sort_keys_texture = render_data.texture(&TextureId::SortKeys)
+            .unwrap()
+            .webgl_texture();
+
+        let sort_keys_swap_texture = render_data.texture(&TextureId::SortKeysSwap)
+            .unwrap()
+            .webgl_texture();
+
+        let cell_ranges_texture = render_data.texture(&TextureId::CellRanges)
+            .unwrap()
+            .webgl_texture();
+
+        gl.viewport(0, 0, SORT_TEXTURE_WIDTH as i32, SORT_TEXTURE_HEIGHT as i32);
+
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(sort_keys_texture),
+            0,
+        );
+
+        debug_assert_framebuffer_complete(gl);
+
+        render_data.use_program(&ProgramId::SortKeys);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::SortKeys, "particles")),
+            0,
+        );
+
+        gl.uniform1ui(
+            Some(locations.get(&ProgramId::SortKeys, "particle_count")),
+            particle_count,
+        );
+
+        gl.uniform1ui(
+            Some(locations.get(&ProgramId::SortKeys, "key_width")),
+            SORT_TEXTURE_WIDTH,
+        );
+
+        gl.uniform2ui(
+            Some(locations.get(&ProgramId::SortKeys, "grid_size")),
+            GRID_COLUMNS,
+            GRID_ROWS,
+        );
+
+        set_cell_uniforms(gl, locations, &ProgramId::SortKeys);
+
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+
+        render_data.use_program(&ProgramId::BitonicSort);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::BitonicSort, "keys")),
+            2,
+        );
+
+        let block_size_location = locations.get(&ProgramId::BitonicSort, "block_size");
+        let compare_distance_location = locations.get(&ProgramId::BitonicSort, "compare_distance");
+
+        let (mut source, mut target) = (sort_keys_texture, sort_keys_swap_texture);
+
+        // Every stage merges blocks twice as long as the previous one, halving the compare
+        // distance each step.
+        let steps = (1..=SORT_SIZE.trailing_zeros())
+            .flat_map(|stage| (0..stage).rev().map(move |step| (1u32 << stage, 1u32 << step)));
+
+        for (block_size, compare_distance) in steps {
+            bind_texture(gl, 2, source, GL::TEXTURE_2D);
+
+            gl.framebuffer_texture_2d(
+                GL::FRAMEBUFFER,
+                GL::COLOR_ATTACHMENT0,
+                GL::TEXTURE_2D,
+                Some(target),
+                0,
+            );
+
+            gl.uniform1ui(Some(block_size_location), block_size);
+            gl.uniform1ui(Some(compare_distance_location), compare_distance);
+
+            gl.draw_arrays(GL::TRIANGLES, 0, 3);
+
+            std::mem::swap(&mut source, &mut target);
+        }
+
+        gl.viewport(0, 0, GRID_COLUMNS as i32, GRID_ROWS as i32);
+
+        bind_texture(gl, 2, source, GL::TEXTURE_2D);
+
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(cell_ranges_texture),
+            0,
+        );
+
+        debug_assert_framebuffer_complete(gl);
+
+        render_data.use_program(&ProgramId::CellRanges);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::CellRanges, "keys")),
+            2,
+        );
+
+        gl.uniform2ui(
+            Some(locations.get(&ProgramId::CellRanges, "grid_size")),
+            GRID_COLUMNS,
+            GRID_ROWS,
+        );
+
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+
+        bind_texture(gl, SORTED_KEYS_TEXTURE_UNIT, source, GL::TEXTURE_2D);
+        bind_texture(gl, CELL_RANGES_TEXTURE_UNIT, cell_ranges_texture, GL::TEXTURE_2D);
+    }
+
+    /// Runs the update shader on the particles bound to texture unit 0 into `target_texture`.
+    fn update_pass(render_data: &AppRenderData, state: &RenderState, target_texture: &WebGlTexture, collision_stage: CollisionStage) {
+        let gl = render_data.gl();
+        let locations = &state.uniform_locations;
 
         let update_fb = render_data.framebuffer(&FramebufferId::Update)
             .unwrap()
             .webgl_framebuffer();
 
-        let binning_fb = render_data.framebuffer(&FramebufferId::Partition)
-            .unwrap()
-            .webgl_framebuffer();
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(update_fb));
+        gl.viewport(0, 0, PARTICLE_COUNT_SQRT as i32, PARTICLE_COUNT_SQRT as i32);
+
+        gl.framebuffer_texture_2d(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            GL::TEXTURE_2D,
+            Some(target_texture),
+            0,
+        );
+
+        debug_assert_framebuffer_complete(gl);
+
+        match &state.custom_update_program {
+            Some(program) => {
+                gl.use_program(Some(program));
+                set_linked_uniforms(render_data, locations, &ProgramId::Update);
+            }
+            None => {
+                render_data.use_program(&ProgramId::Update);
+            }
+        }
+
+        // A custom update shader may leave out any of these, so missing uniforms are skipped.
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "bins"),
+            1,
+        );
+
+        gl.uniform2f(
+            locations.find(&ProgramId::Update, "domain_min"),
+            DOMAIN_MIN.x,
+            DOMAIN_MIN.y,
+        );
+
+        gl.uniform2f(
+            locations.find(&ProgramId::Update, "cell_size"),
+            CELL_SIZE.x,
+            CELL_SIZE.y,
+        );
+
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "morph_targets"),
+            MORPH_TARGETS_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "force_field"),
+            FORCE_FIELD_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "masses"),
+            MASS_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "drags"),
+            DRAG_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "spawn_times"),
+            SPAWN_TIME_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "track"),
+            TRACK_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "sorted_keys"),
+            SORTED_KEYS_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "cell_ranges"),
+            CELL_RANGES_TEXTURE_UNIT as i32,
+        );
+
+        gl.uniform1f(
+            locations.find(&ProgramId::Update, "particle_radius"),
+            PARTICLE_RADIUS_SCALED,
+        );
+
+        gl.uniform2f(
+            locations.find(&ProgramId::Update, "domain_max"),
+            DOMAIN_MAX.x,
+            DOMAIN_MAX.y,
+        );
+
+        gl.uniform1i(
+            locations.find(&ProgramId::Update, "collision_stage"),
+            collision_stage.shader_value(),
+        );
+
+        gl.clear(GL::COLOR_BUFFER_BIT);
+
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+
+        // The target texture is sampled afterwards, so it must not stay attached.
+        detach_color_attachment(gl);
+    }
+
+    /// Reduces the velocities of the particles to their mean on every frame and schedules its
+    /// removal in the update pass following the read-back. Only one reduction is in flight at a
+    /// time, so that the same drift is not removed twice.
+    fn measure_drift(&self) {
+        {
+            let state = self.state();
+
+            let drift_pending = state.pending_reductions.iter()
+                .any(|(_, _, usage)| matches!(usage, ReductionUse::Drift));
+
+            if !state.remove_drift || state.paused || drift_pending {
+                return;
+            }
+        }
+
+        self.request_reduction(ReductionUse::Drift);
+    }
+
+    /// Resolves `resolve` with the largest speed of any particle, in API units, once it has been
+    /// reduced on the GPU and read back.
+    pub fn read_max_speed(&self, resolve: Function) {
+        self.request_reduction(ReductionUse::MaxSpeed(resolve));
+    }
+
+    /// Reduces the current particle velocities on the GPU and copies the resulting texel into a
+    /// pixel buffer. It is read back once the GPU has passed a fence, which is checked without
+    /// blocking at the start of every frame. Without a fence, it is read back right away.
+    fn request_reduction(&self, usage: ReductionUse) {
+        let gl = self.render_data.gl();
+
+        {
+            let state = self.state();
+            let (_, new_data_texture) = data_textures(&self.render_data, &state);
+
+            reduction_pass(&self.render_data, &state.uniform_locations, new_data_texture, state.particle_count);
+        }
+
+        let buffer = gl.create_buffer().unwrap();
+
+        gl.bind_buffer(GL::PIXEL_PACK_BUFFER, Some(&buffer));
+        gl.buffer_data_with_i32(GL::PIXEL_PACK_BUFFER, 4 * std::mem::size_of::<f32>() as i32, GL::STREAM_READ);
+        gl.read_pixels_with_i32(0, 0, 1, 1, GL::RGBA, GL::FLOAT, 0).unwrap();
+        gl.bind_buffer(GL::PIXEL_PACK_BUFFER, None);
+
+        detach_color_attachment(gl);
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        match gl.fence_sync(GL::SYNC_GPU_COMMANDS_COMPLETE, 0) {
+            Some(fence) => {
+                gl.flush();
+                self.state_mut().pending_reductions.push((fence, buffer, usage));
+            }
+            None => self.finish_reduction(buffer, usage),
+        }
+    }
+
+    fn resolve_finished_reductions(&self) {
+        let gl = self.render_data.gl();
+
+        let finished = {
+            let mut state = self.state_mut();
+
+            let (finished, pending) = std::mem::take(&mut state.pending_reductions)
+                .into_iter()
+                .partition(|(fence, _, _)| gl.client_wait_sync_with_u32(fence, 0, 0) != GL::TIMEOUT_EXPIRED);
+
+            state.pending_reductions = pending;
+            finished
+        };
+
+        for (fence, buffer, usage) in finished {
+            gl.delete_sync(Some(&fence));
+            self.finish_reduction(buffer, usage);
+        }
+    }
+
+    /// Reads the reduction back from `buffer`, deletes the buffer and hands the result over.
+    fn finish_reduction(&self, buffer: WebGlBuffer, usage: ReductionUse) {
+        let gl = self.render_data.gl();
+        let texel = Float32Array::new_with_length(4);
+
+        gl.bind_buffer(GL::PIXEL_PACK_BUFFER, Some(&buffer));
+        gl.get_buffer_sub_data_with_i32_and_array_buffer_view(GL::PIXEL_PACK_BUFFER, 0, &texel);
+        gl.bind_buffer(GL::PIXEL_PACK_BUFFER, None);
+        gl.delete_buffer(Some(&buffer));
+
+        let [max_speed, momentum_x, momentum_y, total_mass] = [0, 1, 2, 3].map(|index| texel.get_index(index));
+
+        let reduction = Reduction {
+            max_speed,
+            momentum: Vec2::new(momentum_x, momentum_y),
+            total_mass,
+        };
+
+        match usage {
+            ReductionUse::Drift => {
+                let mut state = self.state_mut();
+
+                // Drift removal may have been disabled in the meantime.
+                if state.remove_drift {
+                    // Velocity of the center of mass, which carries all of the momentum.
+                    let mean_velocity = reduction.mean_velocity();
+                    debug!("Removing drift: {}", mean_velocity);
+                    state.pending_drift = Some(mean_velocity);
+                }
+            }
+            ReductionUse::MaxSpeed(resolve) => {
+                let max_speed = reduction.max_speed * self.api_unit_scale();
+
+                if let Err(err) = resolve.call1(&JsValue::NULL, &max_speed.into()) {
+                    warn!("Could not resolve max speed read-back: {:?}", err);
+                }
+            }
+        }
+    }
+
+    /// Reads back the particle positions and points the auto camera at the densest region.
+    fn track_densest_region(&self) {
+        let current = {
+            let mut state = self.state_mut();
+            let elapsed_ms = state.elapsed_ms;
+
+            match &mut state.auto_camera {
+                Some(auto_camera) if elapsed_ms - auto_camera.last_measurement_ms >= AUTO_CAMERA_INTERVAL_MS => {
+                    auto_camera.last_measurement_ms = elapsed_ms;
+                    auto_camera.target
+                }
+                _ => return,
+            }
+        };
+
+        let target = {
+            let state = self.state();
+            let (_, new_data_texture) = data_textures(&self.render_data, &state);
+            let pixels = read_data_texture_rows(&self.render_data, new_data_texture, 0, DATA_TEXTURE_HEIGHT);
+
+            densest_region(&pixels, state.particle_count, current.map(|(region, _)| region))
+        };
+
+        self.render_data.gl().bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        if let Some(auto_camera) = &mut self.state_mut().auto_camera {
+            auto_camera.target = target.or(current);
+        }
+    }
+
+    /// Calls `resolve` once the GPU has finished all the work issued so far, which is checked
+    /// without blocking at the start of every frame. Without a fence, e.g. after losing the
+    /// context, waits for the GPU right away instead.
+    pub fn sync(&self, resolve: Function) {
+        let gl = self.render_data.gl();
+
+        match gl.fence_sync(GL::SYNC_GPU_COMMANDS_COMPLETE, 0) {
+            Some(fence) => {
+                // Without a flush, the fence might never reach the GPU while the page is idle.
+                gl.flush();
+                self.state_mut().pending_syncs.push((fence, resolve));
+            }
+            None => {
+                gl.finish();
+                call_sync_callback(&resolve);
+            }
+        }
+    }
+
+    fn resolve_finished_syncs(&self) {
+        let gl = self.render_data.gl();
+
+        let finished = {
+            let mut state = self.state_mut();
+
+            let (finished, pending) = std::mem::take(&mut state.pending_syncs)
+                .into_iter()
+                .partition(|(fence, _)| gl.client_wait_sync_with_u32(fence, 0, 0) != GL::TIMEOUT_EXPIRED);
+
+            state.pending_syncs = pending;
+            finished
+        };
+
+        for (fence, resolve) in finished {
+            gl.delete_sync(Some(&fence));
+            call_sync_callback(&resolve);
+        }
+    }
+
+    fn check_corruption(&self) {
+        let (callback, reset_on_error) = {
+            let mut state = self.state_mut();
+
+            let Some(callback) = state.error_callback.clone() else {
+                return;
+            };
+
+            if state.paused || state.elapsed_ms - state.last_corruption_check_ms < CORRUPTION_CHECK_INTERVAL_MS {
+                return;
+            }
+
+            state.last_corruption_check_ms = state.elapsed_ms;
+
+            (callback, state.reset_on_error)
+        };
+
+        // Sampling a random row keeps the read-back small while covering every particle over time.
+        let row = ((random() * DATA_TEXTURE_HEIGHT as f64) as u32).min(DATA_TEXTURE_HEIGHT - 1);
+
+        let pixels = {
+            let state = self.state();
+            let (_, new_data_texture) = data_textures(&self.render_data, &state);
+
+            read_data_texture_rows(&self.render_data, new_data_texture, row, 1)
+        };
+
+        self.render_data.gl().bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        let Some(index) = pixels.iter().position(|value| !value.is_finite()) else {
+            return;
+        };
+
+        let message = format!(
+            "particle {} has a non-finite {} ({})",
+            row * DATA_TEXTURE_WIDTH + index as u32 / 4,
+            if index % 4 < 2 { "position" } else { "velocity" },
+            pixels[index],
+        );
+
+        warn!("Particle data corrupted: {}", message);
+
+        if let Err(err) = callback.call1(&JsValue::NULL, &JsValue::from_str(&message)) {
+            warn!("Error callback failed: {:?}", err);
+        }
+
+        if reset_on_error {
+            self.reset();
+        }
+    }
+
+    /// Returns whether the key was handled by one of the keyboard controls.
+    fn on_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        if !self.state().keyboard_controls_enabled {
+            return false;
+        }
+
+        match key {
+            VirtualKeyCode::Space => {
+                let mut state = self.state_mut();
+                state.paused = !state.paused;
+                state.paused_on_blur = false;
+            }
+            VirtualKeyCode::R => self.reset(),
+            VirtualKeyCode::Left => self.state_mut().gravity.x -= GRAVITY_NUDGE,
+            VirtualKeyCode::Right => self.state_mut().gravity.x += GRAVITY_NUDGE,
+            VirtualKeyCode::Down => self.state_mut().gravity.y -= GRAVITY_NUDGE,
+            VirtualKeyCode::Up => self.state_mut().gravity.y += GRAVITY_NUDGE,
+            VirtualKeyCode::Plus | VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd => {
+                let mut state = self.state_mut();
+                state.time_scale = (state.time_scale * TIME_SCALE_STEP).min(MAX_TIME_SCALE);
+            }
+            VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => {
+                let mut state = self.state_mut();
+                state.time_scale = (state.time_scale / TIME_SCALE_STEP).max(MIN_TIME_SCALE);
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// (Re)creates the MSAA targets for the current canvas size, sample count and color space,
+    /// or releases them when neither MSAA nor linear blending is enabled.
+    fn update_msaa_targets(&self) {
+        let gl = self.render_data.gl();
+        let mut state = self.state_mut();
+
+        if let Some(targets) = state.msaa_targets.take() {
+            gl.delete_renderbuffer(Some(&targets.color));
+            gl.delete_renderbuffer(Some(&targets.depth));
+            gl.delete_texture(Some(&targets.resolved));
+        }
+
+        if (state.msaa_samples <= 1 && state.color_space == ColorSpace::Srgb) || self.canvas_is_degenerate() {
+            return;
+        }
+
+        // Zero samples make a regular renderbuffer, which is only needed for its format.
+        let samples = if state.msaa_samples > 1 { state.msaa_samples } else { 0 };
+        let format = state.color_space.target_format();
+
+        let width = self.render_data.canvas().width() as i32;
+        let height = self.render_data.canvas().height() as i32;
+
+        let color = gl.create_renderbuffer().unwrap();
+
+        gl.bind_renderbuffer(GL::RENDERBUFFER, Some(&color));
+        gl.renderbuffer_storage_multisample(GL::RENDERBUFFER, samples as i32, format, width, height);
+
+        let depth = gl.create_renderbuffer().unwrap();
+
+        gl.bind_renderbuffer(GL::RENDERBUFFER, Some(&depth));
+        gl.renderbuffer_storage_multisample(GL::RENDERBUFFER, samples as i32, GL::DEPTH_COMPONENT24, width, height);
+        gl.bind_renderbuffer(GL::RENDERBUFFER, None);
+
+        let resolved = gl.create_texture().unwrap();
+
+        bind_texture(gl, 2, &resolved, GL::TEXTURE_2D);
+        set_unfiltered_texture_params(gl, GL::TEXTURE_2D);
+
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            format as i32,
+            width,
+            height,
+            0,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            None,
+        ).unwrap();
+
+        let msaa_fb = self.render_data.framebuffer(&FramebufferId::Msaa)
+            .unwrap()
+            .webgl_framebuffer();
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(msaa_fb));
+        gl.framebuffer_renderbuffer(GL::FRAMEBUFFER, GL::COLOR_ATTACHMENT0, GL::RENDERBUFFER, Some(&color));
+        gl.framebuffer_renderbuffer(GL::FRAMEBUFFER, GL::DEPTH_ATTACHMENT, GL::RENDERBUFFER, Some(&depth));
+        debug_assert_framebuffer_complete(gl);
+
+        let resolve_fb = self.render_data.framebuffer(&FramebufferId::MsaaResolve)
+            .unwrap()
+            .webgl_framebuffer();
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(resolve_fb));
+        gl.framebuffer_texture_2d(GL::FRAMEBUFFER, GL::COLOR_ATTACHMENT0, GL::TEXTURE_2D, Some(&resolved), 0);
+        debug_assert_framebuffer_complete(gl);
+
+        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+
+        state.msaa_targets = Some(MsaaTargets { color, depth, resolved });
+    }
+
+    /// (Re)creates the picking texture at the canvas size, or deletes it when picking is disabled.
+    fn update_picking_texture(&self) {
+        let gl = self.render_data.gl();
+        let mut state = self.state_mut();
+
+        if let Some(texture) = state.picking_texture.take() {
+            gl.delete_texture(Some(&texture));
+        }
+
+        if !state.picking_enabled || self.canvas_is_degenerate() {
+            return;
+        }
+
+        let texture = gl.create_texture().unwrap();
+
+        bind_texture(gl, 2, &texture, GL::TEXTURE_2D);
+        set_unfiltered_texture_params(gl, GL::TEXTURE_2D);
+
+        gl.tex_storage_2d(
+            GL::TEXTURE_2D,
+            1,
+            GL::R32UI,
+            self.render_data.canvas().width() as i32,
+            self.render_data.canvas().height() as i32,
+        );
+
+        state.picking_texture = Some(texture);
+    }
 
-        let mut old_data_texture = render_data.texture(&TextureId::OldData)
-            .unwrap()
-            .webgl_texture();
+    fn canvas_is_degenerate(&self) -> bool {
+        let canvas = self.render_data.canvas();
+        canvas.width() == 0 || canvas.height() == 0
+    }
 
-        let mut new_data_texture = render_data.texture(&TextureId::NewData)
-            .unwrap()
-            .webgl_texture();
+    /// Converts a position on the canvas, in physical pixels from the top left corner, to clip space.
+    fn canvas_to_clip(&self, position: PhysicalPosition<f64>) -> Option<Vec2> {
+        if self.canvas_is_degenerate() {
+            return None;
+        }
 
-        let bins_texture = render_data.texture(&TextureId::Bins)
-            .unwrap()
-            .webgl_texture();
+        let canvas = self.render_data.canvas();
+        let viewport = self.state().viewport_mode.viewport(canvas.width(), canvas.height());
 
-        let partition_intermediate_texture = render_data.texture(&TextureId::PartitionIntermediate)
+        // Window positions count from the top, the viewport from the bottom.
+        let pixel = Vec2::new(position.x as f32, canvas.height() as f32 - position.y as f32);
+        let uv = (pixel - viewport.origin()) / viewport.size();
+
+        Some(2.0 * uv - 1.0)
+    }
+
+    fn on_focus_changed(&self, focused: bool) {
+        let mut state = self.state_mut();
+
+        if !state.pause_on_blur {
+            return;
+        }
+
+        if !focused && !state.paused {
+            state.paused = true;
+            state.paused_on_blur = true;
+        } else if focused && state.paused_on_blur {
+            state.paused = false;
+            state.paused_on_blur = false;
+        }
+    }
+
+    fn on_mouse_input(&self, button_state: ElementState) {
+        let pressed = button_state == ElementState::Pressed;
+
+        let spawn_center = {
+            let mut state = self.state_mut();
+            state.mouse_pressed = pressed;
+
+            state.cursor_position.filter(|_| pressed && state.spawn_on_click_enabled)
+        };
+
+        if let Some(center) = spawn_center {
+            self.spawn_burst(center);
+        }
+    }
+
+    fn on_cursor_moved(&self, position: PhysicalPosition<f64>) {
+        let Some(position) = self.canvas_to_clip(position) else {
+            return;
+        };
+
+        let mut state = self.state_mut();
+        state.cursor_position = Some(state.camera.clip_to_domain(position));
+    }
+
+    fn on_touch(&self, touch: &Touch) {
+        let Some(position) = self.canvas_to_clip(touch.location) else {
+            return;
+        };
+
+        let mut state = self.state_mut();
+
+        if !state.touch_controls_enabled {
+            return;
+        }
+
+        let index = state.touches.iter().position(|(id, _)| *id == touch.id);
+
+        match (touch.phase, index) {
+            (TouchPhase::Started, None) if state.touches.len() < 2 => state.touches.push((touch.id, position)),
+            (TouchPhase::Moved, Some(index)) => {
+                let before = state.touches.iter().map(|(_, position)| *position).collect::<Vec<_>>();
+                state.touches[index].1 = position;
+
+                if let [first, second] = before[..] {
+                    let after = [state.touches[0].1, state.touches[1].1];
+                    state.camera_transition = None;
+                    state.camera.pinch([first, second], after);
+                }
+            }
+            (TouchPhase::Ended | TouchPhase::Cancelled, Some(index)) => {
+                state.touches.remove(index);
+            }
+            _ => {}
+        }
+
+        // A single touch point drives the mouse interactions as if the left button was held.
+        let single_touch = match state.touches[..] {
+            [(_, position)] => Some(state.camera.clip_to_domain(position)),
+            _ => None,
+        };
+
+        state.cursor_position = single_touch;
+        state.mouse_pressed = single_touch.is_some();
+    }
+
+    fn on_resize(&self, new_size: PhysicalSize<u32>) {
+        if new_size.width == 0 || new_size.height == 0 {
+            debug!("Ignoring resize to an empty canvas");
+            return;
+        }
+
+        debug!("New WebGL viewport size: [{}, {}]", new_size.width, new_size.height);
+
+        self.render_data.gl()
+            .viewport(0, 0, new_size.width as i32, new_size.height as i32);
+
+        // The draw viewport and projection follow the canvas size on every frame by themselves,
+        // only the canvas-sized targets need to be replaced. Until they are, frames are drawn
+        // straight to the canvas and picking finds nothing.
+        self.release_canvas_targets();
+        let mut state = self.state_mut();
+        state.pending_resize_ms = Some(state.elapsed_ms);
+    }
+
+    /// Reallocates the canvas-sized targets once the canvas has stopped changing size.
+    fn finish_resize(&self) {
+        let settled = {
+            let mut state = self.state_mut();
+
+            match state.pending_resize_ms {
+                Some(resized_ms) if state.elapsed_ms - resized_ms >= RESIZE_DEBOUNCE_MS => {
+                    state.pending_resize_ms = None;
+                    true
+                }
+                _ => false,
+            }
+        };
+
+        if settled {
+            self.update_msaa_targets();
+            self.update_picking_texture();
+        }
+    }
+
+    fn release_canvas_targets(&self) {
+        let gl = self.render_data.gl();
+        let mut state = self.state_mut();
+
+        if let Some(targets) = state.msaa_targets.take() {
+            gl.delete_renderbuffer(Some(&targets.color));
+            gl.delete_renderbuffer(Some(&targets.depth));
+            gl.delete_texture(Some(&targets.resolved));
+        }
+
+        if let Some(texture) = state.picking_texture.take() {
+            gl.delete_texture(Some(&texture));
+        }
+    }
+}
+
+/// Projects the default gravity onto the screen plane of a device tilted by `beta` (front-back)
+/// and `gamma` (left-right) degrees, as reported by the `deviceorientation` event.
+/// A device lying flat gets no gravity, an upright one gets full gravity towards the bottom edge.
+pub fn gravity_from_orientation(beta: f32, gamma: f32, max_magnitude: f32) -> Vec2 {
+    let (sin_beta, cos_beta) = beta.to_radians().sin_cos();
+    let sin_gamma = gamma.to_radians().sin();
+
+    let gravity = GRAVITY.length() * Vec2::new(sin_gamma * cos_beta, -sin_beta);
+    gravity.clamp_length_max(max_magnitude.max(0.0))
+}
+
+/// Fills up `particles` to `count` with particles parked like absorbed ones, for when separated
+/// generation could not fit all of them into the domain. Also returns how many were placed.
+fn park_unplaced(mut particles: Vec<Particle>, count: u32) -> (Vec<Particle>, u32) {
+    let placed = particles.len() as u32;
+
+    if placed < count {
+        warn!("Only {placed} of {count} particles could be placed apart, the rest is parked");
+        particles.resize(count as usize, Particle::resting(ABSORBED_POSITION));
+    }
+
+    (particles, placed)
+}
+
+fn call_sync_callback(resolve: &Function) {
+    if let Err(err) = resolve.call0(&JsValue::NULL) {
+        warn!("Could not resolve GPU sync: {:?}", err);
+    }
+}
+
+/// Formats the lines of the parameter readout.
+fn hud_text(state: &RenderState) -> String {
+    let mut text = format!(
+        "FPS {:.0}\nPARTICLES {}\nTIME SCALE {:.2}\nGRAVITY {:.2}, {:.2}\nDAMPING {:.2}\nRESTITUTION {:.2}, {:.2}\n",
+        state.fps,
+        state.particle_count,
+        state.time_scale,
+        state.gravity.x,
+        state.gravity.y,
+        state.damping,
+        state.wall_restitution,
+        state.particle_restitution,
+    );
+
+    if state.paused {
+        text.push_str("PAUSED\n");
+    }
+
+    text
+}
+
+fn draw_viewport(render_data: &AppRenderData, state: &RenderState) -> Viewport {
+    let canvas = render_data.canvas();
+    state.viewport_mode.viewport(canvas.width(), canvas.height())
+}
+
+/// Binds the framebuffer the draw pass renders into: the offscreen one when MSAA or linear
+/// blending is enabled, the canvas otherwise.
+fn bind_draw_target(render_data: &AppRenderData, state: &RenderState) {
+    let msaa_fb = state.msaa_targets.as_ref().map(|_| {
+        render_data.framebuffer(&FramebufferId::Msaa)
             .unwrap()
-            .webgl_texture();
+            .webgl_framebuffer()
+    });
+
+    render_data.gl().bind_framebuffer(GL::FRAMEBUFFER, msaa_fb);
+}
+
+/// Runs `draw` with the current program once per copy of the particles: every mirror image of
+/// the mirror mode, each with every ghost offset in view. `quad_half_size` is the one the pass draws with.
+fn draw_copies(gl: &GL, locations: &UniformLocations, program_id: &ProgramId, state: &RenderState, quad_half_size: Vec2, draw: impl Fn()) {
+    let mirror_loc = locations.get(program_id, "mirror");
+    let ghost_offset_loc = locations.get(program_id, "ghost_offset");
 
-        if state.odd_frame {
-            mem::swap(&mut old_data_texture, &mut new_data_texture);
+    for mirror in state.mirror_mode.transforms() {
+        gl.uniform_matrix2fv_with_f32_array(Some(mirror_loc), false, &mirror.to_cols_array());
+
+        for offset in ghost_offsets(state, mirror, quad_half_size) {
+            gl.uniform2f(Some(ghost_offset_loc), offset.x, offset.y);
+            draw();
         }
+    }
+}
 
-        bind_texture(gl, 0, &old_data_texture, GL::TEXTURE_2D);
-        bind_texture(gl, 1, &bins_texture, GL::TEXTURE_2D_ARRAY);
+/// Returns the offsets the particles are drawn at in the mirror image `mirror`. With wrapping boundaries,
+/// particles near an edge are also drawn shifted by the domain size, so they appear on both sides of the seam.
+/// Shifted copies whose seam is out of view are left out.
+fn ghost_offsets(state: &RenderState, mirror: Mat2, quad_half_size: Vec2) -> Vec<Vec2> {
+    let size = DOMAIN_MAX - DOMAIN_MIN;
+
+    // Shifted particles are kept up to `quad_half_size` past the domain edges and may be drawn
+    // larger than that by their depth.
+    let depth_scale = if state.depth_enabled { state.depth_range.max_element().max(1.0) } else { 1.0 };
+    let margin = quad_half_size * (1.0 + depth_scale);
+
+    // The mirror images are orthogonal, so their transpose maps the view back onto the copies.
+    let view_corners = [Vec2::new(-1.0, -1.0), Vec2::new(1.0, -1.0), Vec2::new(-1.0, 1.0), Vec2::ONE]
+        .map(|corner| mirror.transpose() * state.camera.clip_to_domain(corner));
+
+    let view_min = view_corners.into_iter().reduce(Vec2::min).unwrap();
+    let view_max = view_corners.into_iter().reduce(Vec2::max).unwrap();
+
+    let [x_range, y_range] = state.boundary_modes.map(|mode| {
+        if mode == BoundaryMode::Wrap { -1..=1 } else { 0..=0 }
+    });
+
+    x_range
+        .flat_map(|x| y_range.clone().map(move |y| Vec2::new(x as f32, y as f32) * size))
+        .filter(|&offset| {
+            // Part of the domain the copy shifted by `offset` can cover.
+            let min = DOMAIN_MIN.max(DOMAIN_MIN + offset) - margin;
+            let max = DOMAIN_MAX.min(DOMAIN_MAX + offset) + margin;
+
+            offset == Vec2::ZERO || (min.cmple(view_max).all() && max.cmpge(view_min).all())
+        })
+        .collect()
+}
 
-        gl.enable(GL::BLEND);
-        gl.blend_func(GL::ONE, GL::ONE);
+/// Returns the data texture holding the current particle state and the one the next update
+/// pass writes into.
+fn data_textures<'a>(render_data: &'a AppRenderData, state: &RenderState) -> (&'a WebGlTexture, &'a WebGlTexture) {
+    let old_data_texture = render_data.texture(&TextureId::OldData)
+        .unwrap()
+        .webgl_texture();
+
+    let new_data_texture = render_data.texture(&TextureId::NewData)
+        .unwrap()
+        .webgl_texture();
+
+    if state.odd_frame {
+        (new_data_texture, old_data_texture)
+    } else {
+        (old_data_texture, new_data_texture)
+    }
+}
 
-        // Draw pass
+/// Splits the domain into `AUTO_CAMERA_REGIONS` squared regions and returns the one holding the
+/// most of the particles in `pixels`, together with the center of its particles. The `current`
+/// region is kept unless another one holds `AUTO_CAMERA_HYSTERESIS` times as many particles.
+fn densest_region(pixels: &[f32], particle_count: u32, current: Option<usize>) -> Option<(usize, Vec2)> {
+    let regions = AUTO_CAMERA_REGIONS as usize;
+    let mut counts = vec![0u32; regions * regions];
+    let mut sums = vec![Vec2::ZERO; regions * regions];
+
+    for particle in pixels.chunks_exact(4).take(particle_count as usize) {
+        let position = Vec2::new(particle[0], particle[1]);
+        let coords = ((position - DOMAIN_MIN) / (DOMAIN_MAX - DOMAIN_MIN) * regions as f32).floor();
+
+        // Skips particles outside of the domain, like the absorbed ones.
+        if coords.x < 0.0 || coords.y < 0.0 || coords.x >= regions as f32 || coords.y >= regions as f32 {
+            continue;
+        }
 
-        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
+        let region = coords.x as usize + coords.y as usize * regions;
+        counts[region] += 1;
+        sums[region] += position;
+    }
 
-        gl.viewport(
-            0,
-            0,
-            render_data.canvas().width() as i32,
-            render_data.canvas().height() as i32,
-        );
+    let densest = (0..counts.len()).max_by_key(|&region| counts[region])?;
 
-        gl.clear(GL::COLOR_BUFFER_BIT);
+    let region = match current {
+        Some(current) if (counts[densest] as f32) < counts[current] as f32 * AUTO_CAMERA_HYSTERESIS => current,
+        _ => densest,
+    };
 
-        render_data.use_program(&ProgramId::Draw);
+    (counts[region] > 0).then(|| (region, sums[region] / counts[region] as f32))
+}
 
-        let draw_program = render_data.program(&ProgramId::Draw)
-            .unwrap();
+/// Counts how many cells of the bins, as returned by `read_bin_layers`, hold 0, 1, ...,
+/// `BIN_CAPACITY` particles.
+fn occupancy_histogram(layers: &[Vec<u32>]) -> Vec<u32> {
+    let mut occupancy = vec![0usize; (GRID_COLUMNS * GRID_ROWS) as usize];
 
-        let pixel_size = (1.0 / render_data.canvas().width() as f32).min(
-            1.0 / render_data.canvas().height() as f32
-        );
+    for layer in layers {
+        for (cell, count) in occupancy.iter_mut().enumerate() {
+            if layer[cell] != 0 {
+                *count += 1;
+            }
+        }
+    }
 
-        gl.uniform1f(
-            Some(
-                &gl.get_uniform_location(draw_program, "point_size").unwrap()
-            ),
-            PARTICLE_RADIUS_SCALED / pixel_size
-        );
+    let mut histogram = vec![0u32; BIN_CAPACITY as usize + 1];
 
-        gl.draw_arrays(GL::POINTS, 0, state.particle_count as i32);
+    for count in occupancy {
+        histogram[count] += 1;
+    }
 
-        gl.disable(GL::BLEND);
+    histogram
+}
+
+/// Panics if a particle is binned more than once into the same cell, which happens when the
+/// binning intermediate is not cleared between layers.
+fn debug_assert_bins_consistent(layers: &[Vec<u32>]) {
+    for cell in 0..(GRID_COLUMNS * GRID_ROWS) as usize {
+        for (i, layer) in layers.iter().enumerate() {
+            let id = layer[cell];
+
+            debug_assert!(
+                id == 0 || layers[i + 1..].iter().all(|other| other[cell] != id),
+                "particle {} is binned more than once into cell {}",
+                id - 1,
+                cell,
+            );
+        }
+    }
+}
 
-        // Binning pass
+/// Index of the grid cell holding `position`, computed like `get_bin_coords` in the update
+/// shader, or `None` outside of the grid.
+fn cell_index(position: Vec2) -> Option<usize> {
+    let bin = ((position - DOMAIN_MIN) / CELL_SIZE).floor();
+    let inside = bin.x >= 0.0 && bin.y >= 0.0 && bin.x < GRID_COLUMNS as f32 && bin.y < GRID_ROWS as f32;
 
-        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&binning_fb));
-        gl.viewport(0, 0, GRID_COLUMNS as i32, GRID_ROWS as i32);
+    inside.then(|| bin.x as usize + bin.y as usize * GRID_COLUMNS as usize)
+}
 
-        render_data.use_program(&ProgramId::Partition);
+/// Checks that the bins, as returned by `read_bin_layers`, agree with the cells the update pass
+/// looks particles up in, which it computes from their positions in `pixels` on its own: every
+/// binned particle has to lie in its cell, and every particle inside the grid has to be binned
+/// unless its cell is full. Particles within rounding distance of a cell border are skipped.
+/// Only holds for layered binning.
+fn check_bins_match_cells(layers: &[Vec<u32>], pixels: &[f32], particle_count: u32) -> Result<(), String> {
+    const BORDER_TOLERANCE: f32 = 1e-4;
+
+    let expected_cell = |id: usize| {
+        let position = Vec2::new(pixels[id * 4], pixels[id * 4 + 1]);
+        let coords = (position - DOMAIN_MIN) / CELL_SIZE;
+        let on_border = (coords - coords.round()).abs().min_element() < BORDER_TOLERANCE;
+
+        (!on_border).then(|| cell_index(position))
+    };
+
+    let mut binned = vec![false; particle_count as usize];
+
+    for layer in layers {
+        for (cell, &id) in layer.iter().enumerate() {
+            if id == 0 || id > particle_count {
+                continue;
+            }
+
+            let id = id as usize - 1;
+            binned[id] = true;
+
+            if let Some(expected) = expected_cell(id) {
+                if expected != Some(cell) {
+                    return Err(format!(
+                        "particle {id} is binned into cell {cell}, but the update pass looks it up in {expected:?}",
+                    ));
+                }
+            }
+        }
+    }
 
-        let partition_program = render_data.program(&ProgramId::Partition)
-            .unwrap();
+    for (id, _) in binned.iter().enumerate().filter(|(_, &binned)| !binned) {
+        if let Some(Some(cell)) = expected_cell(id) {
+            if layers.iter().any(|layer| layer[cell] == 0) {
+                return Err(format!("particle {id} is missing from cell {cell}, which is not full"));
+            }
+        }
+    }
 
-        let pass_uniform_loc = gl.get_uniform_location(
-            partition_program,
-            "pass",
-        ).unwrap();
+    Ok(())
+}
 
-        gl.uniform2ui(
-            Some(
-                &gl.get_uniform_location(partition_program, "grid_size").unwrap()
-            ),
-            GRID_COLUMNS,
-            GRID_ROWS,
-        );
+/// Reads every layer of the bins texture through the currently bound framebuffer and returns
+/// the stored ids, one per cell and layer.
+fn read_bin_layers(gl: &GL, bins_texture: &WebGlTexture) -> Vec<Vec<u32>> {
+    let cell_count = (GRID_COLUMNS * GRID_ROWS) as usize;
+    let pixels = Uint32Array::new_with_length(cell_count as u32 * 4);
 
-        gl.uniform1i(
-            Some(
-                &gl.get_uniform_location(partition_program, "particles").unwrap()
-            ),
+    let mut layers = Vec::with_capacity(BIN_CAPACITY as usize);
+
+    for layer in 0..BIN_CAPACITY {
+        gl.framebuffer_texture_layer(
+            GL::FRAMEBUFFER,
+            GL::COLOR_ATTACHMENT0,
+            Some(bins_texture),
             0,
+            layer as i32,
         );
 
-        gl.uniform1i(
-            Some(
-                &gl.get_uniform_location(partition_program, "bins").unwrap()
-            ),
-            1,
-        );
+        debug_assert_framebuffer_complete(gl);
+
+        gl.read_pixels_with_opt_array_buffer_view(
+            0,
+            0,
+            GRID_COLUMNS as i32,
+            GRID_ROWS as i32,
+            GL::RGBA_INTEGER,
+            GL::UNSIGNED_INT,
+            Some(&pixels),
+        ).unwrap();
+
+        layers.push(pixels.to_vec().into_iter().step_by(4).collect());
+    }
 
+    layers
+}
+
+/// Reads back the first `particle_count` particles of a data texture and sums their momentum
+/// and kinetic energy.
+fn measure_conservation(render_data: &AppRenderData, data_texture: &WebGlTexture, particle_count: u32, masses: &[f32]) -> Conservation {
+    let pixels = read_data_texture_rows(render_data, data_texture, 0, DATA_TEXTURE_HEIGHT);
+
+    Conservation::from_particles(
+        pixels.chunks_exact(4)
+            .take(particle_count as usize)
+            .map(|particle| Vec2::new(particle[2], particle[3]))
+            .zip(masses.iter().copied())
+    )
+}
+
+/// Counts the particles whose position is not finite, or outside of the domain along an axis
+/// whose boundary mode should keep them in it, given the read back particle data. Particles
+/// swallowed by a static collider count as lost too, those absorbed by the sink do not.
+fn count_lost_particles(pixels: &[f32], particle_count: u32, boundary_modes: [BoundaryMode; 2]) -> u32 {
+    let lower = DOMAIN_MIN - PARTICLE_RADIUS_SCALED;
+    let upper = DOMAIN_MAX + PARTICLE_RADIUS_SCALED;
+    let [x_confined, y_confined] = boundary_modes.map(|mode| mode != BoundaryMode::None);
+    let confined = BVec2::new(x_confined, y_confined);
+
+    pixels.chunks_exact(4)
+        .take(particle_count as usize)
+        .map(|particle| Vec2::new(particle[0], particle[1]))
+        .filter(|&position| position != ABSORBED_POSITION)
+        .filter(|&position| !position.is_finite()
+            || ((position.cmplt(lower) | position.cmpgt(upper)) & confined).any())
+        .count() as u32
+}
+
+/// Counts the particles parked by the sink, given the read back particle data.
+fn count_absorbed_particles(pixels: &[f32], particle_count: u32) -> u32 {
+    pixels.chunks_exact(4)
+        .take(particle_count as usize)
+        .filter(|particle| Vec2::new(particle[0], particle[1]) == ABSORBED_POSITION)
+        .count() as u32
+}
+
+/// Halves the velocities of `data_texture` level by level down to a single texel, which holds
+/// the maximum speed, the momentum and the total mass of the first `particle_count` particles,
+/// leaving out the absorbed, frozen and boundary ones. Leaves the update framebuffer bound, with the
+/// texel attached.
+fn reduction_pass(render_data: &AppRenderData, locations: &UniformLocations, data_texture: &WebGlTexture, particle_count: u32) {
+    let gl = render_data.gl();
+
+    let targets = [TextureId::ReductionA, TextureId::ReductionB].map(|texture_id| {
+        render_data.texture(&texture_id)
+            .unwrap()
+            .webgl_texture()
+    });
+
+    let masses_texture = render_data.texture(&TextureId::Masses)
+        .unwrap()
+        .webgl_texture();
+
+    let update_fb = render_data.framebuffer(&FramebufferId::Update)
+        .unwrap()
+        .webgl_framebuffer();
+
+    gl.bind_framebuffer(GL::FRAMEBUFFER, Some(update_fb));
+
+    render_data.use_program(&ProgramId::Reduce);
+
+    bind_texture(gl, MASS_TEXTURE_UNIT, masses_texture, GL::TEXTURE_2D);
+
+    gl.uniform1i(
+        Some(locations.get(&ProgramId::Reduce, "source")),
+        REDUCTION_TEXTURE_UNIT as i32,
+    );
+
+    gl.uniform1i(
+        Some(locations.get(&ProgramId::Reduce, "masses")),
+        MASS_TEXTURE_UNIT as i32,
+    );
+
+    gl.uniform1i(
+        Some(locations.get(&ProgramId::Reduce, "particle_count")),
+        particle_count as i32,
+    );
+
+    let mut source = data_texture;
+    let mut input_size = UVec2::new(DATA_TEXTURE_WIDTH, DATA_TEXTURE_HEIGHT);
+
+    for level in 0.. {
+        let target = targets[level % 2];
+        let output_size = (input_size + 1) / 2;
+
+        // Attaching the target detaches the previous one, which is sampled now.
         gl.framebuffer_texture_2d(
             GL::FRAMEBUFFER,
             GL::COLOR_ATTACHMENT0,
             GL::TEXTURE_2D,
-            Some(partition_intermediate_texture),
+            Some(target),
             0,
         );
 
-        gl.active_texture(GL::TEXTURE1);
-        gl.read_buffer(GL::COLOR_ATTACHMENT0);
+        debug_assert_framebuffer_complete(gl);
+
+        bind_texture(gl, REDUCTION_TEXTURE_UNIT, source, GL::TEXTURE_2D);
+
+        gl.uniform1i(
+            Some(locations.get(&ProgramId::Reduce, "first_level")),
+            (level == 0) as i32,
+        );
+
+        gl.uniform2i(
+            Some(locations.get(&ProgramId::Reduce, "input_size")),
+            input_size.x as i32,
+            input_size.y as i32,
+        );
+
+        gl.viewport(0, 0, output_size.x as i32, output_size.y as i32);
+        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+
+        if output_size == UVec2::ONE {
+            break;
+        }
+
+        source = target;
+        input_size = output_size;
+    }
+}
+
+/// Reads `rows` rows of a data texture starting at `first_row`, four floats per particle.
+/// Leaves the update framebuffer bound.
+fn read_data_texture_rows(render_data: &AppRenderData, data_texture: &WebGlTexture, first_row: u32, rows: u32) -> Vec<f32> {
+    let gl = render_data.gl();
+
+    let update_fb = render_data.framebuffer(&FramebufferId::Update)
+        .unwrap()
+        .webgl_framebuffer();
+
+    gl.bind_framebuffer(GL::FRAMEBUFFER, Some(update_fb));
+
+    gl.framebuffer_texture_2d(
+        GL::FRAMEBUFFER,
+        GL::COLOR_ATTACHMENT0,
+        GL::TEXTURE_2D,
+        Some(data_texture),
+        0,
+    );
+
+    debug_assert_framebuffer_complete(gl);
+
+    let pixels = Float32Array::new_with_length(DATA_TEXTURE_WIDTH * rows * 4);
+
+    gl.read_pixels_with_opt_array_buffer_view(
+        0,
+        first_row as i32,
+        DATA_TEXTURE_WIDTH as i32,
+        rows as i32,
+        GL::RGBA,
+        GL::FLOAT,
+        Some(&pixels),
+    ).unwrap();
+
+    detach_color_attachment(gl);
+
+    pixels.to_vec()
+}
+
+fn detach_color_attachment(gl: &GL) {
+    gl.framebuffer_texture_2d(
+        GL::FRAMEBUFFER,
+        GL::COLOR_ATTACHMENT0,
+        GL::TEXTURE_2D,
+        None,
+        0,
+    );
+}
+
+fn debug_assert_framebuffer_complete(gl: &GL) {
+    debug_assert_eq!(
+        gl.check_framebuffer_status(GL::FRAMEBUFFER),
+        GL::FRAMEBUFFER_COMPLETE,
+        "framebuffer is incomplete",
+    );
+}
+
+/// Logs every pending WebGL error with the operation it was raised by, in debug builds only.
+/// Reading the errors stalls the pipeline, so this is meant for diagnosing blank output.
+fn debug_check_gl_errors(gl: &GL, context: &str) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    loop {
+        let error = gl.get_error();
+
+        let name = match error {
+            GL::NO_ERROR => return,
+            GL::INVALID_ENUM => "INVALID_ENUM",
+            GL::INVALID_VALUE => "INVALID_VALUE",
+            GL::INVALID_OPERATION => "INVALID_OPERATION",
+            GL::INVALID_FRAMEBUFFER_OPERATION => "INVALID_FRAMEBUFFER_OPERATION",
+            GL::OUT_OF_MEMORY => "OUT_OF_MEMORY",
+            GL::CONTEXT_LOST_WEBGL => {
+                error!("WebGL context lost during {}", context);
+                return;
+            }
+            _ => "unknown error",
+        };
+
+        error!("WebGL error {} ({:#06x}) during {}", name, error, context);
+    }
+}
+
+/// Sets the uniforms linked to `program_id` on the current program, which replaces it. The
+/// renderer only updates them on the programs it has built itself.
+fn set_linked_uniforms(render_data: &AppRenderData, locations: &UniformLocations, program_id: &ProgramId) {
+    let gl = render_data.gl();
+
+    let linked_uniforms = render_data.uniforms()
+        .values()
+        .filter(|uniform| uniform.program_ids().contains(program_id));
+
+    for uniform in linked_uniforms {
+        if let Some(location) = locations.find(program_id, &uniform.uniform_id().name()) {
+            let ctx = UniformContext::new(gl.clone(), 0.0, location.clone());
+            uniform.initialize_callback().call_with_into_js_arg(&ctx);
+        }
+    }
+}
+
+/// Builds a program from the update vertex shader and `fragment_source`, in place of the
+/// built-in update program.
+fn build_update_program(render_data: &AppRenderData, fragment_source: &str) -> Result<WebGlProgram, ShaderError> {
+    let gl = render_data.gl();
+
+    let fragment_shader = gl.create_shader(GL::FRAGMENT_SHADER).unwrap();
+
+    gl.shader_source(&fragment_shader, fragment_source);
+    gl.compile_shader(&fragment_shader);
+
+    if !gl.get_shader_parameter(&fragment_shader, GL::COMPILE_STATUS).as_bool().unwrap_or(false) {
+        let log = gl.get_shader_info_log(&fragment_shader).unwrap_or_default();
+        gl.delete_shader(Some(&fragment_shader));
+        return Err(ShaderError::Compile(log));
+    }
+
+    let program = gl.create_program().unwrap();
+
+    gl.attach_shader(&program, render_data.vertex_shader(&VertexShaderId::Update).unwrap());
+    gl.attach_shader(&program, &fragment_shader);
+    gl.link_program(&program);
+
+    // Only flagged for deletion, the shader lives as long as the program it is attached to.
+    gl.delete_shader(Some(&fragment_shader));
+
+    if !gl.get_program_parameter(&program, GL::LINK_STATUS).as_bool().unwrap_or(false) {
+        let log = gl.get_program_info_log(&program).unwrap_or_default();
+        gl.delete_program(Some(&program));
+        return Err(ShaderError::Link(log));
+    }
+
+    Ok(program)
+}
+
+/// Looks up the locations of all active uniforms of a program by name.
+fn active_uniform_locations(gl: &GL, program: &WebGlProgram) -> HashMap<String, WebGlUniformLocation> {
+    let count = gl.get_program_parameter(program, GL::ACTIVE_UNIFORMS)
+        .as_f64()
+        .unwrap() as u32;
+
+    (0..count)
+        .filter_map(|index| gl.get_active_uniform(program, index))
+        .filter_map(|info| {
+            // Arrays are listed as their first element, but linked by their own name.
+            let name = info.name().trim_end_matches("[0]").to_owned();
+            let location = gl.get_uniform_location(program, &name)?;
+            Some((name, location))
+        })
+        .collect()
+}
+
+fn state_uniform_link(
+    state: &Rc<RefCell<RenderState>>,
+    program_ids: impl Into<Bridge<ProgramId>>,
+    uniform_id: UniformId,
+    set_uniform: impl Fn(&GL, Option<&WebGlUniformLocation>, &RenderState) + 'static,
+) -> UniformLink<ProgramId, UniformId> {
+    let state = state.clone();
+
+    let mut link = UniformLink::new(
+        program_ids,
+        uniform_id,
+        move |ctx: &UniformContext| set_uniform(
+            ctx.gl(),
+            Some(ctx.uniform_location()),
+            &state.borrow(),
+        ),
+    );
+
+    link.set_use_init_callback_for_update(true);
+    link
+}
+
+fn create_data_texture_float_4(ctx: &TextureCreateContext, descriptor: TextureDescriptor, precision: Precision, data: Option<&[f32]>) -> WebGlTexture {
+    let gl = ctx.gl();
 
-        for i in 0..BIN_CAPACITY {
-            gl.clear_bufferuiv_with_u32_array(GL::COLOR, 0, &[0, 0, 0, 0]);
+    let texture = gl.create_texture().unwrap();
 
-            gl.uniform1ui(Some(&pass_uniform_loc), i);
+    bind_texture(gl, 0, &texture, GL::TEXTURE_2D);
+    set_unfiltered_texture_params(gl, GL::TEXTURE_2D);
 
-            gl.draw_arrays(GL::POINTS, 0, PARTICLE_COUNT as i32);
+    let (_, data_type) = precision.texture_formats();
+    let data_array = data.map(|data| precision.data_array(data));
 
-            gl.copy_tex_sub_image_3d(
-                GL::TEXTURE_2D_ARRAY,
-                0,
-                0,
-                0,
-                i as i32,
-                0,
-                0,
-                GRID_COLUMNS as i32,
-                GRID_ROWS as i32,
-            );
-        };
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+        GL::TEXTURE_2D,
+        0,
+        descriptor.internal_format as i32,
+        descriptor.width as i32,
+        descriptor.height as i32,
+        0,
+        GL::RGBA,
+        data_type,
+        data_array.as_ref(),
+    ).unwrap();
 
-        gl.read_buffer(GL::NONE);
+    debug_check_gl_errors(gl, "create_data_texture_float_4");
 
-        // Update pass
+    texture
+}
 
-        gl.bind_framebuffer(GL::FRAMEBUFFER, Some(update_fb));
-        gl.viewport(0, 0, PARTICLE_COUNT_SQRT as i32, PARTICLE_COUNT_SQRT as i32);
+fn create_data_texture_float_1(ctx: &TextureCreateContext, descriptor: TextureDescriptor, data: &[f32]) -> WebGlTexture {
+    let gl = ctx.gl();
 
-        gl.framebuffer_texture_2d(
-            GL::FRAMEBUFFER,
-            GL::COLOR_ATTACHMENT0,
-            GL::TEXTURE_2D,
-            Some(new_data_texture),
-            0,
-        );
+    let texture = gl.create_texture().unwrap();
 
-        let update_program = render_data.program(&ProgramId::Update)
-            .unwrap();
+    bind_texture(gl, 0, &texture, GL::TEXTURE_2D);
+    set_unfiltered_texture_params(gl, GL::TEXTURE_2D);
 
-        render_data.use_program(&ProgramId::Update);
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+        GL::TEXTURE_2D,
+        0,
+        descriptor.internal_format as i32,
+        descriptor.width as i32,
+        descriptor.height as i32,
+        0,
+        GL::RED,
+        GL::FLOAT,
+        Some(&Float32Array::from(data)),
+    ).unwrap();
 
-        gl.uniform1i(
-            Some(
-                &gl.get_uniform_location(update_program, "bins").unwrap()
-            ),
-            1,
-        );
+    debug_check_gl_errors(gl, "create_data_texture_float_1");
 
-        gl.uniform2ui(
-            Some(
-                &gl.get_uniform_location(update_program, "grid_size").unwrap()
-            ),
-            GRID_COLUMNS,
-            GRID_ROWS,
-        );
+    texture
+}
 
-        gl.uniform1f(
-            Some(
-                &gl.get_uniform_location(update_program, "particle_radius").unwrap()
-            ),
-            PARTICLE_RADIUS_SCALED,
-        );
+fn create_data_texture_integer(ctx: &TextureCreateContext, descriptor: TextureDescriptor) -> WebGlTexture {
+    let gl = ctx.gl();
 
-        gl.clear(GL::COLOR_BUFFER_BIT);
+    let texture = gl.create_texture().unwrap();
 
-        gl.draw_arrays(GL::TRIANGLES, 0, 3);
+    bind_texture(gl, 0, &texture, GL::TEXTURE_2D);
+    set_unfiltered_texture_params(gl, GL::TEXTURE_2D);
 
-        gl.bind_framebuffer(GL::FRAMEBUFFER, None);
-    }
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        GL::TEXTURE_2D,
+        0,
+        descriptor.internal_format as i32,
+        descriptor.width as i32,
+        descriptor.height as i32,
+        0,
+        GL::RED_INTEGER,
+        GL::UNSIGNED_INT,
+        None,
+    ).unwrap();
 
-    fn on_resize(&self, new_size: PhysicalSize<u32>) {
-        debug!("New WebGL viewport size: [{}, {}]", new_size.width, new_size.height);
+    debug_check_gl_errors(gl, "create_data_texture_integer");
 
-        self.render_data.gl()
-            .viewport(0, 0, new_size.width as i32, new_size.height as i32);
-    }
+    texture
 }
 
-fn create_data_texture_float32_4(ctx: &TextureCreateContext, width: u32, height: u32, data: Option<&[f32]>) -> WebGlTexture {
+/// Creates an unfiltered single channel texture for the parameter readout, optionally filled
+/// with one byte per texel.
+fn create_byte_texture(ctx: &TextureCreateContext, descriptor: TextureDescriptor, format: u32, pixels: Option<&[u8]>) -> WebGlTexture {
     let gl = ctx.gl();
 
     let texture = gl.create_texture().unwrap();
@@ -590,25 +6494,29 @@ fn create_data_texture_float32_4(ctx: &TextureCreateContext, width: u32, height:
     bind_texture(gl, 0, &texture, GL::TEXTURE_2D);
     set_unfiltered_texture_params(gl, GL::TEXTURE_2D);
 
-    let data_view = data.map(|data| unsafe { Float32Array::view(data) });
-    let data_view_obj = data_view.map(|data| Object::from(data));
+    // Rows of single bytes are not padded to four.
+    gl.pixel_storei(GL::UNPACK_ALIGNMENT, 1);
 
-    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
         GL::TEXTURE_2D,
         0,
-        GL::RGBA32F as i32,
-        width as i32,
-        height as i32,
+        descriptor.internal_format as i32,
+        descriptor.width as i32,
+        descriptor.height as i32,
         0,
-        GL::RGBA,
-        GL::FLOAT,
-        data_view_obj.as_ref(),
+        format,
+        GL::UNSIGNED_BYTE,
+        pixels,
     ).unwrap();
 
+    gl.pixel_storei(GL::UNPACK_ALIGNMENT, 4);
+
+    debug_check_gl_errors(gl, "create_byte_texture");
+
     texture
 }
 
-fn create_data_texture_integer(ctx: &TextureCreateContext, width: u32, height: u32) -> WebGlTexture {
+fn create_data_texture_ui32_2(ctx: &TextureCreateContext, descriptor: TextureDescriptor) -> WebGlTexture {
     let gl = ctx.gl();
 
     let texture = gl.create_texture().unwrap();
@@ -619,19 +6527,21 @@ fn create_data_texture_integer(ctx: &TextureCreateContext, width: u32, height: u
     gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
         GL::TEXTURE_2D,
         0,
-        GL::R32UI as i32,
-        width as i32,
-        height as i32,
+        descriptor.internal_format as i32,
+        descriptor.width as i32,
+        descriptor.height as i32,
         0,
-        GL::RED_INTEGER,
+        GL::RG_INTEGER,
         GL::UNSIGNED_INT,
         None,
     ).unwrap();
 
+    debug_check_gl_errors(gl, "create_data_texture_ui32_2");
+
     texture
 }
 
-fn create_data_texture_array_ui32_1(ctx: &TextureCreateContext, width: u32, height: u32, layers: u32) -> WebGlTexture {
+fn create_data_texture_array_ui32_1(ctx: &TextureCreateContext, descriptor: TextureDescriptor) -> WebGlTexture {
     let gl = ctx.gl();
 
     let texture = gl.create_texture().unwrap();
@@ -642,24 +6552,202 @@ fn create_data_texture_array_ui32_1(ctx: &TextureCreateContext, width: u32, heig
     gl.tex_image_3d_with_opt_u8_array(
         GL::TEXTURE_2D_ARRAY,
         0,
-        GL::R32UI as i32,
-        width as i32,
-        height as i32,
-        layers as i32,
+        descriptor.internal_format as i32,
+        descriptor.width as i32,
+        descriptor.height as i32,
+        descriptor.layers as i32,
         0,
         GL::RED_INTEGER,
         GL::UNSIGNED_INT,
         None,
     ).unwrap();
 
+    debug_check_gl_errors(gl, "create_data_texture_array_ui32_1");
+
+    texture
+}
+
+fn create_filtered_texture_f16_1(ctx: &TextureCreateContext, descriptor: TextureDescriptor) -> WebGlTexture {
+    let gl = ctx.gl();
+
+    let texture = gl.create_texture().unwrap();
+
+    bind_texture(gl, 0, &texture, GL::TEXTURE_2D);
+    set_unfiltered_texture_params(gl, GL::TEXTURE_2D);
+
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        GL::TEXTURE_2D,
+        0,
+        descriptor.internal_format as i32,
+        descriptor.width as i32,
+        descriptor.height as i32,
+        0,
+        GL::RED,
+        GL::HALF_FLOAT,
+        None,
+    ).unwrap();
+
+    debug_check_gl_errors(gl, "create_filtered_texture_f16_1");
+
     texture
 }
 
+/// Rainbow ramp from blue over green to red, the palette used unless one is set from JS.
+fn default_palette() -> Vec<u8> {
+    (0..DEFAULT_PALETTE_SIZE)
+        .flat_map(|i| {
+            let t = i as f32 / (DEFAULT_PALETTE_SIZE - 1) as f32;
+            let [r, g, b] = [3.0, 2.0, 1.0].map(|center: f32| {
+                ((1.5 - (4.0 * t - center).abs()).clamp(0.0, 1.0) * 255.0).round() as u8
+            });
+
+            [r, g, b, 255]
+        })
+        .collect()
+}
+
+/// Uploads `palette`, one RGBA entry per 4 bytes, as a linearly filtered `N x 1` texture.
+fn upload_palette(gl: &GL, texture: &WebGlTexture, palette: &[u8]) {
+    bind_texture(gl, PALETTE_TEXTURE_UNIT, texture, GL::TEXTURE_2D);
+    set_unfiltered_texture_params(gl, GL::TEXTURE_2D);
+
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+        GL::TEXTURE_2D,
+        0,
+        GL::RGBA8 as i32,
+        (palette.len() / 4) as i32,
+        1,
+        0,
+        GL::RGBA,
+        GL::UNSIGNED_BYTE,
+        Some(palette),
+    ).unwrap();
+}
+
+/// Sets the uniforms shaders use to map positions to grid cells on the current program.
+/// Sets the uniforms `draw.vert` colors particles by density with, expecting the bins on unit 1.
+/// Passes drawing with it but without colors do not have them, so missing ones are skipped.
+fn set_density_color_uniforms(gl: &GL, locations: &UniformLocations, program_id: &ProgramId) {
+    gl.uniform1i(
+        locations.find(program_id, "bins"),
+        1,
+    );
+
+    gl.uniform2f(
+        locations.find(program_id, "domain_min"),
+        DOMAIN_MIN.x,
+        DOMAIN_MIN.y,
+    );
+
+    gl.uniform2f(
+        locations.find(program_id, "cell_size"),
+        CELL_SIZE.x,
+        CELL_SIZE.y,
+    );
+}
+
+fn set_cell_uniforms(gl: &GL, locations: &UniformLocations, program_id: &ProgramId) {
+    gl.uniform2f(
+        Some(locations.get(program_id, "domain_min")),
+        DOMAIN_MIN.x,
+        DOMAIN_MIN.y,
+    );
+
+    gl.uniform2f(
+        Some(locations.get(program_id, "cell_size")),
+        CELL_SIZE.x,
+        CELL_SIZE.y,
+    );
+}
+
+/// Uploads a force field of two floats per texel and enables bilinear filtering on it, which
+/// needs `OES_texture_float_linear` for 32-bit floats. Without it, half floats are used instead.
+fn upload_force_field(gl: &GL, texture: &WebGlTexture, field: &[f32], width: u32, height: u32, float_linear_supported: bool) {
+    bind_texture(gl, FORCE_FIELD_TEXTURE_UNIT, texture, GL::TEXTURE_2D);
+    set_unfiltered_texture_params(gl, GL::TEXTURE_2D);
+
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+    gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+
+    let (internal_format, data_type, data_array): (u32, u32, Object) = if float_linear_supported {
+        (GL::RG32F, GL::FLOAT, Float32Array::from(field).into())
+    } else {
+        let half_field = field.iter()
+            .map(|&val| f16::from_f32(val).to_bits())
+            .collect::<Vec<u16>>();
+
+        (GL::RG16F, GL::HALF_FLOAT, Uint16Array::from(half_field.as_slice()).into())
+    };
+
+    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_array_buffer_view(
+        GL::TEXTURE_2D,
+        0,
+        internal_format as i32,
+        width as i32,
+        height as i32,
+        0,
+        GL::RG,
+        data_type,
+        Some(&data_array),
+    ).unwrap();
+
+    debug_check_gl_errors(gl, "upload_force_field");
+}
+
 fn bind_texture(gl: &GL, slot: u32, texture: &WebGlTexture, target: u32) {
     gl.active_texture(GL::TEXTURE0 + slot);
     gl.bind_texture(target, Some(&texture));
 }
 
+/// Uploads `data` to the texels of the particles with consecutive ids starting at `first_id` in
+/// a texture laid out like the data texture, one row at a time, as the ids may wrap to the next
+/// row. `texel_size` is the number of elements of `data` per texel, and `view` turns a row of
+/// them into the array uploaded with `format` and `data_type`.
+#[allow(clippy::too_many_arguments)]
+fn write_texel_rows<T>(
+    gl: &GL,
+    texture: &WebGlTexture,
+    first_id: u32,
+    data: &[T],
+    texel_size: usize,
+    format: u32,
+    data_type: u32,
+    view: impl Fn(&[T]) -> Object,
+) {
+    bind_texture(gl, 0, texture, GL::TEXTURE_2D);
+
+    let mut id = first_id;
+    let mut remaining = data;
+
+    while !remaining.is_empty() {
+        let x = id % DATA_TEXTURE_WIDTH;
+        let y = id / DATA_TEXTURE_WIDTH;
+        let (row, rest) = remaining.split_at(remaining.len().min((DATA_TEXTURE_WIDTH - x) as usize * texel_size));
+        let texel_count = (row.len() / texel_size) as u32;
+
+        gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view(
+            GL::TEXTURE_2D,
+            0,
+            x as i32,
+            y as i32,
+            texel_count as i32,
+            1,
+            format,
+            data_type,
+            Some(&view(row)),
+        ).unwrap();
+
+        id += texel_count;
+        remaining = rest;
+    }
+}
+
 fn set_unfiltered_texture_params(gl: &GL, target: u32) {
     gl.tex_parameteri(
         target,
@@ -690,4 +6778,333 @@ fn set_unfiltered_texture_params(gl: &GL, target: u32) {
         GL::TEXTURE_MAG_FILTER,
         GL::NEAREST as i32,
     );
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CELL_COUNT: usize = (GRID_COLUMNS * GRID_ROWS) as usize;
+
+    /// Center of the cell at `column` and `row`, away from its borders.
+    fn cell_center(column: u32, row: u32) -> Vec2 {
+        DOMAIN_MIN + (Vec2::new(column as f32, row as f32) + 0.5) * CELL_SIZE
+    }
+
+    /// Particle data as read back from the data texture, with zero velocities.
+    fn pixels(positions: &[Vec2]) -> Vec<f32> {
+        positions.iter().flat_map(|position| [position.x, position.y, 0.0, 0.0]).collect()
+    }
+
+    /// Evaluates a GLSL expression over `vec2` and `float` operands. Floats are kept as splatted
+    /// `Vec2`s, as they broadcast the same way. Supports the arithmetic operators, parentheses,
+    /// `floor` and the `vec2` and `uvec2` constructors, which is all the binning code paths use.
+    fn evaluate(expression: &str, variables: &[(&str, Vec2)]) -> Vec2 {
+        let mut tokens = Vec::new();
+        let mut rest = expression.trim();
+
+        while let Some(c) = rest.chars().next() {
+            let len = if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                    .unwrap_or(rest.len())
+            } else {
+                1
+            };
+
+            tokens.push(&rest[..len]);
+            rest = rest[len..].trim_start();
+        }
+
+        let mut parser = ExpressionParser { tokens, next: 0, variables };
+        let value = parser.sum();
+
+        assert_eq!(parser.next, parser.tokens.len(), "unexpected tokens in `{expression}`");
+        value
+    }
+
+    struct ExpressionParser<'a> {
+        tokens: Vec<&'a str>,
+        next: usize,
+        variables: &'a [(&'a str, Vec2)],
+    }
+
+    impl ExpressionParser<'_> {
+        fn peek(&self) -> Option<&str> {
+            self.tokens.get(self.next).copied()
+        }
+
+        fn take(&mut self) -> &str {
+            self.next += 1;
+            self.tokens[self.next - 1]
+        }
+
+        fn expect(&mut self, token: &str) {
+            assert_eq!(self.take(), token);
+        }
+
+        fn sum(&mut self) -> Vec2 {
+            let mut value = self.product();
+
+            while let Some(op @ ("+" | "-")) = self.peek() {
+                let op = op == "+";
+                self.next += 1;
+
+                let rhs = self.product();
+                value = if op { value + rhs } else { value - rhs };
+            }
+
+            value
+        }
+
+        fn product(&mut self) -> Vec2 {
+            let mut value = self.unary();
+
+            while let Some(op @ ("*" | "/")) = self.peek() {
+                let op = op == "*";
+                self.next += 1;
+
+                let rhs = self.unary();
+                value = if op { value * rhs } else { value / rhs };
+            }
+
+            value
+        }
+
+        fn unary(&mut self) -> Vec2 {
+            if self.peek() == Some("-") {
+                self.next += 1;
+                return -self.unary();
+            }
+
+            let token = self.take().to_owned();
+
+            if token == "(" {
+                let value = self.sum();
+                self.expect(")");
+                return value;
+            }
+
+            if self.peek() == Some("(") {
+                self.next += 1;
+                let argument = self.sum();
+                self.expect(")");
+
+                return match token.as_str() {
+                    "floor" => argument.floor(),
+                    "vec2" => argument,
+                    // Only ever applied to whole numbers, so truncating is exact.
+                    "uvec2" => argument.trunc(),
+                    _ => panic!("unsupported function `{token}`"),
+                };
+            }
+
+            match token.parse::<f32>() {
+                Ok(value) => Vec2::splat(value),
+                Err(_) => self.variables
+                    .iter()
+                    .find(|(name, _)| *name == token)
+                    .unwrap_or_else(|| panic!("unbound variable `{token}`"))
+                    .1,
+            }
+        }
+    }
+
+    /// The text between `start` and `end`, looking from the first `anchor` on.
+    fn extract<'a>(source: &'a str, anchor: &str, start: &str, end: &str) -> &'a str {
+        let (_, after_anchor) = source.split_once(anchor).expect("anchor not found");
+        let (_, after_start) = after_anchor.split_once(start).expect("start not found");
+
+        after_start.split_once(end).expect("end not found").0
+    }
+
+    /// Index of the cell at whole number `coords`, or `None` outside of the grid.
+    fn grid_cell(coords: Vec2) -> Option<usize> {
+        let inside = coords.x >= 0.0 && coords.y >= 0.0
+            && coords.x < GRID_COLUMNS as f32 && coords.y < GRID_ROWS as f32;
+
+        inside.then(|| coords.x as usize + coords.y as usize * GRID_COLUMNS as usize)
+    }
+
+    /// Cell the partition pass bins `position` into: the texel of the bins target, which is
+    /// `GRID_COLUMNS` by `GRID_ROWS`, that the point emitted by partition.vert lands on.
+    fn partition_cell(position: Vec2) -> Option<usize> {
+        let source = include_str!("shaders/partition.vert");
+        let grid_size = Vec2::new(GRID_COLUMNS as f32, GRID_ROWS as f32);
+
+        let bin_coords = evaluate(
+            extract(source, "void main()", "vec2 bin_coords = ", ";"),
+            &[("particle_pos", position), ("domain_min", DOMAIN_MIN), ("cell_size", CELL_SIZE)],
+        );
+        let clip = evaluate(
+            extract(source, "vec2 bin_coords = ", "gl_Position = vec4(", ", 0.0, 1.0);"),
+            &[("bin_coords", bin_coords), ("grid_size", grid_size)],
+        );
+
+        grid_cell(((clip + 1.0) * 0.5 * grid_size).floor())
+    }
+
+    /// Bin coordinates the update pass looks `position` up at, as computed by `get_bin_coords`
+    /// in update.frag.
+    fn update_bin_coords(position: Vec2) -> Vec2 {
+        let source = include_str!("shaders/update.frag");
+
+        evaluate(
+            extract(source, "uvec2 get_bin_coords(in vec2 position)", "return ", ";"),
+            &[("position", position), ("domain_min", DOMAIN_MIN), ("cell_size", CELL_SIZE)],
+        )
+    }
+
+    /// Offsets of the cells `collide` in update.frag searches, with exact collisions and
+    /// diagonal cell checks enabled.
+    fn update_cell_offsets() -> Vec<Vec2> {
+        let source = include_str!("shaders/update.frag");
+        let (_, body) = source.split_once("void collide(inout Particle particle").unwrap();
+
+        body.lines()
+            .take_while(|line| !line.starts_with('}'))
+            .filter_map(|line| line.trim().strip_prefix("collide_cell(particle, particle_id, bin_coords"))
+            .map(|call| match call.trim().strip_prefix("+ ivec2(") {
+                Some(offset) => {
+                    let (x, y) = offset
+                        .trim_end_matches(", checks_left);")
+                        .trim_end_matches(')')
+                        .split_once(',')
+                        .unwrap();
+
+                    Vec2::new(x.trim().parse().unwrap(), y.trim().parse().unwrap())
+                }
+                None => Vec2::ZERO,
+            })
+            .collect()
+    }
+
+    /// Bins the particles the way the layered binning pass does: every layer takes the highest
+    /// id per cell below those in the previous layers, stored plus one so that 0 stays empty.
+    fn layered_bins(positions: &[Vec2]) -> Vec<Vec<u32>> {
+        let mut layers = vec![vec![0u32; CELL_COUNT]; BIN_CAPACITY as usize];
+        let mut filled = vec![0usize; CELL_COUNT];
+
+        for (id, &position) in positions.iter().enumerate().rev() {
+            if let Some(cell) = partition_cell(position) {
+                if filled[cell] < BIN_CAPACITY as usize {
+                    layers[filled[cell]][cell] = id as u32 + 1;
+                    filled[cell] += 1;
+                }
+            }
+        }
+
+        layers
+    }
+
+    /// Ids found in the cells the update pass searches for neighbors of `position`.
+    fn neighbors(layers: &[Vec<u32>], position: Vec2) -> Vec<u32> {
+        let bin_coords = update_bin_coords(position);
+        let mut ids = Vec::new();
+
+        for offset in update_cell_offsets() {
+            if let Some(cell) = grid_cell(bin_coords + offset) {
+                ids.extend(layers.iter().map(|layer| layer[cell]).filter(|&id| id != 0).map(|id| id - 1));
+            }
+        }
+
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn cell_index_matches_grid_layout() {
+        assert_eq!(cell_index(cell_center(0, 0)), Some(0));
+        assert_eq!(cell_index(cell_center(3, 0)), Some(3));
+        assert_eq!(cell_index(cell_center(2, 5)), Some(2 + 5 * GRID_COLUMNS as usize));
+        assert_eq!(
+            cell_index(cell_center(GRID_COLUMNS - 1, GRID_ROWS - 1)),
+            Some(CELL_COUNT - 1),
+        );
+        assert_eq!(cell_index(DOMAIN_MIN - CELL_SIZE * 0.5), None);
+        assert_eq!(cell_index(DOMAIN_MAX + CELL_SIZE * 0.5), None);
+    }
+
+    #[test]
+    fn cell_index_matches_the_binning_shaders() {
+        let mut positions = vec![DOMAIN_MIN - CELL_SIZE * 0.5, DOMAIN_MAX + CELL_SIZE * 0.5, DOMAIN_MAX];
+
+        for column in (0..GRID_COLUMNS).step_by(7).chain([GRID_COLUMNS - 1]) {
+            for row in (0..GRID_ROWS).step_by(5).chain([GRID_ROWS - 1]) {
+                let center = cell_center(column, row);
+
+                positions.push(center);
+                positions.push(center - CELL_SIZE * 0.49);
+                positions.push(center + CELL_SIZE * 0.49);
+            }
+        }
+
+        for position in positions {
+            let update_cell = grid_cell(update_bin_coords(position));
+
+            assert_eq!(cell_index(position), update_cell, "{position}");
+            assert_eq!(partition_cell(position), update_cell, "{position}");
+        }
+    }
+
+    #[test]
+    fn update_pass_searches_the_cell_and_its_eight_neighbors() {
+        let mut offsets: Vec<_> = update_cell_offsets()
+            .into_iter()
+            .map(|offset| (offset.x as i32, offset.y as i32))
+            .collect();
+
+        offsets.sort_unstable();
+
+        let expected: Vec<_> = (-1..=1).flat_map(|x| (-1..=1).map(move |y| (x, y))).collect();
+        assert_eq!(offsets, expected);
+    }
+
+    #[test]
+    fn neighbor_search_finds_binned_particles() {
+        let positions = [
+            cell_center(10, 10),
+            cell_center(10, 10) + CELL_SIZE * 0.25,
+            cell_center(11, 10),
+            cell_center(9, 11),
+            cell_center(12, 10),
+            cell_center(40, 40),
+            DOMAIN_MAX + CELL_SIZE,
+        ];
+        let layers = layered_bins(&positions);
+
+        assert_eq!(check_bins_match_cells(&layers, &pixels(&positions), positions.len() as u32), Ok(()));
+        assert_eq!(neighbors(&layers, positions[0]), vec![0, 1, 2, 3]);
+        assert_eq!(neighbors(&layers, positions[4]), vec![2, 4]);
+        assert_eq!(neighbors(&layers, positions[5]), vec![5]);
+    }
+
+    #[test]
+    fn full_cells_may_drop_particles() {
+        let positions = vec![cell_center(7, 7); BIN_CAPACITY as usize + 2];
+        let layers = layered_bins(&positions);
+
+        assert_eq!(check_bins_match_cells(&layers, &pixels(&positions), positions.len() as u32), Ok(()));
+    }
+
+    #[test]
+    fn particles_binned_into_another_cell_are_reported() {
+        let positions = [cell_center(3, 3), cell_center(4, 3)];
+        let mut layers = layered_bins(&positions);
+        let (from, to) = (partition_cell(positions[1]).unwrap(), partition_cell(positions[1]).unwrap() + 1);
+
+        layers[0][to] = layers[0][from];
+        layers[0][from] = 0;
+
+        assert!(check_bins_match_cells(&layers, &pixels(&positions), positions.len() as u32).is_err());
+    }
+
+    #[test]
+    fn particles_missing_from_cells_that_are_not_full_are_reported() {
+        let positions = [cell_center(3, 3), cell_center(3, 3)];
+        let mut layers = layered_bins(&positions);
+
+        layers[1][partition_cell(positions[0]).unwrap()] = 0;
+
+        assert!(check_bins_match_cells(&layers, &pixels(&positions), positions.len() as u32).is_err());
+    }
+}
@@ -1,22 +1,34 @@
 extern crate core;
 
-use std::cell::OnceCell;
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::HashMap;
 use std::panic;
 
-use log::{debug, info, Level};
+use glam::Vec2;
+use js_sys::{Array, Float32Array, Function, Object, Promise, Reflect, Uint8ClampedArray};
+use log::{debug, error, info, Level, LevelFilter, warn};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use wasm_bindgen::prelude::*;
-use web_sys::{HtmlCanvasElement, window};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{HtmlCanvasElement, Performance, window};
 use winit::dpi::LogicalSize;
 use winit::error::OsError;
 use winit::event::{Event, WindowEvent};
-use winit::event_loop::{EventLoop, EventLoopBuilder, EventLoopProxy};
-use winit::platform::web::WindowBuilderExtWebSys;
+use winit::event_loop::{ControlFlow, EventLoopBuilder, EventLoopProxy, EventLoopWindowTarget};
+use winit::platform::web::{EventLoopExtWebSys, WindowBuilderExtWebSys, WindowExtWebSys};
 use winit::window::{Window, WindowBuilder};
 
-use crate::graphics::Graphics;
+use crate::graphics::{Axis, BIN_CAPACITY, BinClearMode, BinningMode, BlendMode, BoundaryMode, CollisionScheme, Container, ColorMode, ColorSpace, DebugView, DEFAULT_AGE_COLOR_LIFETIME_MS, DEFAULT_AUTO_CAMERA_SMOOTHING_MS, DEFAULT_PARTICLE_OPACITY, DOMAIN_MAX, DOMAIN_MIN, Easing, FalloffMode, ForceOrder, GRAVITY, Graphics, GraphicsOptions, gravity_from_orientation, GRID_COLUMNS, GRID_ROWS, ImpulseMode, MAX_PLATFORMS, MAX_TRACK_SEGMENTS, MirrorMode, PARTICLE_COUNT, ParticleField, RenderMode, tunable, ViewportMode};
+use crate::particle::{Particle, particles_from_image, VelocityField};
+use crate::stats::{ConservationDrift, Determinism, SharedStats};
+use crate::support::{probe_support, SupportInfo};
 
 mod particle;
 mod graphics;
+mod hud;
+mod stats;
+mod support;
 
 #[cfg(debug_assertions)]
 const LOG_LEVEL: Level = Level::Debug;
@@ -24,135 +36,1972 @@ const LOG_LEVEL: Level = Level::Debug;
 #[cfg(not(debug_assertions))]
 const LOG_LEVEL: Level = Level::Info;
 
+/// Opaque identifier of a running simulation instance, returned by `run`.
+type AppHandle = u32;
+
 #[wasm_bindgen(start)]
-pub fn main() {
+pub fn start() {
     panic::set_hook(Box::new(console_error_panic_hook::hook));
     console_log::init_with_level(LOG_LEVEL).expect("could not initialize logger");
     info!("Wasm successfully initialized!");
 }
 
+// winit supports a single event loop per page, so every instance is driven by the same one
+// and the messages sent to it carry the handle of the instance they are meant for.
 thread_local! {
-    static APP_EVENT_LOOP: OnceCell<EventLoopProxy<AppEvent>> = OnceCell::new();
+    static APP_EVENT_LOOP: OnceCell<EventLoopProxy<AppMessage>> = const { OnceCell::new() };
+    static APP_INSTANCES: RefCell<HashMap<AppHandle, EventLoopProxy<AppMessage>>> = RefCell::new(HashMap::new());
+    static NEXT_APP_HANDLE: Cell<AppHandle> = const { Cell::new(1) };
+    static GRAPHICS_OPTIONS: RefCell<GraphicsOptions> = RefCell::new(GraphicsOptions::default());
+    static APP_STATS: RefCell<HashMap<AppHandle, SharedStats>> = RefCell::new(HashMap::new());
+    static APP_RECORDINGS: RefCell<HashMap<AppHandle, Recording>> = RefCell::new(HashMap::new());
 }
 
+/// Starts a simulation instance on `canvas` and returns its handle, which the other functions
+/// take to address it. Several instances can run on the same page, each on its own canvas.
+/// `settle_steps` optionally runs that many damped, gravity-free simulation steps before
+/// the first frame to relax overlapping particles. `min_velocity` and `max_velocity` optionally
+/// hold the `[x, y]` bounds the initial particle velocities are drawn from, `[-0.1, -0.1]` and
+/// `[0.1, 0.1]` by default; pass `[0, 0]` for both to start at rest. `mass_range` optionally
+/// holds the `[min, max]` bounds of the random particle masses, `[1, 1]` by default. Heavier
+/// particles are pushed less by collisions, impulses and the force field, while gravity
+/// accelerates all of them alike.
+/// `max_bytes` optionally caps the texture memory of the instance, see `textureMemoryBytes`.
+/// `drag_range` optionally holds the `[min, max]` bounds of the random per-particle drag
+/// coefficients, `[0, 0]` by default. A particle with drag `k` loses a factor of `exp(-k)` of its
+/// velocity per unit of time on top of the global damping, like sediment in water.
+/// `initial_velocity` optionally replaces the random initial velocities with a coherent field:
+/// `"random"` (default), `"zero"`, `"rotational"` spinning the particles around the center of
+/// the domain with `initial_velocity_rate` radians per unit of time, or `"shear"` moving them
+/// horizontally with `initial_velocity_rate` times their height above the center. The rate
+/// defaults to 1. The field also applies on resets.
+///
+/// Fails if the canvas is not attached to the document, is already used by a running instance,
+/// cannot provide a WebGL2 context, e.g. because it already has a different kind of context,
+/// or if the textures would exceed `max_bytes`. The limit is checked before anything is allocated.
 #[wasm_bindgen]
-pub async fn run(canvas: HtmlCanvasElement, canvas_width: u32, canvas_height: u32) {
-    let context = Context::new();
+#[allow(clippy::too_many_arguments)]
+pub async fn run(
+    canvas: HtmlCanvasElement,
+    canvas_width: u32,
+    canvas_height: u32,
+    settle_steps: Option<u32>,
+    min_velocity: Option<Vec<f32>>,
+    max_velocity: Option<Vec<f32>>,
+    mass_range: Option<Vec<f32>>,
+    max_bytes: Option<f64>,
+    drag_range: Option<Vec<f32>>,
+    initial_velocity: Option<String>,
+    initial_velocity_rate: Option<f32>,
+) -> Result<AppHandle, JsValue> {
+    if !canvas.is_connected() {
+        return Err(JsError::new(&CreateError::CanvasDetached.to_string()).into());
+    }
 
-    APP_EVENT_LOOP.with(|app_event_loop| {
-        if app_event_loop.get().is_some() {
-            panic!("the application has already started.")
-        }
+    let defaults = GRAPHICS_OPTIONS.with(|options| options.borrow().clone());
+
+    let mass_range = vec2_argument(mass_range, "mass_range")?
+        .unwrap_or(Vec2::new(defaults.min_mass, defaults.max_mass));
+
+    let drag_range = vec2_argument(drag_range, "drag_range")?
+        .unwrap_or(Vec2::new(defaults.min_drag, defaults.max_drag));
+
+    let options = GraphicsOptions {
+        settle_steps: settle_steps.unwrap_or(0),
+        min_velocity: vec2_argument(min_velocity, "min_velocity")?
+            .map_or(defaults.min_velocity, |velocity| velocity / defaults.api_unit_scale),
+        max_velocity: vec2_argument(max_velocity, "max_velocity")?
+            .map_or(defaults.max_velocity, |velocity| velocity / defaults.api_unit_scale),
+        min_mass: mass_range.x,
+        max_mass: mass_range.y,
+        min_drag: drag_range.x,
+        max_drag: drag_range.y,
+        initial_velocity: velocity_field_argument(initial_velocity.as_deref(), initial_velocity_rate)?
+            .unwrap_or(defaults.initial_velocity),
+        ..defaults
+    };
+
+    if options.min_velocity.cmpgt(options.max_velocity).any() {
+        return Err(JsError::new("min_velocity must not exceed max_velocity").into());
+    }
+
+    if !(options.min_mass > 0.0 && options.max_mass.is_finite()) {
+        return Err(JsError::new("masses must be positive and finite").into());
+    }
+
+    if options.min_mass > options.max_mass {
+        return Err(JsError::new("mass_range must be ordered as [min, max]").into());
+    }
+
+    if !(options.min_drag >= 0.0 && options.max_drag.is_finite()) {
+        return Err(JsError::new("drag coefficients must be non-negative and finite").into());
+    }
+
+    if options.min_drag > options.max_drag {
+        return Err(JsError::new("drag_range must be ordered as [min, max]").into());
+    }
+
+    let required_bytes = graphics::texture_memory_bytes(options.precision);
+
+    if let Some(max_bytes) = max_bytes.filter(|&max_bytes| required_bytes as f64 > max_bytes) {
+        return Err(JsError::new(&CreateError::MemoryLimit { required_bytes, max_bytes }.to_string()).into());
+    }
 
-        app_event_loop.set(context.event_loop.create_proxy()).unwrap();
+    let proxy = APP_EVENT_LOOP.with(|app_event_loop| app_event_loop.get_or_init(spawn_event_loop).clone());
+
+    let handle = NEXT_APP_HANDLE.with(|next_handle| {
+        let handle = next_handle.get();
+        next_handle.set(handle + 1);
+        handle
     });
 
-    let app = App::new(&context, canvas, LogicalSize::new(canvas_width, canvas_height))
-        .expect("could not create application");
+    APP_INSTANCES.with(|app_instances| app_instances.borrow_mut().insert(handle, proxy.clone()));
+
+    let created = Promise::new(&mut |resolve, reject| {
+        let sent = proxy.send_event(AppMessage::CreateRequested {
+            handle,
+            canvas: canvas.clone(),
+            size: LogicalSize::new(canvas_width, canvas_height),
+            options: options.clone(),
+            resolve,
+            reject: reject.clone(),
+        });
+
+        if sent.is_err() {
+            APP_INSTANCES.with(|app_instances| app_instances.borrow_mut().remove(&handle));
+
+            let err = JsError::new("the event loop has been terminated");
 
-    app.run(context);
+            if let Err(err) = reject.call1(&JsValue::NULL, &err.into()) {
+                warn!("Could not reject promise: {:?}", err);
+            }
+        }
+    });
+
+    JsFuture::from(created).await?;
+
+    Ok(handle)
+}
+
+fn vec2_argument(argument: Option<Vec<f32>>, name: &str) -> Result<Option<Vec2>, JsError> {
+    match argument.as_deref() {
+        None => Ok(None),
+        Some(&[x, y]) => Ok(Some(Vec2::new(x, y))),
+        Some(_) => Err(JsError::new(&format!("{name} must hold exactly two components"))),
+    }
+}
+
+fn velocity_field_argument(kind: Option<&str>, rate: Option<f32>) -> Result<Option<VelocityField>, JsError> {
+    let rate = rate.unwrap_or(1.0);
+
+    if !rate.is_finite() {
+        return Err(JsError::new("initial_velocity_rate must be finite"));
+    }
+
+    match kind {
+        None => Ok(None),
+        Some("random") => Ok(Some(VelocityField::Random)),
+        Some("zero") => Ok(Some(VelocityField::Zero)),
+        Some("rotational") => Ok(Some(VelocityField::Rotational(rate))),
+        Some("shear") => Ok(Some(VelocityField::Shear(rate))),
+        Some(kind) => Err(JsError::new(&format!("unknown initial velocity field \"{kind}\""))),
+    }
+}
+
+/// Changes which log messages are printed to the console: `"trace"`, `"debug"`, `"info"`,
+/// `"warn"`, `"error"` or `"off"`. The initial level depends on the build configuration.
+#[wasm_bindgen(js_name = "setLogLevel")]
+pub fn set_log_level(level: &str) -> Result<(), JsError> {
+    let filter: LevelFilter = level.parse()
+        .map_err(|_| JsError::new(&format!("unknown log level \"{level}\"")))?;
+
+    log::set_max_level(filter);
+    Ok(())
+}
+
+/// Reports whether this device can run the simulation, probing `canvas` or,
+/// when omitted, a temporary canvas. Does not start the application.
+#[wasm_bindgen(js_name = "checkSupport")]
+pub fn check_support(canvas: Option<HtmlCanvasElement>) -> Result<SupportInfo, JsValue> {
+    probe_support(canvas)
+}
+
+/// Returns how many particles the textures of this device could hold, probing `canvas` or,
+/// when omitted, a temporary canvas. 0 if the simulation is not supported at all. This is a
+/// ceiling of the device, not a setting: every instance simulates the fixed `particleCount`
+/// of `buildInfo`.
+#[wasm_bindgen(js_name = "maxParticleCount")]
+pub fn max_particle_count(canvas: Option<HtmlCanvasElement>) -> Result<u32, JsValue> {
+    Ok(probe_support(canvas)?.particle_capacity())
+}
+
+/// Version and compile-time configuration of this build, for bug reports.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct BuildInfo {
+    pub version: String,
+    pub debug: bool,
+    #[wasm_bindgen(js_name = "logLevel")]
+    pub log_level: String,
+    #[wasm_bindgen(js_name = "particleCount")]
+    pub particle_count: u32,
+    #[wasm_bindgen(js_name = "gridColumns")]
+    pub grid_columns: u32,
+    #[wasm_bindgen(js_name = "gridRows")]
+    pub grid_rows: u32,
+    #[wasm_bindgen(js_name = "binCapacity")]
+    pub bin_capacity: u32,
+}
+
+#[wasm_bindgen(js_name = "buildInfo")]
+pub fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        debug: cfg!(debug_assertions),
+        log_level: LOG_LEVEL.to_string(),
+        particle_count: PARTICLE_COUNT,
+        grid_columns: GRID_COLUMNS,
+        grid_rows: GRID_ROWS,
+        bin_capacity: BIN_CAPACITY,
+    }
 }
 
 #[wasm_bindgen(js_name = "isRunning")]
-pub fn is_running() -> bool {
-    APP_EVENT_LOOP.with(|val| val.get().is_some())
+pub fn is_running(handle: AppHandle) -> bool {
+    APP_INSTANCES.with(|app_instances| app_instances.borrow().contains_key(&handle))
+}
+
+/// Selects the storage format of the particle data (`"f32"` or `"f16"`).
+/// Applies to the instances started afterwards.
+#[wasm_bindgen(js_name = "setPrecision")]
+pub fn set_precision(precision: &str) -> Result<(), JsError> {
+    let precision = precision.parse()?;
+    update_graphics_options(|options| options.precision = precision);
+    Ok(())
+}
+
+/// Sets how many units of your choice, e.g. meters, the simulation domain spans at the API, 2
+/// by default. Positions, lengths, velocities and accelerations passed to and read back from an
+/// instance are then converted from and to these units, with the origin at the center of the
+/// domain; the initial velocity range of `run` as well. Applies to the instances started
+/// afterwards.
+///
+/// This is only a unit conversion at the API boundary. The simulation keeps working in its own
+/// units, so the default gravity, the particle radius, the tunables and the built-in controls
+/// are not affected.
+#[wasm_bindgen(js_name = "setApiUnitScale")]
+pub fn set_api_unit_scale(units_across_domain: f32) -> Result<(), JsError> {
+    if !(units_across_domain > 0.0 && units_across_domain.is_finite()) {
+        return Err(JsError::new("the API unit scale must be positive and finite"));
+    }
+
+    update_graphics_options(|options| options.api_unit_scale = units_across_domain / (DOMAIN_MAX.x - DOMAIN_MIN.x));
+    Ok(())
+}
+
+/// Keeps the particles generated on startup and resets at least `spacing` particle diameters
+/// apart, so that they start without overlaps, or places them independently with `undefined`.
+/// When the domain cannot fit them all, the ones left over are parked outside of it and do not
+/// take part in the simulation, see `placedParticleCount`. Applies to the instances started
+/// afterwards.
+#[wasm_bindgen(js_name = "setStartSpacing")]
+pub fn set_start_spacing(spacing: Option<f32>) -> Result<(), JsError> {
+    if spacing.is_some_and(|spacing| !(spacing > 0.0 && spacing.is_finite())) {
+        return Err(JsError::new("the start spacing must be positive and finite"));
+    }
+
+    update_graphics_options(|options| options.start_spacing = spacing);
+    Ok(())
+}
+
+/// Returns the number of bytes of texture memory the instances started afterwards allocate, with
+/// the current precision. The canvas-sized render targets come on top of this.
+#[wasm_bindgen(js_name = "textureMemoryBytes")]
+pub fn texture_memory_bytes() -> f64 {
+    let precision = GRAPHICS_OPTIONS.with(|options| options.borrow().precision);
+    graphics::texture_memory_bytes(precision) as f64
 }
 
 #[wasm_bindgen(js_name = "handleResize")]
-pub fn handle_resize(new_width: u32, new_height: u32) {
-    send_user_event(AppEvent::ResizeRequested(LogicalSize::new(new_width, new_height)))
+pub fn handle_resize(handle: AppHandle, new_width: u32, new_height: u32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ResizeRequested { width: new_width, height: new_height })
 }
 
-fn send_user_event(event: AppEvent) {
-    APP_EVENT_LOOP.with(|app_event_loop| {
-        app_event_loop
-            .get().expect("the application has not been started")
-            .send_event(event).expect("the application has been terminated");
-    });
+/// Renders only every `n`-th frame while the physics keeps running every frame,
+/// for simulation-heavy uses where visuals are secondary. Defaults to 1.
+#[wasm_bindgen(js_name = "setDrawEvery")]
+pub fn set_draw_every(handle: AppHandle, n: u32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::DrawEveryChanged(n))
+}
+
+/// Draws only every `n`-th particle as a cheap preview of large simulations, while the physics
+/// keeps simulating all of them. Defaults to 1.
+#[wasm_bindgen(js_name = "setDrawStride")]
+pub fn set_draw_stride(handle: AppHandle, n: u32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::DrawStrideChanged(n))
+}
+
+/// Selects how overlapping particles are blended: `"additive"` (default) adds up their colors,
+/// `"alpha"` draws them over each other with `opacity` in `[0, 1]`, 0.5 by default, and
+/// `"opaque"` draws them without blending.
+#[wasm_bindgen(js_name = "setBlendMode")]
+pub fn set_blend_mode(handle: AppHandle, mode: &str, opacity: Option<f32>) -> Result<(), JsError> {
+    let opacity = opacity.unwrap_or(DEFAULT_PARTICLE_OPACITY);
+
+    if !opacity.is_finite() {
+        return Err(JsError::new("opacity must be finite"));
+    }
+
+    send_user_event(handle, AppEvent::BlendModeChanged(mode.parse()?, opacity))?;
+    Ok(())
+}
+
+/// Selects the color space particles are blended in: `"srgb"` (default) adds up the colors as they
+/// are, which makes dense areas washed out and too bright with additive blending, `"linear"`
+/// blends in linear space and converts the result to sRGB, so overlaps brighten gradually and
+/// sparse areas keep their saturation. Linear blending renders into an offscreen target.
+#[wasm_bindgen(js_name = "setColorSpace")]
+pub fn set_color_space(handle: AppHandle, color_space: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ColorSpaceChanged(color_space.parse()?))?;
+    Ok(())
+}
+
+#[wasm_bindgen(js_name = "setPaused")]
+pub fn set_paused(handle: AppHandle, paused: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::PauseRequested(paused))
+}
+
+/// Pauses the physics while the canvas does not have focus, e.g. after switching to another tab
+/// or window, and resumes it when the focus returns. Off by default. Only applies once the canvas
+/// has been focused, e.g. by clicking it, as it receives no blur event otherwise.
+#[wasm_bindgen(js_name = "setPauseOnBlur")]
+pub fn set_pause_on_blur(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::PauseOnBlurToggled(enabled))
+}
+
+/// Adds velocity to every particle on the next simulation step.
+/// `mode` is one of `"uniform"`, `"radial"` or `"random"`.
+#[wasm_bindgen(js_name = "applyImpulse")]
+pub fn apply_impulse(handle: AppHandle, mode: &str, strength_x: f32, strength_y: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ImpulseRequested(mode.parse()?, Vec2::new(strength_x, strength_y)))?;
+    Ok(())
+}
+
+/// Adds the velocity (`velocity_x`, `velocity_y`) once, on the next simulation step, to the
+/// particles inside the rectangle with its lower left corner at (`x`, `y`) and a size of `width`
+/// by `height` in domain coordinates.
+#[wasm_bindgen(js_name = "pushRegion")]
+pub fn push_region(handle: AppHandle, x: f32, y: f32, width: f32, height: f32, velocity_x: f32, velocity_y: f32) -> Result<(), JsError> {
+    let min = Vec2::new(x, y);
+    send_user_event(handle, AppEvent::RegionPushed(min, min + Vec2::new(width, height), Vec2::new(velocity_x, velocity_y)))
+}
+
+/// Multiplies the velocity of every particle by `factor` once, on the next simulation step.
+/// Factors below 1 cool the system down, factors above 1 heat it up.
+#[wasm_bindgen(js_name = "scaleVelocities")]
+pub fn scale_velocities(handle: AppHandle, factor: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::VelocitiesScaled(factor))
+}
+
+/// Enables removing the net drift of the particles: on every frame, their mean velocity is reduced
+/// on the GPU and subtracted from every particle, which keeps the center of mass in place.
+#[wasm_bindgen(js_name = "setRemoveDrift")]
+pub fn set_remove_drift(handle: AppHandle, remove_drift: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::RemoveDriftToggled(remove_drift))
+}
+
+/// Points gravity along the tilt of the device, given the `beta` and `gamma` angles (in degrees)
+/// of a `deviceorientation` event. The magnitude is clamped to `max_magnitude` (in API units),
+/// which defaults to the strength of the default gravity.
+#[wasm_bindgen(js_name = "setGravityFromOrientation")]
+pub fn set_gravity_from_orientation(handle: AppHandle, beta: f32, gamma: f32, max_magnitude: Option<f32>) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::GravityTilted(beta, gamma, max_magnitude))
+}
+
+/// Keeps turning gravity around at `degrees_per_second` (counterclockwise for positive rates)
+/// while the simulation runs, starting from its current direction, with its strength set to
+/// `magnitude`. The rotation stops when gravity is set otherwise, e.g. by `setGravityFromOrientation`.
+#[wasm_bindgen(js_name = "setGravityRotation")]
+pub fn set_gravity_rotation(handle: AppHandle, degrees_per_second: f32, magnitude: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::GravityRotationChanged(degrees_per_second, magnitude))
+}
+
+/// Selects what happens at the edge of the domain: `"none"` (default), `"bounce"` or `"wrap"`.
+/// Applies to both axes, see `setBoundaryModeX` and `setBoundaryModeY` to set them separately.
+#[wasm_bindgen(js_name = "setBoundaryMode")]
+pub fn set_boundary_mode(handle: AppHandle, mode: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::BoundaryModeChanged(mode.parse()?))?;
+    Ok(())
+}
+
+/// Selects what happens at the left and right edges of the domain, like `setBoundaryMode`.
+#[wasm_bindgen(js_name = "setBoundaryModeX")]
+pub fn set_boundary_mode_x(handle: AppHandle, mode: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::AxisBoundaryModeChanged(Axis::X, mode.parse()?))?;
+    Ok(())
+}
+
+/// Selects what happens at the bottom and top edges of the domain, like `setBoundaryMode`.
+#[wasm_bindgen(js_name = "setBoundaryModeY")]
+pub fn set_boundary_mode_y(handle: AppHandle, mode: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::AxisBoundaryModeChanged(Axis::Y, mode.parse()?))?;
+    Ok(())
+}
+
+/// Sets the base seed of [`reset_seeded`] and restarts its series of layouts.
+#[wasm_bindgen(js_name = "setSeed")]
+pub fn set_seed(handle: AppHandle, seed: u32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::SeedChanged(seed))
+}
+
+/// Resets the particles to the next layout of a reproducible series: the n-th call after
+/// [`set_seed`] always produces the same layout for the same seed, while consecutive calls
+/// produce different ones.
+#[wasm_bindgen(js_name = "resetSeeded")]
+pub fn reset_seeded(handle: AppHandle) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::SeededResetRequested)
+}
+
+/// Resets the particles to a random layout, without advancing the seeded series.
+#[wasm_bindgen(js_name = "resetRandom")]
+pub fn reset_random(handle: AppHandle) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::RandomResetRequested)
+}
+
+/// Enables or disables the collisions between particles, and with the static colliders, together
+/// with the binning pass they need. Disabling them speeds up effects whose particles do not
+/// interact. Enabled by default.
+#[wasm_bindgen(js_name = "setCollisionsEnabled")]
+pub fn set_collisions_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::CollisionsToggled(enabled))
+}
+
+/// Caps the number of neighbors each particle is tested against for collisions per step,
+/// which speeds up dense scenes with the sorted binning mode in particular. Particles in
+/// crowded cells may then miss collisions and pass through each other. `undefined` (the
+/// default) removes the cap.
+#[wasm_bindgen(js_name = "setMaxNeighborChecks")]
+pub fn set_max_neighbor_checks(handle: AppHandle, max_checks: Option<u32>) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::MaxNeighborChecksChanged(max_checks))
+}
+
+/// Selects how collisions between particles are resolved: `"combined"` (default, one pass) or
+/// `"split"`, which separates overlapping particles before exchanging their velocities in a second
+/// pass. The split scheme is more stable and symmetric in dense scenes, at the cost of the extra pass.
+/// Custom update shaders without a `collision_stage` uniform always use the combined scheme.
+#[wasm_bindgen(js_name = "setCollisionScheme")]
+pub fn set_collision_scheme(handle: AppHandle, scheme: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::CollisionSchemeChanged(scheme.parse()?))?;
+    Ok(())
+}
+
+/// Selects the order in which a simulation step applies forces, collisions and movement:
+/// `"collisions-first"` (default), `"gravity-first"` (most stable for dense, resting scenes)
+/// or `"split"` (most accurate for sparse and orbital scenes).
+#[wasm_bindgen(js_name = "setForceOrder")]
+pub fn set_force_order(handle: AppHandle, order: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ForceOrderChanged(order.parse()?))?;
+    Ok(())
+}
+
+/// Sets the fraction of the normal velocity particles keep when bouncing off a wall,
+/// from 0 (fully inelastic) to 1 (elastic, the default).
+#[wasm_bindgen(js_name = "setWallRestitution")]
+pub fn set_wall_restitution(handle: AppHandle, restitution: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::WallRestitutionChanged(restitution))
+}
+
+/// Sets the fraction of the velocity along the contact normal a particle keeps when colliding
+/// with another one, from 0 (fully inelastic, the default) to 1 (elastic).
+#[wasm_bindgen(js_name = "setParticleRestitution")]
+pub fn set_particle_restitution(handle: AppHandle, restitution: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ParticleRestitutionChanged(restitution))
+}
+
+/// Makes particles collide as if their radius was `scale` times the drawn one, 1 by default.
+/// The scale is capped so that 2.1 collision radii, the largest contact distance, still fit in
+/// the smaller side of a grid cell, as only neighboring cells are searched. A scale that is not
+/// positive, including NaN, resets it to 1.
+#[wasm_bindgen(js_name = "setCollisionRadiusScale")]
+pub fn set_collision_radius_scale(handle: AppHandle, scale: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::CollisionRadiusScaleChanged(scale))
+}
+
+/// Sets a force field of `width` by `height` cells stretched over the simulation domain, which
+/// adds the bilinearly interpolated acceleration at its position to every particle. `field` holds
+/// the x and y acceleration of every cell, row by row from the bottom of the domain.
+#[wasm_bindgen(js_name = "setForceField")]
+pub fn set_force_field(handle: AppHandle, field: &[f32], width: u32, height: u32) -> Result<(), JsError> {
+    check_force_field(field, width, height)?;
+    send_user_event(handle, AppEvent::ForceFieldChanged(field.to_vec(), width, height))?;
+    Ok(())
+}
+
+fn check_force_field(field: &[f32], width: u32, height: u32) -> Result<(), JsError> {
+    if width == 0 || height == 0 || field.len() != 2 * width as usize * height as usize {
+        return Err(JsError::new(&format!(
+            "expected {} floats for a {width}x{height} force field, got {}",
+            2 * width as usize * height as usize,
+            field.len(),
+        )));
+    }
+
+    Ok(())
+}
+
+/// Sets the factor the force field accelerations are multiplied by, 1 by default.
+#[wasm_bindgen(js_name = "setForceFieldStrength")]
+pub fn set_force_field_strength(handle: AppHandle, strength: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ForceFieldStrengthChanged(strength))
+}
+
+/// Limits the magnitude of the force a particle receives per step from gravity and the force
+/// field together, which keeps strong forces stable without shortening the time step. Negative
+/// limits are clamped to 0. Pass `undefined` to remove the limit (the default). Collisions are
+/// not limited.
+#[wasm_bindgen(js_name = "setMaxForce")]
+pub fn set_max_force(handle: AppHandle, max_force: Option<f32>) -> Result<(), JsError> {
+    if max_force.is_some_and(f32::is_nan) {
+        return Err(JsError::new("max_force must not be NaN"));
+    }
+
+    send_user_event(handle, AppEvent::MaxForceChanged(max_force))
+}
+
+/// Registers `callback` to be called with a message when the particle data is found to contain
+/// NaN or infinite values, which is checked on a sample about once a second. With `reset_on_error`
+/// the particles are also scattered again. Passing `undefined` disables the check.
+#[wasm_bindgen(js_name = "setErrorCallback")]
+pub fn set_error_callback(handle: AppHandle, callback: Option<Function>, reset_on_error: Option<bool>) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ErrorCallbackChanged(callback, reset_on_error.unwrap_or(false)))
+}
+
+/// Enables or disables the periodic collection of the bin occupancy histogram.
+#[wasm_bindgen(js_name = "setOccupancyStatsEnabled")]
+pub fn set_occupancy_stats_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::OccupancyStatsToggled(enabled))
+}
+
+/// Returns how many grid cells held 0, 1, ..., bin capacity particles at the last sample,
+/// or `undefined` if occupancy stats are disabled or have not been collected yet.
+#[wasm_bindgen(js_name = "occupancyHistogram")]
+pub fn occupancy_histogram(handle: AppHandle) -> Option<Vec<u32>> {
+    APP_STATS.with(|app_stats| {
+        app_stats.borrow()
+            .get(&handle)?
+            .borrow()
+            .occupancy_histogram
+            .clone()
+    })
+}
+
+/// Enables or disables measuring the change of total momentum and kinetic energy
+/// over every simulation step. This reads the particle data back twice per frame.
+#[wasm_bindgen(js_name = "setConservationCheckEnabled")]
+pub fn set_conservation_check_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ConservationCheckToggled(enabled))
+}
+
+/// Returns the momentum and kinetic energy drift of the last simulation step,
+/// or `undefined` if the conservation check is disabled or no step has run yet.
+#[wasm_bindgen(js_name = "conservationDrift")]
+pub fn conservation_drift(handle: AppHandle) -> Option<ConservationDrift> {
+    APP_STATS.with(|app_stats| {
+        app_stats.borrow()
+            .get(&handle)?
+            .borrow()
+            .conservation_drift
+    })
+}
+
+/// Runs `steps` simulation steps twice from the current state and resolves to a comparison of
+/// the particle data after both runs, which tells whether the simulation is deterministic on
+/// this device. Without `tolerance` only bitwise identical runs count as deterministic, with it
+/// those whose positions and velocities differ by at most `tolerance`. The simulation continues
+/// from where it was afterwards.
+#[wasm_bindgen(js_name = "measureDeterminism")]
+pub fn measure_determinism(handle: AppHandle, steps: u32, tolerance: Option<f32>) -> Promise {
+    Promise::new(&mut |resolve, reject| send_promise_event(handle, reject, AppEvent::DeterminismRequested(steps, tolerance, resolve)))
+}
+
+/// Enables or disables counting the particles that got lost, i.e. whose position became NaN or
+/// infinite or left a domain with walls, and those absorbed by the sink after every simulation
+/// step. This reads the particle data back once per frame.
+#[wasm_bindgen(js_name = "setLostParticleCheckEnabled")]
+pub fn set_lost_particle_check_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::LostParticleCheckToggled(enabled))
+}
+
+/// Returns the number of particles lost by the last simulation step, or `undefined` if the
+/// lost particle check is disabled or no step has run yet.
+#[wasm_bindgen(js_name = "lostParticleCount")]
+pub fn lost_particle_count(handle: AppHandle) -> Option<u32> {
+    APP_STATS.with(|app_stats| {
+        app_stats.borrow()
+            .get(&handle)?
+            .borrow()
+            .lost_particles
+    })
+}
+
+/// Returns the number of particles held by the sink after the last simulation step, or
+/// `undefined` if the lost particle check is disabled or no step has run yet.
+#[wasm_bindgen(js_name = "absorbedParticleCount")]
+pub fn absorbed_particle_count(handle: AppHandle) -> Option<u32> {
+    APP_STATS.with(|app_stats| {
+        app_stats.borrow()
+            .get(&handle)?
+            .borrow()
+            .absorbed_particles
+    })
+}
+
+/// Returns the number of particles that could be placed apart on startup or the last reset, see
+/// `setStartSpacing`, or `undefined` if the instance was started without a start spacing. The
+/// rest of the particles is parked outside of the domain.
+#[wasm_bindgen(js_name = "placedParticleCount")]
+pub fn placed_particle_count(handle: AppHandle) -> Option<u32> {
+    APP_STATS.with(|app_stats| {
+        app_stats.borrow()
+            .get(&handle)?
+            .borrow()
+            .placed_particles
+    })
+}
+
+/// Selects how particles are rasterized: `"points"` (default), `"quads"` or `"metaball"`.
+#[wasm_bindgen(js_name = "setRenderMode")]
+pub fn set_render_mode(handle: AppHandle, mode: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::RenderModeChanged(mode.parse()?))?;
+    Ok(())
+}
+
+/// Selects how particles are colored: `"solid"`, `"speed"`, `"density"`, `"index"`,
+/// `"spawn"`, the color they were spawned with (see `setSpawnColor`), or `"drag"`, their drag
+/// coefficient within the `drag_range` of `run`.
+#[wasm_bindgen(js_name = "setColorMode")]
+pub fn set_color_mode(handle: AppHandle, mode: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ColorModeChanged(mode.parse()?))?;
+    Ok(())
+}
+
+/// Colors the particles by their age on the palette, overriding the color mode: freshly spawned
+/// particles take its first entry and those `lifetime_ms` old or older, 5000 by default, its
+/// last. Particles of the initial distribution count as old.
+#[wasm_bindgen(js_name = "setAgeColorEnabled")]
+pub fn set_age_color_enabled(handle: AppHandle, enabled: bool, lifetime_ms: Option<f32>) -> Result<(), JsError> {
+    let lifetime_ms = enabled.then_some(lifetime_ms.unwrap_or(DEFAULT_AGE_COLOR_LIFETIME_MS));
+    send_user_event(handle, AppEvent::AgeColorChanged(lifetime_ms))
+}
+
+/// Selects how the brightness of a particle falls off from its center to its edge:
+/// `"flat"` (default), `"linear"`, `"gaussian"` or `"inverse-square"`.
+/// Soft falloffs look best with additive blending.
+#[wasm_bindgen(js_name = "setFalloffMode")]
+pub fn set_falloff_mode(handle: AppHandle, mode: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::FalloffModeChanged(mode.parse()?))?;
+    Ok(())
+}
+
+/// Replaces the palette the `"speed"`, `"density"`, `"index"` and `"drag"` color modes map their
+/// values onto, from low to high. `palette` holds RGBA entries of 4 bytes each, which are
+/// interpolated. Defaults to a blue-green-red rainbow.
+#[wasm_bindgen(js_name = "setPalette")]
+pub fn set_palette(handle: AppHandle, palette: &[u8]) -> Result<(), JsError> {
+    check_palette(palette)?;
+    send_user_event(handle, AppEvent::PaletteChanged(palette.to_vec()))?;
+    Ok(())
+}
+
+fn check_palette(palette: &[u8]) -> Result<(), JsError> {
+    if palette.is_empty() || !palette.len().is_multiple_of(4) {
+        return Err(JsError::new("the palette must hold at least one RGBA entry of 4 bytes"));
+    }
+
+    Ok(())
+}
+
+/// Moves the particles onto `target_positions`, which holds the x and y coordinates of one
+/// position per particle, over `duration_ms` while not paused. The physics is suspended for
+/// the particles until they arrive, after which they are at rest and simulated normally again.
+#[wasm_bindgen(js_name = "morphTo")]
+pub fn morph_to(handle: AppHandle, target_positions: &[f32], duration_ms: f64) -> Result<(), JsError> {
+    if target_positions.len() != 2 * PARTICLE_COUNT as usize {
+        return Err(JsError::new(&format!(
+            "expected {} target coordinates, got {}",
+            2 * PARTICLE_COUNT,
+            target_positions.len(),
+        )));
+    }
+
+    let targets = target_positions.chunks_exact(2)
+        .map(|target| Vec2::new(target[0], target[1]))
+        .collect();
+
+    send_user_event(handle, AppEvent::MorphRequested(targets, duration_ms))?;
+    Ok(())
+}
+
+fn check_morph_targets(targets: &[Vec2]) -> Result<(), JsError> {
+    if targets.len() != PARTICLE_COUNT as usize {
+        return Err(JsError::new(&format!("expected {PARTICLE_COUNT} morph targets, got {}", targets.len())));
+    }
+
+    Ok(())
+}
+
+/// Moves every particle onto the pixels of an RGBA image whose alpha is above `threshold`,
+/// at rest. The image is stretched over the whole simulation domain.
+#[wasm_bindgen(js_name = "setParticlesFromImage")]
+pub fn set_particles_from_image(handle: AppHandle, image_data: &[u8], width: u32, height: u32, threshold: u8) -> Result<(), JsError> {
+    let particles = particles_from_image(
+        image_data,
+        width,
+        height,
+        threshold,
+        PARTICLE_COUNT,
+        DOMAIN_MIN,
+        DOMAIN_MAX,
+    )?;
+
+    send_user_event(handle, AppEvent::ParticlesReplaced(particles))?;
+    Ok(())
+}
+
+/// Replaces the state of every particle with `data`, which holds the position and velocity of
+/// each of them as `4 * particle_count` interleaved x, y, vx and vy values.
+#[wasm_bindgen(js_name = "setParticles")]
+pub fn set_particles(handle: AppHandle, data: &[f32]) -> Result<(), JsError> {
+    if data.len() != 4 * PARTICLE_COUNT as usize {
+        return Err(JsError::new(&format!(
+            "expected {} particle values, got {}",
+            4 * PARTICLE_COUNT,
+            data.len(),
+        )));
+    }
+
+    send_user_event(handle, AppEvent::ParticlesLoaded(data.to_vec()))?;
+    Ok(())
+}
+
+/// Turns the `count` particles from index `start` on into static boundary particles, e.g. ones
+/// placed along the walls of a container with `setParticles`. They stay in place, are binned
+/// like the others and push back the particles colliding with them without giving way.
+/// A count of 0 turns them back into regular particles.
+#[wasm_bindgen(js_name = "setBoundaryParticles")]
+pub fn set_boundary_particles(handle: AppHandle, start: u32, count: u32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::BoundaryParticlesChanged(start, count))
+}
+
+/// Resolves to the positions of all particles as a `Float32Array` of `2 * particle_count`
+/// interleaved x and y coordinates, read back from the current particle data.
+#[wasm_bindgen(js_name = "readPositions")]
+pub fn read_positions(handle: AppHandle) -> Promise {
+    read_particle_field(handle, ParticleField::Position)
+}
+
+/// Resolves to the velocities of all particles, laid out like the result of `readPositions`.
+#[wasm_bindgen(js_name = "readVelocities")]
+pub fn read_velocities(handle: AppHandle) -> Promise {
+    read_particle_field(handle, ParticleField::Velocity)
+}
+
+/// Resolves to the largest speed of any particle, reduced on the GPU so that only a single value
+/// is read back. Absorbed, frozen and boundary particles are left out. The value is read back without
+/// stalling the GPU, so the promise resolves on one of the next frames.
+#[wasm_bindgen(js_name = "readMaxSpeed")]
+pub fn read_max_speed(handle: AppHandle) -> Promise {
+    Promise::new(&mut |resolve, reject| send_promise_event(handle, reject, AppEvent::MaxSpeedRequested(resolve)))
+}
+
+/// Enables rendering the particle indices into an offscreen buffer along with every drawn frame,
+/// which `pickParticle` needs. Disabled by default, since it costs an extra draw per frame.
+#[wasm_bindgen(js_name = "setPickingEnabled")]
+pub fn set_picking_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::PickingToggled(enabled))
+}
+
+/// Resolves to the index of the particle drawn at canvas pixel (`x`, `y`), counted from the
+/// top left corner, or to `undefined` if there is none or picking is disabled.
+#[wasm_bindgen(js_name = "pickParticle")]
+pub fn pick_particle(handle: AppHandle, x: u32, y: u32) -> Promise {
+    Promise::new(&mut |resolve, reject| send_promise_event(handle, reject, AppEvent::ParticlePickRequested(x, y, resolve)))
+}
+
+/// Enables the built-in key bindings on the focused canvas: space pauses, R resets the particles,
+/// the arrow keys nudge gravity and +/- change the time scale.
+#[wasm_bindgen(js_name = "setKeyboardControlsEnabled")]
+pub fn set_keyboard_controls_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::KeyboardControlsToggled(enabled))
+}
+
+/// Enables freezing the particles under a circular brush around the mouse cursor
+/// while the left mouse button is held. They are released when the button is.
+#[wasm_bindgen(js_name = "setFreezeBrushEnabled")]
+pub fn set_freeze_brush_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::FreezeBrushToggled(enabled))
+}
+
+/// Enables spawning a burst of particles with random velocities at the mouse cursor on every
+/// left click. As the particle count is fixed, each burst takes over existing particles in turn.
+#[wasm_bindgen(js_name = "setSpawnOnClickEnabled")]
+pub fn set_spawn_on_click_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::SpawnOnClickToggled(enabled))
+}
+
+/// Sets the number of particles spawned per click, 200 by default.
+#[wasm_bindgen(js_name = "setSpawnBurstCount")]
+pub fn set_spawn_burst_count(handle: AppHandle, count: u32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::SpawnBurstCountChanged(count))
+}
+
+/// Sets the color the particles of the next bursts are spawned with as 4 RGBA bytes, which the
+/// `"spawn"` color mode shows. Particles not spawned yet keep the solid red.
+#[wasm_bindgen(js_name = "setSpawnColor")]
+pub fn set_spawn_color(handle: AppHandle, color: &[u8]) -> Result<(), JsError> {
+    let color = color.try_into().map_err(|_| JsError::new("the spawn color must be 4 RGBA bytes"))?;
+    send_user_event(handle, AppEvent::SpawnColorChanged(color))?;
+    Ok(())
+}
+
+/// Softens the collisions of spawned particles for `soft_time_ms` milliseconds of simulation
+/// time, so that particles spawned into a crowd ease into it instead of being ejected. 0 (the
+/// default) disables it. Particles replaced with `setParticles` or `setParticlesFromImage` count
+/// as never spawned and collide at full stiffness.
+#[wasm_bindgen(js_name = "setSpawnSoftTime")]
+pub fn set_spawn_soft_time(handle: AppHandle, soft_time_ms: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::SpawnSoftTimeChanged(soft_time_ms))
+}
+
+/// Places a circular sink of `radius` around (`x`, `y`) in domain coordinates. Particles inside
+/// of it are absorbed with `probability` per simulation step (1 by default) and parked outside
+/// of the domain until a spawn burst or reset replaces them. A radius of 0 removes the sink.
+#[wasm_bindgen(js_name = "setSink")]
+pub fn set_sink(handle: AppHandle, x: f32, y: f32, radius: f32, probability: Option<f32>) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::SinkChanged(Vec2::new(x, y), radius, probability.unwrap_or(1.0)))
+}
+
+/// Places a platform as the line segment from (`x1`, `y1`) to (`x2`, `y2`) in domain coordinates,
+/// which particles rest on and bounce off like off the walls. Up to `MAX_PLATFORMS` (4) platforms
+/// can be placed at once, `index` (0 by default) selects the one to place or replace.
+#[wasm_bindgen(js_name = "setPlatform")]
+pub fn set_platform(handle: AppHandle, x1: f32, y1: f32, x2: f32, y2: f32, index: Option<usize>) -> Result<(), JsError> {
+    let index = platform_index(index)?;
+    send_user_event(handle, AppEvent::PlatformChanged(index, Some((Vec2::new(x1, y1), Vec2::new(x2, y2)))))?;
+    Ok(())
+}
+
+/// Removes platform `index`, 0 by default.
+#[wasm_bindgen(js_name = "removePlatform")]
+pub fn remove_platform(handle: AppHandle, index: Option<usize>) -> Result<(), JsError> {
+    let index = platform_index(index)?;
+    send_user_event(handle, AppEvent::PlatformChanged(index, None))?;
+    Ok(())
+}
+
+/// Lets the particles pass through all platforms while disabled, without removing them.
+/// Platforms are enabled by default.
+#[wasm_bindgen(js_name = "setPlatformsEnabled")]
+pub fn set_platforms_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::PlatformsToggled(enabled))
+}
+
+/// Adds a rectangular zone with its lower left corner at (`x`, `y`) and a size of `width` by
+/// `height` in domain coordinates, in which the gravity (`gravity_x`, `gravity_y`) applies
+/// instead of the global one, e.g. reversed or zero for floating pockets. Up to
+/// `MAX_GRAVITY_ZONES` (4) zones can be placed, further ones are ignored. Where zones overlap,
+/// the one added first applies.
+#[wasm_bindgen(js_name = "addGravityZone")]
+pub fn add_gravity_zone(handle: AppHandle, x: f32, y: f32, width: f32, height: f32, gravity_x: f32, gravity_y: f32) -> Result<(), JsError> {
+    let min = Vec2::new(x, y);
+    send_user_event(handle, AppEvent::GravityZoneAdded(min, min + Vec2::new(width, height), Vec2::new(gravity_x, gravity_y)))
+}
+
+/// Removes all gravity zones.
+#[wasm_bindgen(js_name = "clearGravityZones")]
+pub fn clear_gravity_zones(handle: AppHandle) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::GravityZonesCleared)
+}
+
+/// Threads the particles onto a track like beads on a wire: after every step, each particle is
+/// moved onto the closest point of the polyline through `points`, given as interleaved x and y
+/// coordinates, and keeps only the part of its velocity along it. Collisions then happen along
+/// the track too. Repeat the first point at the end for a closed curve, such as a sampled circle
+/// or Lissajous figure. Up to `MAX_TRACK_SEGMENTS + 1` (257) points are used, passing fewer than
+/// two removes the track.
+#[wasm_bindgen(js_name = "setTrack")]
+pub fn set_track(handle: AppHandle, points: &[f32]) -> Result<(), JsError> {
+    if !points.len().is_multiple_of(2) {
+        return Err(JsError::new("track points must hold an x and a y coordinate each"));
+    }
+
+    let points: Vec<Vec2> = points.chunks_exact(2)
+        .take(MAX_TRACK_SEGMENTS as usize + 1)
+        .map(|point| Vec2::new(point[0], point[1]))
+        .collect();
+
+    check_track(&points)?;
+    send_user_event(handle, AppEvent::TrackChanged(points))?;
+    Ok(())
+}
+
+fn check_track(points: &[Vec2]) -> Result<(), JsError> {
+    if points.iter().any(|point| !point.is_finite()) {
+        return Err(JsError::new("track points must be finite"));
+    }
+
+    Ok(())
+}
+
+/// Lets the particles move freely while disabled, which keeps the track set. Enabled by default.
+#[wasm_bindgen(js_name = "setTrackEnabled")]
+pub fn set_track_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::TrackToggled(enabled))
+}
+
+/// Keeps the particles inside of a shape within the domain, whose boundary acts as a wall. Outside
+/// of the shape the canvas stays transparent, so that the page shows through. `shape` is
+/// `"circle"` with `params` [center x, center y, radius], `"rounded-rect"` with [center x,
+/// center y, width, height, corner radius], or `"none"` without parameters to remove the
+/// container again.
+#[wasm_bindgen(js_name = "setContainer")]
+pub fn set_container(handle: AppHandle, shape: &str, params: &[f32]) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ContainerChanged(parse_container(shape, params)?))?;
+    Ok(())
+}
+
+fn parse_container(shape: &str, params: &[f32]) -> Result<Option<Container>, JsError> {
+    let container = match (shape, params) {
+        ("none", []) => None,
+        ("circle", &[x, y, radius]) => Some(Container::Circle { center: Vec2::new(x, y), radius }),
+        ("rounded-rect", &[x, y, width, height, corner_radius]) => Some(Container::RoundedRect {
+            center: Vec2::new(x, y),
+            half_size: Vec2::new(width, height) / 2.0,
+            corner_radius,
+        }),
+        ("none" | "circle" | "rounded-rect", _) =>
+            return Err(JsError::new(&format!("invalid parameters for a {shape} container"))),
+        _ => return Err(JsError::new(&format!("unknown container shape \"{shape}\""))),
+    };
+
+    if let Some(container) = &container {
+        check_container(container)?;
+    }
+
+    Ok(container)
+}
+
+fn check_container(container: &Container) -> Result<(), JsError> {
+    let valid = match *container {
+        Container::Circle { center, radius } => center.is_finite() && radius.is_finite() && radius > 0.0,
+        Container::RoundedRect { center, half_size, corner_radius } =>
+            center.is_finite() && half_size.is_finite() && corner_radius.is_finite()
+                && half_size.min_element() > 0.0 && corner_radius >= 0.0,
+    };
+
+    if !valid {
+        return Err(JsError::new("container parameters must be finite, with a positive size"));
+    }
+
+    Ok(())
+}
+
+fn platform_index(index: Option<usize>) -> Result<usize, JsError> {
+    let index = index.unwrap_or(0);
+
+    if index >= MAX_PLATFORMS {
+        return Err(JsError::new(&format!("platform index must be less than {MAX_PLATFORMS}")));
+    }
+
+    Ok(index)
+}
+
+/// Shows the simulation domain around (`x`, `y`) magnified by `zoom`. The domain is centered
+/// on the origin, so `setCamera(0, 0, 1)` (the default) shows all of it. With a positive
+/// `duration_ms`, the camera moves there over that time instead of snapping to it, along
+/// `easing`: `"linear"` or `"ease-in-out"` (default). Pinching the camera or the automatic camera
+/// cancels the move.
+#[wasm_bindgen(js_name = "setCamera")]
+pub fn set_camera(handle: AppHandle, x: f32, y: f32, zoom: f32, duration_ms: Option<f64>, easing: Option<String>) -> Result<(), JsError> {
+    let easing = easing.as_deref().map(str::parse).transpose()?.unwrap_or_default();
+    let duration_ms = duration_ms.unwrap_or(0.0);
+    check_camera_duration(duration_ms)?;
+    send_user_event(handle, AppEvent::CameraChanged(Vec2::new(x, y), zoom, duration_ms, easing))?;
+    Ok(())
+}
+
+fn check_camera_duration(duration_ms: f64) -> Result<(), JsError> {
+    if !duration_ms.is_finite() {
+        return Err(JsError::new("duration_ms must be finite"));
+    }
+
+    Ok(())
+}
+
+/// Keeps panning the camera towards the region of the domain holding the most particles, which
+/// is looked for twice a second. The camera covers about 63% of the way to it in `smoothing_ms`,
+/// 2000 by default. Another region is only followed once it clearly holds more particles.
+/// The zoom is left as set with `setCamera`.
+#[wasm_bindgen(js_name = "setAutoCamera")]
+pub fn set_auto_camera(handle: AppHandle, enabled: bool, smoothing_ms: Option<f32>) -> Result<(), JsError> {
+    let smoothing_ms = enabled.then_some(smoothing_ms.unwrap_or(DEFAULT_AUTO_CAMERA_SMOOTHING_MS));
+    send_user_event(handle, AppEvent::AutoCameraChanged(smoothing_ms))
+}
+
+/// Enables the touch controls: pinching with two fingers zooms and pans the camera,
+/// a single finger acts like the mouse with the left button held.
+#[wasm_bindgen(js_name = "setTouchControlsEnabled")]
+pub fn set_touch_controls_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::TouchControlsToggled(enabled))
+}
+
+/// Sets the radius of the freeze brush in canvas pixels. Defaults to 30.
+#[wasm_bindgen(js_name = "setFreezeBrushRadius")]
+pub fn set_freeze_brush_radius(handle: AppHandle, radius: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::FreezeBrushRadiusChanged(radius))
+}
+
+/// Selects the binning algorithm: `"layered"` (default, fills every bin slot before dropping
+/// particles), `"slotted"` (fewer draw calls, but may drop particles from cells that are not
+/// full) or `"sorted"` (sorts all particles by cell, so no cell has a capacity limit, at the
+/// cost of more passes).
+#[wasm_bindgen(js_name = "setBinningAlgorithm")]
+pub fn set_binning_algorithm(handle: AppHandle, algorithm: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::BinningModeChanged(algorithm.parse()?))?;
+    Ok(())
+}
+
+/// Selects when the binning pass clears its intermediate texture: `"per-layer"` (default),
+/// `"once"` per frame or `"never"`. Anything but the default leaves stale particle ids in the
+/// bins, which breaks collisions, and is only meant for measuring the cost of the clears.
+/// Debug builds check that the bins are consistent whenever occupancy stats are collected.
+#[wasm_bindgen(js_name = "setBinClearMode")]
+pub fn set_bin_clear_mode(handle: AppHandle, mode: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::BinClearModeChanged(mode.parse()?))?;
+    Ok(())
+}
+
+/// Anti-aliases the rendered frame with `samples` samples per pixel, clamped to the device maximum.
+/// 0 or 1 (the default) renders directly onto the canvas.
+#[wasm_bindgen(js_name = "setMsaaSamples")]
+pub fn set_msaa_samples(handle: AppHandle, samples: u32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::MsaaSamplesChanged(samples))
+}
+
+/// Draws an outline around the simulation domain.
+#[wasm_bindgen(js_name = "setShowBounds")]
+pub fn set_show_bounds(handle: AppHandle, show_bounds: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ShowBoundsToggled(show_bounds))
+}
+
+/// Replaces the fragment shader of the update pass by `source`, which is compiled at runtime to
+/// experiment with custom physics. It runs once per particle with the same uniforms and textures
+/// as the built-in `update.frag` and writes the new position and velocity to `out_particle`.
+/// Resolves once the shader is in use, or rejects with the compiler or linker log, in which case
+/// the current shader is kept.
+#[wasm_bindgen(js_name = "setUpdateShaderSource")]
+pub fn set_update_shader_source(handle: AppHandle, source: String) -> Promise {
+    Promise::new(&mut |resolve, reject| {
+        send_promise_event(handle, reject.clone(), AppEvent::UpdateShaderSourceChanged(source.clone(), resolve, reject))
+    })
+}
+
+/// Switches back to the built-in update shader.
+#[wasm_bindgen(js_name = "resetUpdateShader")]
+pub fn reset_update_shader(handle: AppHandle) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::UpdateShaderReset)
+}
+
+/// Leaves the background of the canvas transparent instead of black, so that it can be laid over
+/// page content as a decorative overlay. The canvas holds premultiplied colors, which the browser
+/// composites over the page: additive particles brighten it, alpha-blended ones cover it by
+/// their opacity. A container is not filled in either.
+#[wasm_bindgen(js_name = "setTransparentBackground")]
+pub fn set_transparent_background(handle: AppHandle, transparent: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::TransparentBackgroundToggled(transparent))
+}
+
+/// Shows the frame rate, the particle count and the main simulation parameters as text in the
+/// top left corner of the canvas. Unlike a DOM overlay, it is part of captured frames.
+#[wasm_bindgen(js_name = "setShowHud")]
+pub fn set_show_hud(handle: AppHandle, show_hud: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ShowHudToggled(show_hud))
+}
+
+/// Chooses how the domain is fitted into a canvas of a different aspect ratio: `"stretch"`
+/// (default) distorts it to cover the canvas, `"fit"` shows all of it with black bars and
+/// `"fill"` covers the canvas, cropping the domain.
+#[wasm_bindgen(js_name = "setViewportMode")]
+pub fn set_viewport_mode(handle: AppHandle, mode: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::ViewportModeChanged(mode.parse()?))?;
+    Ok(())
+}
+
+/// Draws mirror images of the particles on top of each other for kaleidoscope effects:
+/// `"none"` (default), `"horizontal"` mirrors them left to right, `"quad"` at both axes and
+/// `"hex"` six times around the center, like three mirrors at 60 degrees. The particles are
+/// simulated once, only drawing gets more expensive. Particles can be picked in their mirror
+/// images as well.
+#[wasm_bindgen(js_name = "setMirrorMode")]
+pub fn set_mirror_mode(handle: AppHandle, mode: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::MirrorModeChanged(mode.parse()?))?;
+    Ok(())
+}
+
+/// Replaces the particles with a raw simulation texture stretched over the canvas:
+/// `"positions"`, `"velocities"` or `"bins"`. `"none"` (default) draws the particles again.
+#[wasm_bindgen(js_name = "setDebugView")]
+pub fn set_debug_view(handle: AppHandle, view: &str) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::DebugViewChanged(view.parse()?))?;
+    Ok(())
+}
+
+/// Gives every particle a fixed random depth that scales its size and brightness, for a
+/// pseudo-3D look. Works best with additive blending, which does not need the particles sorted.
+/// Applies to the `"points"` and `"quads"` render modes.
+#[wasm_bindgen(js_name = "setDepthEnabled")]
+pub fn set_depth_enabled(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::DepthToggled(enabled))
+}
+
+/// Lets nearer particles hide the farther ones behind them, going by the depths of the depth
+/// effect, instead of blending with them. Particles are drawn opaquely regardless of the blend
+/// mode while it is enabled. Applies to the `"points"` and `"quads"` render modes.
+#[wasm_bindgen(js_name = "setDepthTest")]
+pub fn set_depth_test(handle: AppHandle, enabled: bool) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::DepthTestToggled(enabled))
+}
+
+/// Sets the size and brightness factors of the nearest (`near`) and farthest (`far`) particles
+/// of the depth effect. Defaults to 1 and 0.3.
+#[wasm_bindgen(js_name = "setDepthRange")]
+pub fn set_depth_range(handle: AppHandle, near: f32, far: f32) -> Result<(), JsError> {
+    send_user_event(handle, AppEvent::DepthRangeChanged(near, far))
+}
+
+/// Starts recording the events sent to the instance, such as resizes and parameter changes,
+/// discarding any recording in progress. Error callbacks and replaced particles are not recorded.
+#[wasm_bindgen(js_name = "startRecording")]
+pub fn start_recording(handle: AppHandle) {
+    let recording = Recording {
+        start: now(),
+        events: Vec::new(),
+    };
+
+    APP_RECORDINGS.with(|app_recordings| app_recordings.borrow_mut().insert(handle, recording));
+}
+
+/// Stops recording and returns the recorded events as a JSON log that can be passed to `replay`.
+#[wasm_bindgen(js_name = "stopRecording")]
+pub fn stop_recording(handle: AppHandle) -> Result<String, JsError> {
+    APP_RECORDINGS.with(|app_recordings| {
+        let mut app_recordings = app_recordings.borrow_mut();
+        let recording = app_recordings.get(&handle)
+            .ok_or_else(|| JsError::new("no recording in progress"))?;
+
+        // Serialized first, so that the recording is kept if that fails.
+        let log = serde_json::to_string(&recording.events)?;
+        app_recordings.remove(&handle);
+
+        Ok(log)
+    })
+}
+
+/// Captures the next `frame_count` frames, e.g. to encode them into a video. Every captured frame
+/// advances the simulation by exactly `frame_duration_ms` (1000 / 60 by default) instead of the
+/// real time since the previous one, and is drawn even if `setDrawEvery` would skip it.
+/// `on_frame` is called with the frame index and its pixels at the canvas resolution, as a
+/// `Uint8ClampedArray` of RGBA rows from the top that `new ImageData(pixels, canvas.width)`
+/// accepts. `on_capture_complete` is called after the last frame. Replaces any capture in progress.
+#[wasm_bindgen(js_name = "beginCapture")]
+pub fn begin_capture(handle: AppHandle, frame_count: u32, on_frame: Function, on_capture_complete: Function, frame_duration_ms: Option<f64>) -> Result<(), JsError> {
+    let frame_duration_ms = frame_duration_ms.unwrap_or(1000.0 / 60.0);
+
+    if !(frame_duration_ms > 0.0 && frame_duration_ms.is_finite()) {
+        return Err(JsError::new("frame_duration_ms must be positive and finite"));
+    }
+
+    let capture = Capture {
+        frame_count,
+        captured: 0,
+        frame_duration_ms,
+        on_frame,
+        on_capture_complete,
+    };
+
+    send_app_message(handle, AppMessage::CaptureRequested(handle, capture))
+}
+
+/// Publishes the particle positions every `interval_ms` milliseconds, so that another browsing
+/// context or a worker can render or analyze them. `on_positions` is called with an `ArrayBuffer`
+/// of interleaved 32-bit float x and y coordinates of every `stride`-th particle (every one by
+/// default), which it can hand on to `postMessage` or a `BroadcastChannel` as a transferable.
+/// Replaces any streaming in progress.
+#[wasm_bindgen(js_name = "enableStreaming")]
+pub fn enable_streaming(handle: AppHandle, interval_ms: f64, on_positions: Function, stride: Option<u32>) -> Result<(), JsError> {
+    if !(interval_ms >= 0.0 && interval_ms.is_finite()) {
+        return Err(JsError::new("interval_ms must be non-negative and finite"));
+    }
+
+    let streaming = Streaming {
+        interval_ms,
+        stride: stride.unwrap_or(1).max(1),
+        elapsed_ms: 0.0,
+        on_positions,
+    };
+
+    send_streaming(handle, Some(streaming))
+}
+
+/// Stops publishing the particle positions.
+#[wasm_bindgen(js_name = "disableStreaming")]
+pub fn disable_streaming(handle: AppHandle) -> Result<(), JsError> {
+    send_streaming(handle, None)
+}
+
+fn send_streaming(handle: AppHandle, streaming: Option<Streaming>) -> Result<(), JsError> {
+    send_app_message(handle, AppMessage::StreamingChanged(handle, streaming))
+}
+
+/// Sends the events of a log returned by `stopRecording` to the instance again,
+/// each delayed by its recorded time relative to this call. Replaces any replay in progress.
+#[wasm_bindgen]
+pub fn replay(handle: AppHandle, log: &str) -> Result<(), JsError> {
+    let events: Vec<RecordedEvent> = serde_json::from_str(log)?;
+
+    for recorded in &events {
+        recorded.event.validate()?;
+    }
+
+    send_app_message(handle, AppMessage::ReplayRequested(handle, events))
+}
+
+/// Resolves once the GPU has finished all the work issued for the instance so far, e.g. before
+/// reading pixels of the canvas from JS. Read-backs done by the instance itself, like
+/// `readPositions`, already wait for the GPU on their own.
+#[wasm_bindgen]
+pub fn sync(handle: AppHandle) -> Promise {
+    Promise::new(&mut |resolve, reject| send_promise_event(handle, reject, AppEvent::SyncRequested(resolve)))
+}
+
+/// Resolves with an array describing every parameter that can be changed with [`set_tunable`],
+/// as `{ name, min, max, value }` objects, so that JS can build a control panel automatically.
+#[wasm_bindgen(js_name = "getTunables")]
+pub fn get_tunables(handle: AppHandle) -> Promise {
+    Promise::new(&mut |resolve, reject| send_promise_event(handle, reject, AppEvent::TunablesRequested(resolve)))
+}
+
+/// Sets the parameter called `name`, as listed by [`get_tunables`], clamping `value` to its range.
+#[wasm_bindgen(js_name = "setTunable")]
+pub fn set_tunable(handle: AppHandle, name: &str, value: f32) -> Result<(), JsError> {
+    check_tunable(name)?;
+    send_user_event(handle, AppEvent::TunableChanged(name.to_owned(), value))?;
+    Ok(())
+}
+
+fn check_tunable(name: &str) -> Result<(), JsError> {
+    if tunable(name).is_none() {
+        return Err(JsError::new(&format!("unknown tunable \"{name}\"")));
+    }
+
+    Ok(())
+}
+
+fn update_graphics_options(update: impl FnOnce(&mut GraphicsOptions)) {
+    GRAPHICS_OPTIONS.with(|options| update(&mut options.borrow_mut()));
+}
+
+fn read_particle_field(handle: AppHandle, field: ParticleField) -> Promise {
+    Promise::new(&mut |resolve, reject| send_promise_event(handle, reject, AppEvent::ParticleFieldRequested(field, resolve)))
+}
+
+fn send_user_event(handle: AppHandle, event: AppEvent) -> Result<(), JsError> {
+    if event.is_recordable() {
+        APP_RECORDINGS.with(|app_recordings| {
+            if let Some(recording) = app_recordings.borrow_mut().get_mut(&handle) {
+                recording.events.push(RecordedEvent {
+                    time: now() - recording.start,
+                    event: event.clone(),
+                });
+            }
+        });
+    }
+
+    send_app_message(handle, AppMessage::UserEvent(handle, event))
+}
+
+/// Sends `event` to the instance, rejecting the promise it belongs to with the error if that fails.
+fn send_promise_event(handle: AppHandle, reject: Function, event: AppEvent) {
+    if let Err(err) = send_user_event(handle, event) {
+        if let Err(err) = reject.call1(&JsValue::NULL, &err.into()) {
+            warn!("Could not reject promise: {:?}", err);
+        }
+    }
+}
+
+/// Sends `message` to the event loop on behalf of the instance, failing if the handle does not
+/// belong to a running instance.
+fn send_app_message(handle: AppHandle, message: AppMessage) -> Result<(), JsError> {
+    APP_INSTANCES.with(|app_instances| {
+        app_instances
+            .borrow()
+            .get(&handle)
+            .ok_or_else(|| JsError::new("unknown application handle"))?
+            .send_event(message)
+            .map_err(|_| JsError::new("the event loop has been terminated"))
+    })
+}
+
+fn spawn_event_loop() -> EventLoopProxy<AppMessage> {
+    let event_loop = EventLoopBuilder::with_user_event().build();
+    let proxy = event_loop.create_proxy();
+
+    let mut apps = Apps::new();
+    event_loop.spawn(move |event, target, control_flow| apps.handle_event(event, target, control_flow));
+
+    proxy
+}
+
+fn now() -> f64 {
+    window().unwrap().performance().unwrap().now()
 }
 
 #[derive(Debug)]
+enum AppMessage {
+    CreateRequested {
+        handle: AppHandle,
+        canvas: HtmlCanvasElement,
+        size: LogicalSize<u32>,
+        options: GraphicsOptions,
+        /// Called once the instance has been created, or with the error that prevented it.
+        resolve: Function,
+        reject: Function,
+    },
+    UserEvent(AppHandle, AppEvent),
+    ReplayRequested(AppHandle, Vec<RecordedEvent>),
+    CaptureRequested(AppHandle, Capture),
+    StreamingChanged(AppHandle, Option<Streaming>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 enum AppEvent {
-    ResizeRequested(LogicalSize<u32>)
+    ResizeRequested {
+        width: u32,
+        height: u32,
+    },
+    DrawEveryChanged(u32),
+    DrawStrideChanged(u32),
+    BlendModeChanged(BlendMode, f32),
+    ColorSpaceChanged(ColorSpace),
+    RenderModeChanged(RenderMode),
+    ColorModeChanged(ColorMode),
+    AgeColorChanged(Option<f32>),
+    FalloffModeChanged(FalloffMode),
+    PaletteChanged(Vec<u8>),
+    PauseRequested(bool),
+    PauseOnBlurToggled(bool),
+    SeedChanged(u32),
+    SeededResetRequested,
+    RandomResetRequested,
+    ImpulseRequested(ImpulseMode, Vec2),
+    VelocitiesScaled(f32),
+    RegionPushed(Vec2, Vec2, Vec2),
+    RemoveDriftToggled(bool),
+    GravityTilted(f32, f32, Option<f32>),
+    GravityRotationChanged(f32, f32),
+    BoundaryModeChanged(BoundaryMode),
+    AxisBoundaryModeChanged(Axis, BoundaryMode),
+    WallRestitutionChanged(f32),
+    ParticleRestitutionChanged(f32),
+    CollisionRadiusScaleChanged(f32),
+    MaxForceChanged(Option<f32>),
+    ForceFieldChanged(Vec<f32>, u32, u32),
+    ForceFieldStrengthChanged(f32),
+    CollisionsToggled(bool),
+    MaxNeighborChecksChanged(Option<u32>),
+    CollisionSchemeChanged(CollisionScheme),
+    ForceOrderChanged(ForceOrder),
+    OccupancyStatsToggled(bool),
+    ConservationCheckToggled(bool),
+    LostParticleCheckToggled(bool),
+    #[serde(skip)]
+    ErrorCallbackChanged(Option<Function>, bool),
+    ShowBoundsToggled(bool),
+    ShowHudToggled(bool),
+    TransparentBackgroundToggled(bool),
+    DebugViewChanged(DebugView),
+    ViewportModeChanged(ViewportMode),
+    MirrorModeChanged(MirrorMode),
+    DepthToggled(bool),
+    DepthTestToggled(bool),
+    DepthRangeChanged(f32, f32),
+    MsaaSamplesChanged(u32),
+    BinningModeChanged(BinningMode),
+    BinClearModeChanged(BinClearMode),
+    KeyboardControlsToggled(bool),
+    FreezeBrushToggled(bool),
+    FreezeBrushRadiusChanged(f32),
+    SpawnOnClickToggled(bool),
+    SpawnBurstCountChanged(u32),
+    SpawnColorChanged([u8; 4]),
+    SpawnSoftTimeChanged(f32),
+    SinkChanged(Vec2, f32, f32),
+    PlatformChanged(usize, Option<(Vec2, Vec2)>),
+    PlatformsToggled(bool),
+    GravityZoneAdded(Vec2, Vec2, Vec2),
+    GravityZonesCleared,
+    ContainerChanged(Option<Container>),
+    TrackChanged(Vec<Vec2>),
+    TrackToggled(bool),
+    CameraChanged(Vec2, f32, f64, Easing),
+    AutoCameraChanged(Option<f32>),
+    TouchControlsToggled(bool),
+    MorphRequested(Vec<Vec2>, f64),
+    #[serde(skip)]
+    ParticlesReplaced(Vec<Particle>),
+    #[serde(skip)]
+    ParticlesLoaded(Vec<f32>),
+    BoundaryParticlesChanged(u32, u32),
+    #[serde(skip)]
+    ParticleFieldRequested(ParticleField, Function),
+    #[serde(skip)]
+    MaxSpeedRequested(Function),
+    PickingToggled(bool),
+    #[serde(skip)]
+    ParticlePickRequested(u32, u32, Function),
+    #[serde(skip)]
+    DeterminismRequested(u32, Option<f32>, Function),
+    #[serde(skip)]
+    SyncRequested(Function),
+    TunableChanged(String, f32),
+    #[serde(skip)]
+    TunablesRequested(Function),
+    #[serde(skip)]
+    UpdateShaderSourceChanged(String, Function, Function),
+    UpdateShaderReset,
+}
+
+impl AppEvent {
+    /// Runs the checks of the setter sending the event on its arguments, for events that do not
+    /// come from the setters, like those of a replayed log.
+    fn validate(&self) -> Result<(), JsError> {
+        match self {
+            AppEvent::PaletteChanged(palette) => check_palette(palette),
+            AppEvent::ForceFieldChanged(field, width, height) => check_force_field(field, *width, *height),
+            AppEvent::MorphRequested(targets, _) => check_morph_targets(targets),
+            AppEvent::PlatformChanged(index, _) => platform_index(Some(*index)).map(|_| ()),
+            AppEvent::TrackChanged(points) => check_track(points),
+            AppEvent::CameraChanged(_, _, duration_ms, _) => check_camera_duration(*duration_ms),
+            AppEvent::ContainerChanged(Some(container)) => check_container(container),
+            AppEvent::TunableChanged(name, _) => check_tunable(name),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_recordable(&self) -> bool {
+        !matches!(
+            self,
+            AppEvent::ErrorCallbackChanged(..)
+                | AppEvent::ParticlesReplaced(_)
+                | AppEvent::ParticlesLoaded(_)
+                | AppEvent::ParticleFieldRequested(..)
+                | AppEvent::MaxSpeedRequested(_)
+                | AppEvent::ParticlePickRequested(..)
+                | AppEvent::DeterminismRequested(..)
+                | AppEvent::SyncRequested(_)
+                | AppEvent::TunablesRequested(_)
+                | AppEvent::UpdateShaderSourceChanged(..)
+        )
+    }
+}
+
+/// An event together with the time it was sent, in milliseconds since the recording started.
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedEvent {
+    time: f64,
+    event: AppEvent,
+}
+
+struct Recording {
+    start: f64,
+    events: Vec<RecordedEvent>,
 }
 
-struct Context {
-    event_loop: EventLoop<AppEvent>,
+/// Recorded events waiting to be sent to an instance, ordered by time.
+struct Replay {
+    start: f64,
+    events: Vec<RecordedEvent>,
 }
 
-impl Context {
+/// Frames being captured, see [`begin_capture`].
+#[derive(Debug)]
+struct Capture {
+    frame_count: u32,
+    captured: u32,
+    frame_duration_ms: f64,
+    on_frame: Function,
+    on_capture_complete: Function,
+}
+
+/// Positions published periodically, see [`enable_streaming`].
+#[derive(Debug)]
+struct Streaming {
+    interval_ms: f64,
+    stride: u32,
+    elapsed_ms: f64,
+    on_positions: Function,
+}
+
+/// All instances driven by the shared event loop.
+struct Apps {
+    apps: HashMap<AppHandle, App>,
+    performance: Performance,
+}
+
+impl Apps {
     pub fn new() -> Self {
-        Context {
-            event_loop: EventLoopBuilder::with_user_event().build()
+        Apps {
+            apps: HashMap::new(),
+            performance: window().unwrap().performance().unwrap(),
+        }
+    }
+
+    fn handle_event(&mut self, event: Event<AppMessage>, target: &EventLoopWindowTarget<AppMessage>, control_flow: &mut ControlFlow) {
+        control_flow.set_poll();
+
+        match event {
+            Event::UserEvent(AppMessage::CreateRequested { handle, canvas, size, options, resolve, reject }) => {
+                let result = match self.create_app(target, handle, canvas, size, &options) {
+                    Ok(()) => resolve.call0(&JsValue::NULL),
+                    Err(err) => reject.call1(&JsValue::NULL, &JsError::new(&err.to_string()).into()),
+                };
+
+                if let Err(err) = result {
+                    warn!("Could not report the creation of instance {handle}: {:?}", err);
+                }
+            }
+            Event::UserEvent(AppMessage::UserEvent(handle, event)) => {
+                if let Some(app) = self.apps.get(&handle) {
+                    app.handle_user_event(event)
+                }
+            }
+            Event::UserEvent(AppMessage::ReplayRequested(handle, events)) => {
+                if let Some(app) = self.apps.get_mut(&handle) {
+                    app.replay = Some(Replay {
+                        start: self.performance.now(),
+                        events,
+                    });
+                }
+            }
+            Event::UserEvent(AppMessage::CaptureRequested(handle, capture)) => {
+                if let Some(app) = self.apps.get_mut(&handle) {
+                    app.begin_capture(capture);
+                }
+            }
+            Event::UserEvent(AppMessage::StreamingChanged(handle, streaming)) => {
+                if let Some(app) = self.apps.get_mut(&handle) {
+                    app.streaming = streaming;
+                }
+            }
+            Event::WindowEvent {
+                window_id,
+                event,
+            } => {
+                let Some((&handle, app)) = self.apps.iter_mut().find(|(_, app)| app.window.id() == window_id) else {
+                    return;
+                };
+
+                // Frames may have stopped while the page was in the background, which would
+                // otherwise show up as a single long time step.
+                if matches!(event, WindowEvent::Focused(true)) {
+                    app.last_frame_time = self.performance.now();
+                }
+
+                if !app.graphics.event(&event) && matches!(event, WindowEvent::CloseRequested) {
+                    self.remove_app(handle)
+                }
+            }
+            Event::RedrawRequested(window_id) => {
+                let cur_frame_time = self.performance.now();
+
+                if let Some(app) = self.apps.values_mut().find(|app| app.window.id() == window_id) {
+                    let delta_time = cur_frame_time - app.last_frame_time;
+                    app.last_frame_time = cur_frame_time;
+
+                    debug!("FPS (instantaneous): {}", 1000.0 / delta_time);
+
+                    app.advance_replay(cur_frame_time);
+                    app.frame(delta_time)
+                }
+            }
+            Event::MainEventsCleared => {
+                for app in self.apps.values() {
+                    app.window.request_redraw()
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn create_app(&mut self, target: &EventLoopWindowTarget<AppMessage>, handle: AppHandle, canvas: HtmlCanvasElement, size: LogicalSize<u32>, options: &GraphicsOptions) -> Result<(), CreateError> {
+        let in_use = self.apps.iter()
+            .find(|(_, app)| app.window.canvas() == canvas)
+            .map(|(&other, _)| other);
+
+        let result = match in_use {
+            Some(other) => Err(CreateError::CanvasInUse(other)),
+            None => App::new(target, canvas, size, options, self.performance.now()),
+        };
+
+        match result {
+            Ok(app) => {
+                APP_STATS.with(|app_stats| app_stats.borrow_mut().insert(handle, app.graphics.stats()));
+                self.apps.insert(handle, app);
+                Ok(())
+            }
+            Err(err) => {
+                error!("could not create application: {err}");
+                self.remove_app(handle);
+                Err(err)
+            }
         }
     }
+
+    fn remove_app(&mut self, handle: AppHandle) {
+        self.apps.remove(&handle);
+        APP_INSTANCES.with(|app_instances| app_instances.borrow_mut().remove(&handle));
+        APP_STATS.with(|app_stats| app_stats.borrow_mut().remove(&handle));
+        APP_RECORDINGS.with(|app_recordings| app_recordings.borrow_mut().remove(&handle));
+    }
+}
+
+#[derive(Debug, Error)]
+enum CreateError {
+    #[error("the canvas is not attached to the document")]
+    CanvasDetached,
+    #[error("the canvas is already used by instance {0}, stop it or use another canvas")]
+    CanvasInUse(AppHandle),
+    #[error("could not get a WebGL2 context from the canvas; it may already have a different kind of context")]
+    NoWebGl2Context,
+    #[error("the textures need {required_bytes} bytes, more than the limit of {max_bytes}")]
+    MemoryLimit { required_bytes: u64, max_bytes: f64 },
+    #[error("could not create the window: {0}")]
+    Window(#[from] OsError),
 }
 
 struct App {
     graphics: Graphics,
     window: Window,
+    last_frame_time: f64,
+    replay: Option<Replay>,
+    capture: Option<Capture>,
+    streaming: Option<Streaming>,
 }
 
 impl App {
-    pub fn new(context: &Context, canvas: HtmlCanvasElement, size: LogicalSize<u32>) -> anyhow::Result<App> {
-        let window = App::create_window(&context.event_loop, canvas, size)?;
+    pub fn new(target: &EventLoopWindowTarget<AppMessage>, canvas: HtmlCanvasElement, size: LogicalSize<u32>, options: &GraphicsOptions, now: f64) -> Result<App, CreateError> {
+        // Requesting the context here returns the same one the renderer gets later on, but
+        // turns a canvas holding another kind of context into an error instead of a panic.
+        // The attributes are the defaults, spelled out since the transparent background and
+        // containers rely on them to composite over the page.
+        let context_options = Object::new();
+        Reflect::set(&context_options, &"alpha".into(), &true.into()).unwrap();
+        Reflect::set(&context_options, &"premultipliedAlpha".into(), &true.into()).unwrap();
+
+        if !matches!(canvas.get_context_with_context_options("webgl2", &context_options), Ok(Some(_))) {
+            return Err(CreateError::NoWebGl2Context);
+        }
+
+        let window = App::create_window(target, canvas, size)?;
 
         Ok(App {
-            graphics: Graphics::initialize_with_window(&window),
+            graphics: Graphics::initialize_with_window(&window, options),
             window,
+            last_frame_time: now,
+            replay: None,
+            capture: None,
+            streaming: None,
         })
     }
 
-    pub fn run(self, context: Context) -> ! {
-        let performance = window().unwrap().performance().unwrap();
-        let mut last_frame_time = performance.now();
+    fn handle_user_event(&self, event: AppEvent) {
+        // Converts from the API units of the events to simulation units, see `setApiUnitScale`.
+        let api_unit_scale = self.graphics.api_unit_scale();
 
-        context.event_loop.run(move |event, _, control_flow| {
-            control_flow.set_poll();
+        match event {
+            AppEvent::ResizeRequested { width, height } => self.window.set_inner_size(LogicalSize::new(width, height)),
+            AppEvent::DrawEveryChanged(draw_every) => self.graphics.set_draw_every(draw_every),
+            AppEvent::DrawStrideChanged(draw_stride) => self.graphics.set_draw_stride(draw_stride),
+            AppEvent::BlendModeChanged(blend_mode, opacity) => self.graphics.set_blend_mode(blend_mode, opacity),
+            AppEvent::ColorSpaceChanged(color_space) => self.graphics.set_color_space(color_space),
+            AppEvent::RenderModeChanged(render_mode) => self.graphics.set_render_mode(render_mode),
+            AppEvent::ColorModeChanged(color_mode) => self.graphics.set_color_mode(color_mode),
+            AppEvent::AgeColorChanged(lifetime_ms) => self.graphics.set_age_color(lifetime_ms),
+            AppEvent::FalloffModeChanged(falloff_mode) => self.graphics.set_falloff_mode(falloff_mode),
+            AppEvent::PaletteChanged(palette) => self.graphics.set_palette(&palette),
+            AppEvent::PauseRequested(paused) => self.graphics.set_paused(paused),
+            AppEvent::PauseOnBlurToggled(enabled) => self.graphics.set_pause_on_blur(enabled),
+            AppEvent::ImpulseRequested(mode, strength) => self.graphics.apply_impulse(mode, strength / api_unit_scale),
+            AppEvent::SeedChanged(seed) => self.graphics.set_seed(seed as u64),
+            AppEvent::SeededResetRequested => self.graphics.reset_seeded(),
+            AppEvent::RandomResetRequested => self.graphics.reset(),
+            AppEvent::MorphRequested(mut targets, duration_ms) => {
+                targets.iter_mut().for_each(|target| *target /= api_unit_scale);
+                self.graphics.morph_to(&targets, duration_ms);
+            }
+            AppEvent::VelocitiesScaled(factor) => self.graphics.scale_velocities(factor),
+            AppEvent::RegionPushed(min, max, velocity) =>
+                self.graphics.push_region(min / api_unit_scale, max / api_unit_scale, velocity / api_unit_scale),
+            AppEvent::RemoveDriftToggled(remove_drift) => self.graphics.set_remove_drift(remove_drift),
+            AppEvent::GravityTilted(beta, gamma, max_magnitude) => {
+                // The default gravity is in simulation units already.
+                let max_magnitude = max_magnitude.map_or(GRAVITY.length(), |magnitude| magnitude / api_unit_scale);
+                self.graphics.set_gravity(gravity_from_orientation(beta, gamma, max_magnitude));
+            }
+            AppEvent::GravityRotationChanged(degrees_per_second, magnitude) =>
+                self.graphics.set_gravity_rotation(degrees_per_second, magnitude / api_unit_scale),
+            AppEvent::BoundaryModeChanged(boundary_mode) => self.graphics.set_boundary_mode(boundary_mode),
+            AppEvent::AxisBoundaryModeChanged(axis, boundary_mode) => self.graphics.set_axis_boundary_mode(axis, boundary_mode),
+            AppEvent::WallRestitutionChanged(restitution) => self.graphics.set_wall_restitution(restitution),
+            AppEvent::ParticleRestitutionChanged(restitution) => self.graphics.set_particle_restitution(restitution),
+            AppEvent::CollisionRadiusScaleChanged(scale) => self.graphics.set_collision_radius_scale(scale),
+            AppEvent::MaxForceChanged(max_force) =>
+                self.graphics.set_max_force(max_force.map(|max_force| max_force / api_unit_scale)),
+            AppEvent::ForceFieldChanged(mut field, width, height) => {
+                field.iter_mut().for_each(|acceleration| *acceleration /= api_unit_scale);
+                self.graphics.set_force_field(&field, width, height);
+            }
+            AppEvent::ForceFieldStrengthChanged(strength) => self.graphics.set_force_field_strength(strength),
+            AppEvent::CollisionsToggled(enabled) => self.graphics.set_collisions_enabled(enabled),
+            AppEvent::MaxNeighborChecksChanged(max_checks) => self.graphics.set_max_neighbor_checks(max_checks),
+            AppEvent::CollisionSchemeChanged(scheme) => self.graphics.set_collision_scheme(scheme),
+            AppEvent::ForceOrderChanged(force_order) => self.graphics.set_force_order(force_order),
+            AppEvent::OccupancyStatsToggled(enabled) => self.graphics.set_occupancy_stats_enabled(enabled),
+            AppEvent::ConservationCheckToggled(enabled) => self.graphics.set_conservation_check_enabled(enabled),
+            AppEvent::LostParticleCheckToggled(enabled) => self.graphics.set_lost_particle_check_enabled(enabled),
+            AppEvent::ErrorCallbackChanged(callback, reset_on_error) =>
+                self.graphics.set_error_callback(callback, reset_on_error),
+            AppEvent::ShowBoundsToggled(show_bounds) => self.graphics.set_show_bounds(show_bounds),
+            AppEvent::ShowHudToggled(show_hud) => self.graphics.set_show_hud(show_hud),
+            AppEvent::TransparentBackgroundToggled(transparent) => self.graphics.set_transparent_background(transparent),
+            AppEvent::DebugViewChanged(debug_view) => self.graphics.set_debug_view(debug_view),
+            AppEvent::ViewportModeChanged(viewport_mode) => self.graphics.set_viewport_mode(viewport_mode),
+            AppEvent::MirrorModeChanged(mirror_mode) => self.graphics.set_mirror_mode(mirror_mode),
+            AppEvent::DepthToggled(enabled) => self.graphics.set_depth_enabled(enabled),
+            AppEvent::DepthTestToggled(enabled) => self.graphics.set_depth_test(enabled),
+            AppEvent::DepthRangeChanged(near, far) => self.graphics.set_depth_range(near, far),
+            AppEvent::MsaaSamplesChanged(samples) => self.graphics.set_msaa_samples(samples),
+            AppEvent::BinningModeChanged(binning_mode) => self.graphics.set_binning_mode(binning_mode),
+            AppEvent::BinClearModeChanged(bin_clear_mode) => self.graphics.set_bin_clear_mode(bin_clear_mode),
+            AppEvent::KeyboardControlsToggled(enabled) => self.graphics.set_keyboard_controls_enabled(enabled),
+            AppEvent::FreezeBrushToggled(enabled) => self.graphics.set_freeze_brush_enabled(enabled),
+            AppEvent::FreezeBrushRadiusChanged(radius) => self.graphics.set_freeze_brush_radius(radius),
+            AppEvent::SpawnOnClickToggled(enabled) => self.graphics.set_spawn_on_click_enabled(enabled),
+            AppEvent::SpawnBurstCountChanged(count) => self.graphics.set_spawn_burst_count(count),
+            AppEvent::SpawnColorChanged(color) => self.graphics.set_spawn_color(color),
+            AppEvent::SpawnSoftTimeChanged(soft_time_ms) => self.graphics.set_spawn_soft_time(soft_time_ms),
+            AppEvent::SinkChanged(center, radius, probability) =>
+                self.graphics.set_sink(center / api_unit_scale, radius / api_unit_scale, probability),
+            AppEvent::PlatformChanged(index, Some((start, end))) =>
+                self.graphics.set_platform(index, start / api_unit_scale, end / api_unit_scale),
+            AppEvent::PlatformChanged(index, None) => self.graphics.remove_platform(index),
+            AppEvent::PlatformsToggled(enabled) => self.graphics.set_platforms_enabled(enabled),
+            AppEvent::GravityZoneAdded(min, max, gravity) =>
+                self.graphics.add_gravity_zone(min / api_unit_scale, max / api_unit_scale, gravity / api_unit_scale),
+            AppEvent::GravityZonesCleared => self.graphics.clear_gravity_zones(),
+            AppEvent::TrackChanged(points) =>
+                self.graphics.set_track(&points.iter().map(|&point| point / api_unit_scale).collect::<Vec<_>>()),
+            AppEvent::TrackToggled(enabled) => self.graphics.set_track_enabled(enabled),
+            AppEvent::ContainerChanged(container) =>
+                self.graphics.set_container(container.map(|container| container.scaled(1.0 / api_unit_scale))),
+            AppEvent::CameraChanged(center, zoom, duration_ms, easing) =>
+                self.graphics.set_camera(center / api_unit_scale, zoom, duration_ms, easing),
+            AppEvent::AutoCameraChanged(smoothing_ms) => self.graphics.set_auto_camera(smoothing_ms),
+            AppEvent::TouchControlsToggled(enabled) => self.graphics.set_touch_controls_enabled(enabled),
+            AppEvent::ParticlesReplaced(particles) => self.graphics.set_particles(&particles),
+            AppEvent::ParticlesLoaded(mut data) => {
+                data.iter_mut().for_each(|value| *value /= api_unit_scale);
+                self.graphics.set_particles(bytemuck::cast_slice(&data));
+            }
+            AppEvent::BoundaryParticlesChanged(start, count) => self.graphics.set_boundary_particles(start, count),
+            AppEvent::DeterminismRequested(steps, tolerance, resolve) => {
+                let [mut first, mut second] = self.graphics.run_twice(steps);
 
-            match event {
-                Event::UserEvent(event) => self.handle_user_event(event),
-                Event::WindowEvent {
-                    event,
-                    ..
-                } => {
-                    if !self.graphics.event(&event) {
-                        match event {
-                            WindowEvent::CloseRequested => control_flow.set_exit(),
-                            _ => {}
-                        }
-                    }
+                for value in first.iter_mut().chain(second.iter_mut()) {
+                    *value *= api_unit_scale;
                 }
-                Event::RedrawRequested(_) => {
-                    let cur_frame_time = performance.now();
-                    let delta_time = cur_frame_time - last_frame_time;
-                    last_frame_time = cur_frame_time;
 
-                    debug!("FPS (instantaneous): {}", 1000.0 / delta_time);
+                let determinism = Determinism::between(&first, &second, tolerance);
 
-                    self.frame(delta_time)
+                if let Err(err) = resolve.call1(&JsValue::NULL, &JsValue::from(determinism)) {
+                    warn!("Could not resolve determinism measurement: {:?}", err);
                 }
-                Event::MainEventsCleared => self.window.request_redraw(),
-                _ => {}
             }
-        })
+            AppEvent::ParticleFieldRequested(field, resolve) => {
+                let mut data = self.graphics.read_particle_field(field);
+                data.iter_mut().for_each(|value| *value *= api_unit_scale);
+
+                let data = Float32Array::from(data.as_slice());
+
+                if let Err(err) = resolve.call1(&JsValue::NULL, &data) {
+                    warn!("Could not resolve particle read-back: {:?}", err);
+                }
+            }
+            AppEvent::MaxSpeedRequested(resolve) => self.graphics.read_max_speed(resolve),
+            AppEvent::PickingToggled(enabled) => self.graphics.set_picking_enabled(enabled),
+            AppEvent::ParticlePickRequested(x, y, resolve) => {
+                let index = self.graphics.pick_particle(x, y).map_or(JsValue::UNDEFINED, JsValue::from);
+
+                if let Err(err) = resolve.call1(&JsValue::NULL, &index) {
+                    warn!("Could not resolve particle pick: {:?}", err);
+                }
+            }
+            AppEvent::TunableChanged(name, value) => {
+                if let Some(tunable) = tunable(&name) {
+                    self.graphics.set_tunable(tunable, value);
+                }
+            }
+            AppEvent::UpdateShaderSourceChanged(source, resolve, reject) => {
+                let result = match self.graphics.set_update_shader_source(&source) {
+                    Ok(()) => resolve.call0(&JsValue::NULL),
+                    Err(err) => reject.call1(&JsValue::NULL, &JsError::new(&err.to_string()).into()),
+                };
+
+                if let Err(err) = result {
+                    warn!("Could not report the update shader change: {:?}", err);
+                }
+            }
+            AppEvent::UpdateShaderReset => self.graphics.reset_update_shader(),
+            AppEvent::SyncRequested(resolve) => self.graphics.sync(resolve),
+            AppEvent::TunablesRequested(resolve) => {
+                let tunables = self.graphics.tunables()
+                    .into_iter()
+                    .map(|(tunable, value)| {
+                        let object = Object::new();
+
+                        for (key, field) in [
+                            ("name", JsValue::from(tunable.name)),
+                            ("min", JsValue::from(tunable.min)),
+                            ("max", JsValue::from(tunable.max)),
+                            ("value", JsValue::from(value)),
+                        ] {
+                            Reflect::set(&object, &JsValue::from(key), &field).unwrap();
+                        }
+
+                        object
+                    })
+                    .collect::<Array>();
+
+                if let Err(err) = resolve.call1(&JsValue::NULL, &tunables) {
+                    warn!("Could not resolve tunables: {:?}", err);
+                }
+            }
+        }
     }
 
-    fn handle_user_event(&self, event: AppEvent) {
-        match event {
-            AppEvent::ResizeRequested(size) => self.window.set_inner_size(size)
+    /// Sends the replayed events that are due at `now`.
+    fn advance_replay(&mut self, now: f64) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+
+        let elapsed = now - replay.start;
+        let due = replay.events.iter().take_while(|recorded| recorded.time <= elapsed).count();
+        let events: Vec<_> = replay.events.drain(..due).collect();
+
+        if replay.events.is_empty() {
+            self.replay = None;
+        }
+
+        for recorded in events {
+            self.handle_user_event(recorded.event);
+        }
+    }
+
+    fn begin_capture(&mut self, capture: Capture) {
+        // A capture in progress is abandoned without calling its completion callback.
+        self.capture = None;
+
+        if capture.frame_count == 0 {
+            self.end_capture(capture);
+            return;
         }
+
+        self.graphics.set_capturing(true);
+        self.capture = Some(capture);
     }
 
-    fn frame(&self, delta_time_ms: f64) {
-        self.graphics.frame(delta_time_ms);
+    fn end_capture(&mut self, capture: Capture) {
+        self.graphics.set_capturing(false);
+
+        if let Err(err) = capture.on_capture_complete.call0(&JsValue::NULL) {
+            warn!("Capture completion callback failed: {:?}", err);
+        }
     }
 
-    fn create_window(event_loop: &EventLoop<AppEvent>, canvas: HtmlCanvasElement, size: LogicalSize<u32>) -> Result<Window, OsError> {
+    fn frame(&mut self, delta_time_ms: f64) {
+        let Some(capture) = &mut self.capture else {
+            self.graphics.frame(delta_time_ms);
+            self.stream(delta_time_ms);
+            return;
+        };
+
+        let frame_duration_ms = capture.frame_duration_ms;
+
+        self.graphics.frame(frame_duration_ms);
+
+        let pixels = Uint8ClampedArray::from(self.graphics.read_canvas_pixels().as_slice());
+
+        if let Err(err) = capture.on_frame.call2(&JsValue::NULL, &capture.captured.into(), &pixels) {
+            warn!("Capture frame callback failed: {:?}", err);
+        }
+
+        capture.captured += 1;
+
+        if capture.captured == capture.frame_count {
+            if let Some(capture) = self.capture.take() {
+                self.end_capture(capture);
+            }
+        }
+
+        self.stream(frame_duration_ms);
+    }
+
+    /// Publishes the positions once the streaming interval has passed since the last time.
+    fn stream(&mut self, delta_time_ms: f64) {
+        let Some(streaming) = &mut self.streaming else {
+            return;
+        };
+
+        streaming.elapsed_ms += delta_time_ms;
+
+        if streaming.elapsed_ms < streaming.interval_ms {
+            return;
+        }
+
+        // Slow frames delay the next publication instead of causing a burst of them.
+        streaming.elapsed_ms = 0.0;
+
+        let api_unit_scale = self.graphics.api_unit_scale();
+
+        let positions: Vec<f32> = self.graphics.read_particle_field(ParticleField::Position)
+            .chunks_exact(2)
+            .step_by(streaming.stride as usize)
+            .flat_map(|position| [position[0] * api_unit_scale, position[1] * api_unit_scale])
+            .collect();
+
+        let buffer = Float32Array::from(positions.as_slice()).buffer();
+
+        if let Err(err) = streaming.on_positions.call1(&JsValue::NULL, &buffer) {
+            warn!("Streaming callback failed: {:?}", err);
+        }
+    }
+
+    fn create_window(target: &EventLoopWindowTarget<AppMessage>, canvas: HtmlCanvasElement, size: LogicalSize<u32>) -> Result<Window, OsError> {
         WindowBuilder::new()
             .with_inner_size(size)
             .with_resizable(true)
             .with_canvas(Some(canvas))
-            .build(event_loop)
+            .build(target)
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,7 @@
 use bytemuck::{Pod, Zeroable};
 use glam::Vec2;
 use js_sys::Math::random;
+use thiserror::Error;
 
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 #[repr(C)]
@@ -9,15 +10,268 @@ pub struct Particle {
     velocity: Vec2,
 }
 
-const MIN_VELOCITY: f32 = -0.1;
-const MAX_VELOCITY: f32 = 0.1;
+pub const MIN_VELOCITY: Vec2 = Vec2::splat(-0.1);
+pub const MAX_VELOCITY: Vec2 = Vec2::splat(0.1);
 
-pub fn generate_particles(cnt: u32, min_pos: Vec2, max_pos: Vec2) -> Vec<Particle> {
-    (0..cnt).map(|_| Particle {
-        position: range_random_v2(min_pos, max_pos),
-        //velocity: Vec2::ZERO,
-        velocity: range_random_v2(Vec2::splat(MIN_VELOCITY), Vec2::splat(MAX_VELOCITY)),
-    }).collect()
+pub const MIN_MASS: f32 = 1.0;
+pub const MAX_MASS: f32 = 1.0;
+
+/// Number of candidate positions in a row that may be rejected before separated generation
+/// gives up on placing the remaining particles, as the domain is most likely full.
+const MAX_REJECTED_CANDIDATES: u32 = 1000;
+
+/// Maximum number of columns and rows of the background grid of separated generation, which
+/// would otherwise grow without bounds for tiny distances.
+const MAX_SEPARATION_GRID_SIZE: usize = 1024;
+
+impl Particle {
+    pub fn resting(position: Vec2) -> Self {
+        Particle { position, velocity: Vec2::ZERO }
+    }
+}
+
+/// Initial velocity of generated particles as a function of their position.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum VelocityField {
+    /// Uniformly distributed over the velocity range, independent of the position.
+    #[default]
+    Random,
+    Zero,
+    /// Rigid rotation around the origin with the given angular velocity per unit of time,
+    /// counterclockwise for positive values.
+    Rotational(f32),
+    /// Horizontal flow whose velocity is the given rate times the height above the origin.
+    Shear(f32),
+}
+
+impl VelocityField {
+    fn velocity(self, position: Vec2, random: impl FnOnce() -> Vec2) -> Vec2 {
+        match self {
+            Self::Random => random(),
+            Self::Zero => Vec2::ZERO,
+            Self::Rotational(omega) => omega * position.perp(),
+            Self::Shear(rate) => Vec2::new(rate * position.y, 0.0),
+        }
+    }
+}
+
+/// Generates `cnt` particles uniformly distributed over `min_pos..max_pos`, with velocities
+/// given by `initial_velocity`. The `Random` field draws them uniformly from `min_vel..max_vel`.
+///
+/// With `min_distance`, positions closer than that to an already placed particle are rejected
+/// and drawn again. Generation stops early when the domain fills up, so fewer than `cnt`
+/// particles may be returned.
+pub fn generate_particles(cnt: u32, min_pos: Vec2, max_pos: Vec2, min_vel: Vec2, max_vel: Vec2, initial_velocity: VelocityField, min_distance: Option<f32>) -> Vec<Particle> {
+    generate_particles_with(cnt, min_pos, max_pos, min_vel, max_vel, initial_velocity, min_distance, || random() as f32)
+}
+
+/// Same as [`generate_particles`], but the layout is fully determined by `seed`.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_seeded_particles(cnt: u32, min_pos: Vec2, max_pos: Vec2, min_vel: Vec2, max_vel: Vec2, initial_velocity: VelocityField, min_distance: Option<f32>, seed: u64) -> Vec<Particle> {
+    let mut rng = SeededRandom::new(seed);
+    generate_particles_with(cnt, min_pos, max_pos, min_vel, max_vel, initial_velocity, min_distance, || rng.next_f32())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn generate_particles_with(
+    cnt: u32,
+    min_pos: Vec2,
+    max_pos: Vec2,
+    min_vel: Vec2,
+    max_vel: Vec2,
+    initial_velocity: VelocityField,
+    min_distance: Option<f32>,
+    mut random: impl FnMut() -> f32,
+) -> Vec<Particle> {
+    let mut random_v2 = |min: Vec2, max: Vec2| Vec2::new(
+        random() * (max.x - min.x) + min.x,
+        random() * (max.y - min.y) + min.y,
+    );
+
+    let Some(min_distance) = min_distance.filter(|&distance| distance > 0.0) else {
+        return (0..cnt).map(|_| {
+            let position = random_v2(min_pos, max_pos);
+            let velocity = initial_velocity.velocity(position, || random_v2(min_vel, max_vel));
+
+            Particle { position, velocity }
+        }).collect();
+    };
+
+    let mut grid = SeparationGrid::new(min_pos, max_pos, min_distance);
+    let mut particles = Vec::with_capacity(cnt as usize);
+    let mut rejected = 0;
+
+    while particles.len() < cnt as usize && rejected < MAX_REJECTED_CANDIDATES {
+        let position = random_v2(min_pos, max_pos);
+
+        if grid.try_insert(position) {
+            let velocity = initial_velocity.velocity(position, || random_v2(min_vel, max_vel));
+            particles.push(Particle { position, velocity });
+            rejected = 0;
+        } else {
+            rejected += 1;
+        }
+    }
+
+    particles
+}
+
+/// Background grid of rejection sampling. Cells are at least `min_distance` wide, so only the
+/// points in the cell of a candidate and in its direct neighbors can be too close to it. With the
+/// grid size capped, cells may hold several points, which are chained into a list.
+struct SeparationGrid {
+    min_pos: Vec2,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    min_distance: f32,
+    /// Index of the last point added to every cell.
+    heads: Vec<Option<u32>>,
+    /// Every point added, with the index of the point added to its cell before it.
+    points: Vec<(Vec2, Option<u32>)>,
+}
+
+impl SeparationGrid {
+    fn new(min_pos: Vec2, max_pos: Vec2, min_distance: f32) -> Self {
+        let size = max_pos - min_pos;
+        let cell_size = min_distance.max(size.max_element() / MAX_SEPARATION_GRID_SIZE as f32);
+        let columns = (size.x / cell_size).ceil().clamp(1.0, MAX_SEPARATION_GRID_SIZE as f32) as usize;
+        let rows = (size.y / cell_size).ceil().clamp(1.0, MAX_SEPARATION_GRID_SIZE as f32) as usize;
+
+        SeparationGrid {
+            min_pos,
+            cell_size,
+            columns,
+            rows,
+            min_distance,
+            heads: vec![None; columns * rows],
+            points: Vec::new(),
+        }
+    }
+
+    /// Adds `position` unless it is closer than `min_distance` to a point already added.
+    fn try_insert(&mut self, position: Vec2) -> bool {
+        let cell = ((position - self.min_pos) / self.cell_size).floor();
+        let column = (cell.x.max(0.0) as usize).min(self.columns - 1);
+        let row = (cell.y.max(0.0) as usize).min(self.rows - 1);
+
+        for neighbor_row in row.saturating_sub(1)..(row + 2).min(self.rows) {
+            for neighbor_column in column.saturating_sub(1)..(column + 2).min(self.columns) {
+                let mut next = self.heads[neighbor_row * self.columns + neighbor_column];
+
+                while let Some(index) = next {
+                    let (other, previous) = self.points[index as usize];
+
+                    if other.distance_squared(position) < self.min_distance * self.min_distance {
+                        return false;
+                    }
+
+                    next = previous;
+                }
+            }
+        }
+
+        let head = &mut self.heads[row * self.columns + column];
+        self.points.push((position, *head));
+        *head = Some(self.points.len() as u32 - 1);
+        true
+    }
+}
+
+/// Random depths in `0.0..1.0` for the pseudo-3D effect, one per particle.
+pub fn generate_depths(cnt: u32) -> Vec<f32> {
+    (0..cnt).map(|_| random() as f32).collect()
+}
+
+/// Random masses uniformly distributed over `min_mass..max_mass`, one per particle.
+pub fn generate_masses(cnt: u32, min_mass: f32, max_mass: f32) -> Vec<f32> {
+    (0..cnt).map(|_| random() as f32 * (max_mass - min_mass) + min_mass).collect()
+}
+
+/// Random drag coefficients uniformly distributed over `min_drag..max_drag`, one per particle.
+pub fn generate_drags(cnt: u32, min_drag: f32, max_drag: f32) -> Vec<f32> {
+    (0..cnt).map(|_| random() as f32 * (max_drag - min_drag) + min_drag).collect()
+}
+
+/// Seed of the `reset_index`-th seeded reset, so that a series of resets is reproducible as
+/// a whole while every reset in it still gets a different layout.
+pub fn reset_seed(base_seed: u64, reset_index: u64) -> u64 {
+    splitmix64(base_seed ^ splitmix64(reset_index.wrapping_add(GOLDEN_GAMMA)))
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// SplitMix64 generator; small, fast and good enough for particle layouts.
+#[derive(Debug, Clone)]
+struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    fn new(seed: u64) -> Self {
+        SeededRandom { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        splitmix64(self.state)
+    }
+
+    /// Uniform value in `0.0..1.0` built from the upper 24 bits.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u32 << 24) as f32
+    }
+}
+
+fn splitmix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("expected {expected} bytes of RGBA data, got {actual}")]
+    SizeMismatch { expected: usize, actual: usize },
+    #[error("no pixel has an alpha value above {0}")]
+    NoOpaquePixels(u8),
+}
+
+/// Places `cnt` resting particles on the pixels of an RGBA image whose alpha is above `threshold`.
+/// The image is stretched over `min_pos..max_pos` with its first row at the top. Pixels are
+/// subsampled evenly when there are more of them than particles and reused otherwise; every
+/// particle is jittered within its pixel so that reused pixels do not stack exactly.
+pub fn particles_from_image(pixels: &[u8], width: u32, height: u32, threshold: u8, cnt: u32, min_pos: Vec2, max_pos: Vec2) -> Result<Vec<Particle>, ImageError> {
+    let expected = width as usize * height as usize * 4;
+
+    if pixels.len() != expected {
+        return Err(ImageError::SizeMismatch { expected, actual: pixels.len() });
+    }
+
+    let opaque = pixels.chunks_exact(4)
+        .enumerate()
+        .filter(|(_, rgba)| rgba[3] > threshold)
+        .map(|(i, _)| Vec2::new((i % width as usize) as f32, (i / width as usize) as f32))
+        .collect::<Vec<Vec2>>();
+
+    if opaque.is_empty() {
+        return Err(ImageError::NoOpaquePixels(threshold));
+    }
+
+    let image_size = Vec2::new(width as f32, height as f32);
+
+    Ok((0..cnt as usize).map(|i| {
+        let pixel = opaque[i * opaque.len() / cnt as usize];
+        let uv = (pixel + range_random_v2(Vec2::ZERO, Vec2::ONE)) / image_size;
+
+        Particle {
+            position: Vec2::new(
+                min_pos.x + uv.x * (max_pos.x - min_pos.x),
+                max_pos.y - uv.y * (max_pos.y - min_pos.y),
+            ),
+            velocity: Vec2::ZERO,
+        }
+    }).collect())
 }
 
 #[inline]
@@ -31,4 +285,50 @@ fn range_random_v2(min: Vec2, max: Vec2) -> Vec2 {
 #[inline]
 fn range_random(min: f32, max: f32) -> f32 {
     (random() as f32) * (max - min) + min
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: u64 = 42;
+    const COUNT: u32 = 1000;
+
+    /// Generates a layout like a seeded reset does, with separated placement when `min_distance`
+    /// is given.
+    fn seeded_layout(seed: u64, min_distance: Option<f32>) -> Vec<Particle> {
+        generate_seeded_particles(COUNT, Vec2::NEG_ONE, Vec2::ONE, MIN_VELOCITY, MAX_VELOCITY, VelocityField::Random, min_distance, seed)
+    }
+
+    /// Bit patterns of the particle data, so that runs are compared bitwise rather than by value.
+    fn bits(particles: &[Particle]) -> Vec<u32> {
+        bytemuck::cast_slice::<Particle, f32>(particles).iter().map(|value| value.to_bits()).collect()
+    }
+
+    #[test]
+    fn seeded_generation_is_bitwise_identical() {
+        assert_eq!(bits(&seeded_layout(SEED, None)), bits(&seeded_layout(SEED, None)));
+    }
+
+    #[test]
+    fn separated_seeded_generation_is_bitwise_identical() {
+        let first = seeded_layout(SEED, Some(0.01));
+
+        assert_eq!(first.len(), COUNT as usize);
+        assert_eq!(bits(&first), bits(&seeded_layout(SEED, Some(0.01))));
+    }
+
+    #[test]
+    fn seeded_generation_depends_on_the_seed() {
+        assert_ne!(bits(&seeded_layout(SEED, None)), bits(&seeded_layout(SEED + 1, None)));
+    }
+
+    #[test]
+    fn seeded_resets_are_reproducible_and_differ_from_each_other() {
+        let first = reset_seed(SEED, 0);
+
+        assert_eq!(first, reset_seed(SEED, 0));
+        assert_ne!(first, reset_seed(SEED, 1));
+        assert_ne!(bits(&seeded_layout(first, None)), bits(&seeded_layout(reset_seed(SEED, 1), None)));
+    }
+}
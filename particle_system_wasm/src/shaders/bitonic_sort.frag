@@ -0,0 +1,33 @@
+#version 300 es
+
+precision highp int;
+precision highp float;
+
+uniform highp usampler2D keys;
+// Size of the sequences being merged in this stage.
+uniform uint block_size;
+// Distance between the keys compared in this step.
+uniform uint compare_distance;
+
+layout (location = 0) out uvec2 out_key;
+
+bool key_less(uvec2 a, uvec2 b) {
+    return a.x < b.x || (a.x == b.x && a.y < b.y);
+}
+
+void main() {
+    uint width = uint(textureSize(keys, 0).x);
+    uvec2 coords = uvec2(gl_FragCoord.xy);
+    uint index = coords.x + coords.y * width;
+    uint partner = index ^ compare_distance;
+
+    uvec2 key = texelFetch(keys, ivec2(coords), 0).xy;
+    uvec2 other = texelFetch(keys, ivec2(partner % width, partner / width), 0).xy;
+
+    // Blocks alternate between ascending and descending order, so that every pair of
+    // neighbouring blocks forms a bitonic sequence for the next stage.
+    bool ascending = (index & block_size) == 0u;
+    bool keep_smaller = (index < partner) == ascending;
+
+    out_key = key_less(other, key) == keep_smaller ? other : key;
+}
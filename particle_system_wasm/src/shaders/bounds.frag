@@ -0,0 +1,19 @@
+#version 300 es
+precision mediump float;
+
+uniform int color_space;
+
+out vec4 out_color;
+
+const vec3 BOUNDS_COLOR = vec3(0.5);
+
+const int COLOR_SPACE_LINEAR = 1;
+
+// The colors below are given in sRGB, the offscreen target of linear blending expects linear ones.
+vec3 srgb_to_linear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), greaterThan(color, vec3(0.04045)));
+}
+
+void main() {
+    out_color = vec4(color_space == COLOR_SPACE_LINEAR ? srgb_to_linear(BOUNDS_COLOR) : BOUNDS_COLOR, 1.0);
+}
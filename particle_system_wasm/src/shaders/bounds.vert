@@ -0,0 +1,12 @@
+#version 300 es
+
+uniform vec2 min_bounds;
+uniform vec2 max_bounds;
+// xy: domain point shown at the center of the canvas, z: zoom.
+uniform vec3 view;
+
+void main() {
+    vec2 corner = vec2(gl_VertexID == 1 || gl_VertexID == 2, gl_VertexID >= 2);
+
+    gl_Position = vec4((mix(min_bounds, max_bounds, corner) - view.xy) * view.z, 0.0, 1.0);
+}
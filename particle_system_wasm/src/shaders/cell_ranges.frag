@@ -0,0 +1,40 @@
+#version 300 es
+
+precision highp int;
+precision highp float;
+
+uniform highp usampler2D keys;
+uniform uvec2 grid_size;
+
+// Range of sorted keys belonging to the cell, end exclusive.
+layout (location = 0) out uvec2 out_range;
+
+uint key_cell(uint index, uint width) {
+    return texelFetch(keys, ivec2(index % width, index / width), 0).x;
+}
+
+// Index of the first key whose cell is not below `cell`, i.e. the number of particles
+// in all cells before it.
+uint lower_bound(uint cell) {
+    uvec2 size = uvec2(textureSize(keys, 0));
+    uint low = 0u;
+    uint high = size.x * size.y;
+
+    while (low < high) {
+        uint middle = (low + high) / 2u;
+
+        if (key_cell(middle, size.x) < cell)
+            low = middle + 1u;
+        else
+            high = middle;
+    }
+
+    return low;
+}
+
+void main() {
+    uvec2 coords = uvec2(gl_FragCoord.xy);
+    uint cell = coords.x + coords.y * grid_size.x;
+
+    out_range = uvec2(lower_bound(cell), lower_bound(cell + 1u));
+}
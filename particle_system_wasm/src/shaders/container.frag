@@ -0,0 +1,26 @@
+#version 300 es
+precision highp float;
+
+// xy: domain point shown at the center of the canvas, z: zoom.
+uniform vec3 view;
+// xy: origin, zw: size in pixels.
+uniform vec4 viewport;
+// (center, half size) of the rounded rectangle the particles are kept in.
+uniform vec4 container;
+uniform float container_corner_radius;
+
+out vec4 out_color;
+
+const vec4 BACKGROUND_COLOR = vec4(0.0, 0.0, 0.0, 1.0);
+
+void main() {
+    vec2 clip = (gl_FragCoord.xy - viewport.xy) / viewport.zw * 2.0 - 1.0;
+    vec2 offset = clip / view.z + view.xy - container.xy;
+    vec2 q = abs(offset) - (container.zw - container_corner_radius);
+
+    // Outside of the container, the cleared transparent background is kept.
+    if (length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) > container_corner_radius)
+        discard;
+
+    out_color = BACKGROUND_COLOR;
+}
@@ -0,0 +1,67 @@
+#version 300 es
+
+precision highp float;
+precision highp int;
+precision highp usampler2DArray;
+
+uniform sampler2D particles;
+uniform usampler2DArray bins;
+uniform int debug_view;
+uniform vec2 domain_min;
+uniform vec2 domain_max;
+// xy: origin, zw: size in pixels.
+uniform vec4 viewport;
+uniform int color_space;
+
+out vec4 out_color;
+
+const int VIEW_POSITIONS = 1;
+const int VIEW_VELOCITIES = 2;
+const int VIEW_BINS = 3;
+
+const float VELOCITY_SCALE = 0.5;
+
+const int COLOR_SPACE_LINEAR = 1;
+
+// The views are meant to be read as sRGB, the offscreen target of linear blending expects linear ones.
+vec3 srgb_to_linear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), greaterThan(color, vec3(0.04045)));
+}
+
+vec4 view_color(vec2 uv) {
+    if (debug_view == VIEW_BINS) {
+        ivec3 size = textureSize(bins, 0);
+        ivec2 coords = ivec2(uv * vec2(size.xy));
+        int occupied = 0;
+
+        for (int i = 0; i < size.z; ++i) {
+            if (texelFetch(bins, ivec3(coords, i), 0).r != 0u)
+                ++occupied;
+        }
+
+        return vec4(vec3(float(occupied) / float(size.z)), 1.0);
+    }
+
+    vec4 particle = texelFetch(particles, ivec2(uv * vec2(textureSize(particles, 0))), 0);
+
+    if (debug_view == VIEW_POSITIONS)
+        // Red and green map the domain to [0, 1], blue is set outside of it.
+        return vec4(
+            (particle.xy - domain_min) / (domain_max - domain_min),
+            any(lessThan(particle.xy, domain_min)) || any(greaterThan(particle.xy, domain_max)) ? 1.0 : 0.0,
+            1.0
+        );
+    else if (debug_view == VIEW_VELOCITIES)
+        // Zero velocity is gray.
+        return vec4(clamp(particle.zw * VELOCITY_SCALE + 0.5, 0.0, 1.0), 0.5, 1.0);
+    else
+        return vec4(0.0, 0.0, 0.0, 1.0);
+}
+
+void main() {
+    vec2 uv = (gl_FragCoord.xy - viewport.xy) / viewport.zw;
+    out_color = view_color(uv);
+
+    if (color_space == COLOR_SPACE_LINEAR)
+        out_color.rgb = srgb_to_linear(out_color.rgb);
+}
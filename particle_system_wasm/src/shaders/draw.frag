@@ -1,17 +1,57 @@
 #version 300 es
 precision mediump float;
 
+in vec4 v_color;
+in vec2 v_uv;
+in float v_brightness;
+
+uniform highp int render_mode;
+uniform int falloff_mode;
+uniform int color_space;
+// Below 1 only with alpha blending.
+uniform float opacity;
+
 out vec4 out_color;
 
+const int RENDER_QUADS = 1;
+
+const int COLOR_SPACE_LINEAR = 1;
+
+const int FALLOFF_LINEAR = 1;
+const int FALLOFF_GAUSSIAN = 2;
+const int FALLOFF_INVERSE_SQUARE = 3;
+
 float len2(vec2 v) {
     return dot(v, v);
 }
 
-void main() {
-    if (len2(2.0 * gl_PointCoord - 1.0) <= 1.0)
-        out_color = vec4(1.0, 0.0, 0.0, 1.0);
+// The palettes are given in sRGB.
+vec3 srgb_to_linear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), greaterThan(color, vec3(0.04045)));
+}
+
+// Brightness at the squared distance from the center, where 1 is the edge of the particle.
+float falloff(float dst2) {
+    if (falloff_mode == FALLOFF_LINEAR)
+        return 1.0 - sqrt(dst2);
+    else if (falloff_mode == FALLOFF_GAUSSIAN)
+        return exp(-4.0 * dst2);
+    else if (falloff_mode == FALLOFF_INVERSE_SQUARE)
+        return 1.0 / (1.0 + 16.0 * dst2);
     else
+        return 1.0;
+}
+
+void main() {
+    vec2 coord = render_mode == RENDER_QUADS ? v_uv : gl_PointCoord;
+
+    float dst2 = len2(2.0 * coord - 1.0);
+
+    if (dst2 > 1.0)
         discard;
 
+    vec4 color = color_space == COLOR_SPACE_LINEAR ? vec4(srgb_to_linear(v_color.rgb), v_color.a) : v_color;
+    out_color = color * falloff(dst2) * v_brightness * opacity;
+
 //    out_color = vec4(1.0, 0.0, 0.0, 1.0);
 }
\ No newline at end of file
@@ -1,20 +1,145 @@
 #version 300 es
 
+precision highp usampler2DArray;
+
 uniform sampler2D particles;
+uniform usampler2DArray bins;
+uniform sampler2D palette;
+uniform sampler2D depths;
+// Color every particle was spawned with.
+uniform sampler2D colors;
+uniform sampler2D drags;
+// Range the drag coefficients were drawn from.
+uniform vec2 drag_range;
+// Simulation time every particle was spawned at, in milliseconds.
+uniform sampler2D spawn_times;
+uniform float running_time;
+// Age at which the palette ends when coloring by age, 0 colors by `color_mode` instead.
+uniform float age_color_lifetime;
+uniform vec2 domain_min;
+uniform vec2 cell_size;
 uniform float point_size;
+// Only every `draw_stride`-th particle is drawn.
+uniform int draw_stride;
+uniform vec2 quad_half_size;
+uniform vec2 ghost_offset;
+// Mirror image of the domain around its center drawn by this pass.
+uniform mat2 mirror;
+uniform int color_mode;
+uniform highp int render_mode;
+// xy: domain point shown at the center of the canvas, z: zoom.
+uniform vec3 view;
+uniform bool depth_enabled;
+// Size and brightness factors at depth 0 and 1.
+uniform vec2 depth_range;
+// Writes the depth of the particle, so that nearer ones hide farther ones behind them.
+uniform bool depth_test;
+
+out vec4 v_color;
+out vec2 v_uv;
+out float v_brightness;
+flat out uint v_id;
 
 const float PARTICLE_SCALE = 1.0;
 
+const int COLOR_SPEED = 1;
+const int COLOR_DENSITY = 2;
+const int COLOR_INDEX = 3;
+const int COLOR_SPAWN = 4;
+const int COLOR_DRAG = 5;
+
+const float SPEED_COLOR_SCALE = 0.5;
+
+const int RENDER_QUADS = 1;
+
+const vec2 QUAD[6] = vec2[6](
+    vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+);
+
+vec2 to_clip(vec2 position) {
+    return (position - view.xy) * view.z;
+}
+
 float rand(float n) {
     return fract(sin(n) * 43758.5453123);
 }
 
+// Looks up `t` in [0, 1] in the palette, from the center of its first entry to that of its last.
+vec4 palette_color(float t) {
+    float entries = float(textureSize(palette, 0).x);
+    return texture(palette, vec2((t * (entries - 1.0) + 0.5) / entries, 0.5));
+}
+
+float bin_density(vec2 position) {
+    ivec2 bin_coords = ivec2(floor((position - domain_min) / cell_size));
+    int layers = textureSize(bins, 0).z;
+    int occupied = 0;
+
+    for (int i = 0; i < layers; ++i) {
+        if (texelFetch(bins, ivec3(bin_coords, i), 0).r != 0u)
+            ++occupied;
+    }
+
+    return float(occupied) / float(layers);
+}
+
 void main() {
+    int id = (render_mode == RENDER_QUADS ? gl_InstanceID : gl_VertexID) * draw_stride;
+
     ivec2 size = textureSize(particles, 0).xy;
-    ivec2 coords = ivec2(gl_VertexID % size.x, gl_VertexID / size.x);
+    ivec2 coords = ivec2(id % size.x, id / size.x);
 
     vec4 particle = texelFetch(particles, coords, 0);
+    v_id = uint(id);
+
+    vec2 position = particle.xy + ghost_offset;
+
+    // Ghost copies are only needed for particles overlapping the domain, which spans the clip space,
+    // after being shifted. The rest is moved outside the clip volume.
+    if (ghost_offset != vec2(0.0) && any(greaterThan(abs(position), vec2(1.0) + quad_half_size))) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        gl_PointSize = 1.0;
+        v_color = vec4(0.0);
+        v_uv = vec2(0.0);
+        v_brightness = 0.0;
+        return;
+    }
+
+    position = mirror * position;
+
+    float depth = texelFetch(depths, coords, 0).r;
+    // Depth 0 is nearest, which the LESS depth test keeps.
+    float z = depth_test ? 2.0 * depth - 1.0 : 0.0;
+
+    float depth_scale = depth_enabled ? mix(depth_range.x, depth_range.y, depth) : 1.0;
+    v_brightness = depth_scale;
+
+    if (age_color_lifetime > 0.0)
+        v_color = palette_color(clamp((running_time - texelFetch(spawn_times, coords, 0).r) / age_color_lifetime, 0.0, 1.0));
+    else if (color_mode == COLOR_SPEED)
+        v_color = palette_color(clamp(length(particle.zw) * SPEED_COLOR_SCALE, 0.0, 1.0));
+    else if (color_mode == COLOR_DENSITY)
+        v_color = palette_color(bin_density(particle.xy));
+    else if (color_mode == COLOR_INDEX)
+        v_color = palette_color(float(id) / float(size.x * size.y));
+    else if (color_mode == COLOR_SPAWN)
+        v_color = texelFetch(colors, coords, 0);
+    else if (color_mode == COLOR_DRAG)
+        v_color = palette_color(drag_range.y > drag_range.x
+            ? (texelFetch(drags, coords, 0).r - drag_range.x) / (drag_range.y - drag_range.x)
+            : 0.0);
+    else
+        v_color = vec4(1.0, 0.0, 0.0, 1.0);
+
+    if (render_mode == RENDER_QUADS) {
+        vec2 corner = QUAD[gl_VertexID];
 
-    gl_Position = vec4(particle.xy, 0.0, 1.0);
-    gl_PointSize = point_size;
+        gl_Position = vec4(to_clip(position) + corner * quad_half_size * depth_scale * view.z, z, 1.0);
+        v_uv = corner * 0.5 + 0.5;
+    } else {
+        gl_Position = vec4(to_clip(position), z, 1.0);
+        gl_PointSize = point_size * depth_scale * view.z;
+        v_uv = vec2(0.0);
+    }
 }
\ No newline at end of file
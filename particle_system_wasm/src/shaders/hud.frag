@@ -0,0 +1,47 @@
+#version 300 es
+precision mediump float;
+precision highp int;
+
+// Glyph index of every character, line by line from the top.
+uniform highp usampler2D text;
+// Glyphs side by side, top row first.
+uniform sampler2D font;
+// Top left corner of the text in pixels.
+uniform vec2 origin;
+// Pixels per font pixel.
+uniform float scale;
+uniform int color_space;
+
+out vec4 out_color;
+
+const ivec2 GLYPH_SIZE = ivec2(5, 7);
+// Glyphs are placed one font pixel from the top left corner of their cell.
+const ivec2 CELL_SIZE = GLYPH_SIZE + ivec2(1, 2);
+
+const vec4 TEXT_COLOR = vec4(1.0);
+const vec4 BACKGROUND_COLOR = vec4(0.0, 0.0, 0.0, 0.6);
+
+const int COLOR_SPACE_LINEAR = 1;
+
+// The colors below are given in sRGB, the offscreen target of linear blending expects linear ones.
+vec3 srgb_to_linear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), greaterThan(color, vec3(0.04045)));
+}
+
+void main() {
+    ivec2 position = ivec2(vec2(gl_FragCoord.x - origin.x, origin.y - gl_FragCoord.y) / scale);
+    ivec2 cell = position / CELL_SIZE;
+    ivec2 glyph_position = position - cell * CELL_SIZE - ivec2(1);
+
+    out_color = BACKGROUND_COLOR;
+
+    if (all(greaterThanEqual(glyph_position, ivec2(0))) && all(lessThan(glyph_position, GLYPH_SIZE))) {
+        int glyph = int(texelFetch(text, cell, 0).r);
+
+        if (texelFetch(font, ivec2(glyph * GLYPH_SIZE.x + glyph_position.x, glyph_position.y), 0).r > 0.5)
+            out_color = TEXT_COLOR;
+    }
+
+    if (color_space == COLOR_SPACE_LINEAR)
+        out_color.rgb = srgb_to_linear(out_color.rgb);
+}
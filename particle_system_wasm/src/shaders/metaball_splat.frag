@@ -0,0 +1,16 @@
+#version 300 es
+precision mediump float;
+
+in vec2 v_uv;
+
+out vec4 out_density;
+
+void main() {
+    vec2 offset = 2.0 * v_uv - 1.0;
+    float distance2 = dot(offset, offset);
+
+    if (distance2 > 1.0)
+        discard;
+
+    out_density = vec4(exp(-4.0 * distance2), 0.0, 0.0, 0.0);
+}
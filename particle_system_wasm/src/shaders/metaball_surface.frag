@@ -0,0 +1,53 @@
+#version 300 es
+precision mediump float;
+
+uniform highp sampler2D density;
+// xy: origin, zw: size in pixels.
+uniform vec4 viewport;
+uniform int color_space;
+
+out vec4 out_color;
+
+const float THRESHOLD = 1.0;
+const float EDGE_WIDTH = 0.25;
+const float NORMAL_SCALE = 2.0;
+const float SHININESS = 32.0;
+
+const vec3 SURFACE_COLOR = vec3(0.2, 0.5, 1.0);
+const vec3 LIGHT_DIRECTION = vec3(-0.4, 0.56, 0.72);
+
+const int COLOR_SPACE_LINEAR = 1;
+
+// The colors below are given in sRGB, the offscreen target of linear blending expects linear ones.
+vec3 srgb_to_linear(vec3 color) {
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), greaterThan(color, vec3(0.04045)));
+}
+
+float sample_density(vec2 uv) {
+    return texture(density, uv).r;
+}
+
+void main() {
+    vec2 uv = (gl_FragCoord.xy - viewport.xy) / viewport.zw;
+    float value = sample_density(uv);
+
+    float alpha = smoothstep(THRESHOLD - EDGE_WIDTH, THRESHOLD + EDGE_WIDTH, value);
+
+    if (alpha <= 0.0)
+        discard;
+
+    vec2 texel = 1.0 / vec2(textureSize(density, 0));
+
+    vec2 gradient = vec2(
+        sample_density(uv + vec2(texel.x, 0.0)) - sample_density(uv - vec2(texel.x, 0.0)),
+        sample_density(uv + vec2(0.0, texel.y)) - sample_density(uv - vec2(0.0, texel.y))
+    );
+
+    vec3 normal = normalize(vec3(-NORMAL_SCALE * gradient, 1.0));
+
+    float diffuse = max(dot(normal, LIGHT_DIRECTION), 0.0);
+    float specular = pow(max(reflect(-LIGHT_DIRECTION, normal).z, 0.0), SHININESS);
+
+    vec3 color = SURFACE_COLOR * (0.3 + 0.7 * diffuse) + specular;
+    out_color = vec4(color_space == COLOR_SPACE_LINEAR ? srgb_to_linear(color) : color, alpha);
+}
@@ -0,0 +1,19 @@
+#version 300 es
+
+precision highp int;
+precision highp float;
+
+flat in uint v_id;
+
+layout (location = 0) out vec4 out_slots;
+
+// One slot per color channel, so this must match BIN_CAPACITY.
+const uint SLOT_COUNT = 4u;
+
+void main() {
+    vec4 slots = vec4(0.0);
+    slots[int(v_id % SLOT_COUNT)] = float(v_id + 1u);
+
+    // Blended with MAX, so every slot ends up with the highest id that maps to it.
+    out_slots = slots;
+}
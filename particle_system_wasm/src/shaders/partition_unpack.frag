@@ -0,0 +1,20 @@
+#version 300 es
+
+precision highp int;
+precision highp float;
+
+uniform highp sampler2D slots;
+
+layout (location = 0) out uint out_bin_0;
+layout (location = 1) out uint out_bin_1;
+layout (location = 2) out uint out_bin_2;
+layout (location = 3) out uint out_bin_3;
+
+void main() {
+    uvec4 ids = uvec4(texelFetch(slots, ivec2(gl_FragCoord.xy), 0));
+
+    out_bin_0 = ids.x;
+    out_bin_1 = ids.y;
+    out_bin_2 = ids.z;
+    out_bin_3 = ids.w;
+}
@@ -0,0 +1,18 @@
+#version 300 es
+
+precision highp float;
+precision highp int;
+
+flat in uint v_id;
+
+layout (location = 0) out uint out_id;
+
+void main() {
+    vec2 delta = 2.0 * gl_PointCoord - 1.0;
+
+    if (dot(delta, delta) > 1.0)
+        discard;
+
+    // 0 is left for the background.
+    out_id = v_id + 1u;
+}
@@ -0,0 +1,19 @@
+#version 300 es
+precision mediump float;
+
+uniform sampler2D image;
+// Set when the image is an sRGB texture, which is sampled as linear colors.
+uniform bool encode_srgb;
+
+out vec4 out_color;
+
+vec3 linear_to_srgb(vec3 color) {
+    return mix(color * 12.92, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055, greaterThan(color, vec3(0.0031308)));
+}
+
+void main() {
+    out_color = texelFetch(image, ivec2(gl_FragCoord.xy), 0);
+
+    if (encode_srgb)
+        out_color.rgb = linear_to_srgb(out_color.rgb);
+}
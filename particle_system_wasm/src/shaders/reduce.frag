@@ -0,0 +1,69 @@
+#version 300 es
+precision highp float;
+precision highp int;
+
+// Particle data on the first level, the output of the previous level afterwards.
+uniform sampler2D source;
+uniform sampler2D masses;
+uniform bool first_level;
+// Size of the input of this level in texels. Every output texel combines a 2x2 block of them.
+uniform ivec2 input_size;
+uniform int particle_count;
+uniform vec2 freeze_brush_center;
+// Zero while the freeze brush is not in use.
+uniform vec2 freeze_brush_radius;
+// First and one past the last index of the boundary particles.
+uniform uvec2 boundary_range;
+
+// r: maximum speed, gb: sum of mass times velocity, a: sum of masses.
+out vec4 out_result;
+
+// Must match the update shader.
+const vec2 ABSORBED_POSITION = vec2(-1000.0);
+
+// Absorbed, frozen and boundary particles are held in place by the update pass, so they neither
+// drift nor should be set in motion by removing the drift of the others.
+bool is_held(uint id, vec2 position) {
+    if ((id >= boundary_range.x && id < boundary_range.y) || position == ABSORBED_POSITION)
+        return true;
+
+    if (freeze_brush_radius.x <= 0.0)
+        return false;
+
+    vec2 delta_pos = (position - freeze_brush_center) / freeze_brush_radius;
+    return dot(delta_pos, delta_pos) <= 1.0;
+}
+
+vec4 load(ivec2 coords) {
+    if (any(greaterThanEqual(coords, input_size)))
+        return vec4(0.0);
+
+    if (!first_level)
+        return texelFetch(source, coords, 0);
+
+    int id = coords.x + coords.y * input_size.x;
+
+    if (id >= particle_count)
+        return vec4(0.0);
+
+    vec4 particle = texelFetch(source, coords, 0);
+
+    if (is_held(uint(id), particle.xy))
+        return vec4(0.0);
+
+    vec2 velocity = particle.zw;
+    float mass = texelFetch(masses, coords, 0).r;
+
+    return vec4(length(velocity), mass * velocity, mass);
+}
+
+void main() {
+    ivec2 coords = 2 * ivec2(gl_FragCoord.xy);
+
+    vec4 a = load(coords);
+    vec4 b = load(coords + ivec2(1, 0));
+    vec4 c = load(coords + ivec2(0, 1));
+    vec4 d = load(coords + ivec2(1, 1));
+
+    out_result = vec4(max(max(a.r, b.r), max(c.r, d.r)), a.gba + b.gba + c.gba + d.gba);
+}
@@ -0,0 +1,35 @@
+#version 300 es
+
+precision highp int;
+precision highp float;
+
+uniform sampler2D particles;
+uniform uint particle_count;
+// Width of the key texture being written.
+uniform uint key_width;
+uniform uvec2 grid_size;
+uniform vec2 domain_min;
+uniform vec2 cell_size;
+
+// x: cell index, y: particle id.
+layout (location = 0) out uvec2 out_key;
+
+// Sorts after every cell, so padding and particles outside the grid end up at the back.
+const uint NO_CELL = 0xFFFFFFFFu;
+
+void main() {
+    uvec2 coords = uvec2(gl_FragCoord.xy);
+    uint id = coords.x + coords.y * key_width;
+    uint cell = NO_CELL;
+
+    if (id < particle_count) {
+        int data_width = textureSize(particles, 0).x;
+        vec2 position = texelFetch(particles, ivec2(int(id) % data_width, int(id) / data_width), 0).xy;
+        vec2 bin = floor((position - domain_min) / cell_size);
+
+        if (all(greaterThanEqual(bin, vec2(0.0))) && all(lessThan(bin, vec2(grid_size))))
+            cell = uint(bin.x) + uint(bin.y) * grid_size.x;
+    }
+
+    out_key = uvec2(cell, id);
+}
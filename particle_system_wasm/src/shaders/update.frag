@@ -8,17 +8,136 @@
 precision highp float;
 precision highp int;
 precision highp usampler2DArray;
+precision highp usampler2D;
 
 layout (location = 0) out vec4 out_particle;
 
 uniform sampler2D particles;
+uniform sampler2D masses;
+// Drag coefficient of every particle, which slows it down on top of the global damping.
+uniform sampler2D drags;
+// Simulation time every particle was last spawned at, in milliseconds.
+uniform sampler2D spawn_times;
+// Collisions of particles spawned less than this many milliseconds ago are softened, 0 disables it.
+uniform float spawn_soft_time;
+uniform float running_time;
 uniform usampler2DArray bins;
+// Sorted binning: (cell, id) keys ordered by cell, and the range of keys of every cell.
+uniform bool sorted_binning;
+uniform usampler2D sorted_keys;
+uniform usampler2D cell_ranges;
 uniform float dt;
-uniform uvec2 grid_size;
+uniform vec2 cell_size;
 uniform float particle_radius;
+// Factor the radius is multiplied by in collisions between particles, the drawn size is unaffected.
+uniform float collision_radius_scale;
+uniform vec2 gravity;
+uniform float damping;
+uniform vec2 domain_min;
+uniform vec2 domain_max;
+// Per axis.
+uniform ivec2 boundary_mode;
+uniform float wall_restitution;
+uniform float particle_restitution;
+uniform int force_order;
+uniform bool collisions_enabled;
+// Upper bound on the neighbors a particle is tested against per step. Crowded cells then
+// get some of their collisions missed.
+uniform int max_neighbor_checks;
+// Part of the collision response run by this pass, see the COLLISION_STAGE constants.
+uniform int collision_stage;
+uniform float max_force;
+// Accelerations over the domain, scaled by the strength.
+uniform sampler2D force_field;
+uniform float force_field_strength;
+
+uniform int impulse_mode;
+uniform vec2 impulse;
+uniform float impulse_seed;
+uniform float velocity_scale;
+// Velocity added to the particles inside the rectangle from xy to zw.
+uniform vec4 push_region;
+uniform vec2 push_velocity;
+uniform vec2 drift;
+// Boundary particles, from the first index to one past the last, which stay in place.
+uniform uvec2 boundary_range;
+
+uniform sampler2D morph_targets;
+uniform vec2 morph_progress;
+
+uniform vec2 freeze_brush_center;
+uniform vec2 freeze_brush_radius;
+
+// A zero radius disables the sink.
+uniform vec2 sink_center;
+uniform float sink_radius;
+uniform float sink_probability;
+uniform float sink_seed;
+
+const int MAX_PLATFORMS = 4;
+
+// Line segments as (start, end), the first `platform_count` of which are placed.
+uniform vec4 platforms[MAX_PLATFORMS];
+uniform int platform_count;
+
+const int MAX_GRAVITY_ZONES = 4;
+
+// Rectangles as (min, max), the first `gravity_zone_count` of which are placed, and the gravity
+// inside each of them.
+uniform vec4 gravity_zone_bounds[MAX_GRAVITY_ZONES];
+uniform vec2 gravity_zone_gravity[MAX_GRAVITY_ZONES];
+uniform int gravity_zone_count;
+
+// Rounded rectangle as (center, half size) the particles are kept inside of, with corners of
+// radius `container_corner_radius`. A circle is a square with fully rounded corners, a zero size
+// disables the container.
+uniform vec4 container;
+uniform float container_corner_radius;
+
+// Segments of the polyline the particles are threaded onto as (start, end), one per texel of the
+// first row, of which the first `track_segment_count` are used. Zero disables the track.
+uniform sampler2D track;
+uniform int track_segment_count;
 
 const uint BIN_CAPACITY = 4u;
 
+const int IMPULSE_UNIFORM = 1;
+const int IMPULSE_RADIAL = 2;
+const int IMPULSE_RANDOM = 3;
+
+const int BOUNDARY_BOUNCE = 1;
+const int BOUNDARY_WRAP = 2;
+
+const int FORCE_ORDER_GRAVITY_FIRST = 1;
+const int FORCE_ORDER_SPLIT = 2;
+
+// Overlaps and velocities together, in a single pass.
+const int COLLISION_STAGE_FULL = 0;
+// Only pushes overlapping particles apart and leaves everything else to the velocity stage.
+const int COLLISION_STAGE_POSITIONS = 1;
+// The whole step on the separated positions, with collisions only exchanging velocities.
+const int COLLISION_STAGE_VELOCITIES = 2;
+
+// Distance overlapping particles are pushed apart to, in particle radii.
+const float SEPARATION_DISTANCE = 2.05;
+// Pairs separated by the position stage end up just out of reach of each other, so the
+// velocity stage treats particles a little further apart as touching.
+const float SEPARATED_CONTACT_DISTANCE = 2.1;
+
+// Upper bound on wall contacts resolved per step, e.g. when moving into a corner.
+const int MAX_WALL_CONTACTS = 4;
+
+// Absorbed particles are parked here until they are replaced, must match the renderer.
+const vec2 ABSORBED_POSITION = vec2(-1000.0);
+
+// Mass of the boundary particles, large enough for the others to take the whole response.
+const float BOUNDARY_MASS = 1e20;
+
+// Largest share of a morph a particle may wait for before starting to move.
+const float MORPH_STAGGER = 0.3;
+
+const float PI = 3.14159265359;
+
 struct StaticCollider {
     vec2 position;
     float radius;
@@ -27,15 +146,46 @@ struct StaticCollider {
 struct Particle {
     vec2 position;
     vec2 velocity;
+    // Constant, read from its own texture.
+    float mass;
+    // Constant like the mass.
+    float drag;
+    // Share of the collision response the particle takes part with, which grows from 0 to 1
+    // over the soft time after it was spawned.
+    float stiffness;
 };
 
 struct Bin {
     Particle particles[BIN_CAPACITY];
+    // Whether the slot holds another particle, as opposed to being empty or holding the current one.
+    bool occupied[BIN_CAPACITY];
 };
 
+float get_stiffness(in ivec2 coords) {
+    if (spawn_soft_time <= 0.0)
+        return 1.0;
+
+    return clamp((running_time - texelFetch(spawn_times, coords, 0).r) / spawn_soft_time, 0.0, 1.0);
+}
+
+uint get_particle_id(in ivec2 coords) {
+    return uint(coords.x + coords.y * textureSize(particles, 0).x);
+}
+
+bool is_boundary(in uint id) {
+    return id >= boundary_range.x && id < boundary_range.y;
+}
+
 Particle load_particle(in ivec2 coords) {
     vec4 raw_particle = texelFetch(particles, coords, 0);
-    return Particle(raw_particle.xy, raw_particle.zw);
+    return Particle(
+        raw_particle.xy,
+        raw_particle.zw,
+        // Boundary particles take the whole collision response off the others.
+        is_boundary(get_particle_id(coords)) ? BOUNDARY_MASS : texelFetch(masses, coords, 0).r,
+        texelFetch(drags, coords, 0).r,
+        get_stiffness(coords)
+    );
 }
 
 Particle get_particle(in uint id) {
@@ -48,43 +198,105 @@ void load_bin(in uint cur_particle_id, in uvec2 position, out Bin bin) {
     for (uint i = 0u; i < BIN_CAPACITY; ++i) {
         uint id = texelFetch(bins, ivec3(ivec2(position), int(i)), 0).x;
 
-        if (id == 0u || id - 1u == cur_particle_id)
-        bin.particles[i] = Particle(vec2(-1000.0), vec2(0.0));
-        else
+        bin.occupied[i] = id != 0u && id - 1u != cur_particle_id;
+
+        if (bin.occupied[i])
         bin.particles[i] = get_particle(id - 1u);
+        else
+        bin.particles[i] = Particle(vec2(-1000.0), vec2(0.0), 1.0, 0.0, 1.0);
     }
 }
 
 uvec2 get_bin_coords(in vec2 position) {
-    return uvec2(floor((position * 0.5 + 0.5) * vec2(grid_size)));
+    return uvec2(floor((position - domain_min) / cell_size));
 }
 
-void process_collisions(inout Particle cur_particle, in Bin bin) {
-    for (uint i = 0u; i < BIN_CAPACITY; ++i) {
-        vec2 delta_pos = bin.particles[i].position - cur_particle.position;
+// Moves the particle by its share of the overlap along the contact normal. The other particle
+// moves by the rest in its own invocation, so the pair ends up exactly separated.
+void separate_from(inout Particle cur_particle, in Particle other) {
+    vec2 delta_pos = other.position - cur_particle.position;
+    float radius = particle_radius * collision_radius_scale;
+
+    if (dot(delta_pos, delta_pos) <= 4.0 * radius * radius) {
+        float share = min(cur_particle.stiffness, other.stiffness) * other.mass / (cur_particle.mass + other.mass);
+        float overlap = max(0.0, SEPARATION_DISTANCE * radius - length(delta_pos));
+
+        cur_particle.position -= share * overlap * normalize(delta_pos);
+    }
+}
+
+void collide_with(inout Particle cur_particle, in Particle other) {
+    if (collision_stage == COLLISION_STAGE_POSITIONS) {
+        separate_from(cur_particle, other);
+        return;
+    }
+
+    vec2 delta_pos = other.position - cur_particle.position;
+    float radius = particle_radius * collision_radius_scale;
+    float contact_distance = (collision_stage == COLLISION_STAGE_VELOCITIES ? SEPARATED_CONTACT_DISTANCE : 2.0) * radius;
 
-        if (dot(delta_pos, delta_pos) <= 4.0 * particle_radius * particle_radius) {
-            vec2 n_delta_pos = normalize(delta_pos);
-            vec2 n_velocity = normalize(cur_particle.velocity);
+    if (dot(delta_pos, delta_pos) <= contact_distance * contact_distance) {
+        vec2 n_delta_pos = normalize(delta_pos);
+        vec2 n_velocity = normalize(cur_particle.velocity);
 
-            cur_particle.position -= max(0.0, 2.05 * particle_radius - length(delta_pos)) * (dot(n_delta_pos, n_velocity) * n_velocity);
-            //cur_particle.velocity = 1.0 * -cur_particle.velocity;
+        // Share of the collision taken by this particle, 1/2 for equal masses. Softened while
+        // either of the particles has just been spawned.
+        float share = min(cur_particle.stiffness, other.stiffness) * other.mass / (cur_particle.mass + other.mass);
 
-            vec2 a = dot(cur_particle.velocity, n_delta_pos) * n_delta_pos;
-            vec2 b = cur_particle.velocity - a;
+        if (collision_stage == COLLISION_STAGE_FULL)
+            cur_particle.position -= 2.0 * share * max(0.0, SEPARATION_DISTANCE * radius - length(delta_pos)) * (dot(n_delta_pos, n_velocity) * n_velocity);
+        //cur_particle.velocity = 1.0 * -cur_particle.velocity;
 
-            //cur_particle.velocity = 0.8 * -a + b;
-            cur_particle.velocity =  b;
+        // The other particle receives the opposite impulse in its own invocation, so the momentum
+        // along the normal is conserved. Separating pairs are left alone, otherwise an overlap
+        // lasting several steps would flip the velocities back and forth.
+        float approach_speed = dot(cur_particle.velocity - other.velocity, n_delta_pos);
 
-            //cur_particle.velocity -= dot(cur_particle.velocity, n_delta_pos) * n_delta_pos;
+        if (approach_speed > 0.0)
+            cur_particle.velocity -= (1.0 + particle_restitution) * share * approach_speed * n_delta_pos;
 
-            //break;
+        //cur_particle.velocity -= dot(cur_particle.velocity, n_delta_pos) * n_delta_pos;
+    }
+}
+
+void process_collisions(inout Particle cur_particle, in Bin bin, inout int checks_left) {
+    for (uint i = 0u; i < BIN_CAPACITY && checks_left > 0; ++i) {
+        if (bin.occupied[i]) {
+            collide_with(cur_particle, bin.particles[i]);
+            --checks_left;
         }
     }
 }
 
-uint get_particle_id(in ivec2 coords) {
-    return uint(coords.x + coords.y * textureSize(particles, 0).x);
+// Unlike the fixed-capacity bins, the sorted keys hold every particle in the cell.
+void process_sorted_collisions(inout Particle cur_particle, in uint cur_particle_id, in ivec2 bin_coords, inout int checks_left) {
+    if (any(lessThan(bin_coords, ivec2(0))) || any(greaterThanEqual(bin_coords, textureSize(cell_ranges, 0))))
+        return;
+
+    uvec2 range = texelFetch(cell_ranges, bin_coords, 0).xy;
+    int width = textureSize(sorted_keys, 0).x;
+
+    for (uint i = range.x; i < range.y && checks_left > 0; ++i) {
+        uint id = texelFetch(sorted_keys, ivec2(int(i) % width, int(i) / width), 0).y;
+
+        if (id != cur_particle_id) {
+            collide_with(cur_particle, get_particle(id));
+            --checks_left;
+        }
+    }
+}
+
+void collide_cell(inout Particle particle, in uint particle_id, in ivec2 bin_coords, inout int checks_left) {
+    if (checks_left <= 0)
+        return;
+
+    if (sorted_binning) {
+        process_sorted_collisions(particle, particle_id, bin_coords, checks_left);
+    } else {
+        Bin bin;
+        load_bin(particle_id, uvec2(bin_coords), bin);
+        process_collisions(particle, bin, checks_left);
+    }
 }
 
 void gravity_field(inout Particle particle, vec2 center, float strength) {
@@ -115,47 +327,221 @@ void static_collider(inout Particle particle, in StaticCollider collider) {
     }
 }
 
-void main() {
-    uint particle_id = get_particle_id(ivec2(gl_FragCoord.xy));
-    Particle particle = load_particle(ivec2(gl_FragCoord.xy));
+float hash(vec2 p) {
+    return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453123);
+}
+
+void apply_impulse(inout Particle particle, in uint id) {
+    if (impulse_mode == IMPULSE_UNIFORM) {
+        particle.velocity += impulse / particle.mass;
+    } else if (impulse_mode == IMPULSE_RADIAL) {
+        float dst = length(particle.position);
+
+        if (dst > 0.0)
+            particle.velocity += particle.position / dst * impulse / particle.mass;
+    } else if (impulse_mode == IMPULSE_RANDOM) {
+        float angle = 2.0 * PI * hash(vec2(float(id), impulse_seed));
+        particle.velocity += vec2(cos(angle), sin(angle)) * impulse / particle.mass;
+    }
+}
+
+void apply_region_push(inout Particle particle) {
+    if (all(greaterThanEqual(particle.position, push_region.xy)) && all(lessThanEqual(particle.position, push_region.zw)))
+        particle.velocity += push_velocity;
+}
+
+// Moves the particle along its velocity for the whole time step. Instead of testing the end
+// position only, the swept segment is intersected with the walls so that fast particles are
+// reflected at the point of first contact and continue with the remaining part of the step.
+// Only the walls of the axes set in `bounce` are taken into account.
+void move_bounce(inout Particle particle, in bvec2 bounce) {
+    vec2 lower = domain_min + particle_radius;
+    vec2 upper = domain_max - particle_radius;
+
+    float remaining = dt;
+
+    for (int contact = 0; contact < MAX_WALL_CONTACTS; ++contact) {
+        vec2 end = particle.position + remaining * particle.velocity;
+
+        float contact_time = remaining;
+        int contact_axis = -1;
+
+        for (int axis = 0; axis < 2; ++axis) {
+            float wall;
+
+            if (!bounce[axis])
+                continue;
 
-    // Process collisions
+            if (end[axis] < lower[axis] && particle.velocity[axis] < 0.0)
+                wall = lower[axis];
+            else if (end[axis] > upper[axis] && particle.velocity[axis] > 0.0)
+                wall = upper[axis];
+            else
+                continue;
+
+            float time = max(0.0, (wall - particle.position[axis]) / particle.velocity[axis]);
+
+            if (time < contact_time) {
+                contact_time = time;
+                contact_axis = axis;
+            }
+        }
+
+        particle.position += contact_time * particle.velocity;
+
+        if (contact_axis < 0)
+            return;
+
+        particle.velocity[contact_axis] *= -wall_restitution;
+        remaining -= contact_time;
+    }
+
+    particle.position = mix(particle.position, clamp(particle.position, lower, upper), bounce);
+}
+
+// Keeps the particle a radius away from the segment and reflects its velocity off of it like off
+// a wall. Particles which crossed the line within the step are put back on the side they came from.
+void collide_platform(inout Particle particle, in vec2 previous_position, in vec4 platform) {
+    vec2 start = platform.xy;
+    vec2 direction = platform.zw - start;
+    float length2 = dot(direction, direction);
+
+    if (length2 == 0.0)
+        return;
+
+    vec2 line_normal = vec2(-direction.y, direction.x) * inversesqrt(length2);
+    float side_before = dot(previous_position - start, line_normal);
+    float side_after = dot(particle.position - start, line_normal);
+    float along = dot(particle.position - start, direction) / length2;
+
+    if (side_before * side_after < 0.0 && along >= 0.0 && along <= 1.0)
+        particle.position += (sign(side_before) * particle_radius - side_after) * line_normal;
+
+    vec2 closest = start + clamp(dot(particle.position - start, direction) / length2, 0.0, 1.0) * direction;
+    vec2 delta_pos = particle.position - closest;
+    float distance = length(delta_pos);
+
+    if (distance >= particle_radius)
+        return;
+
+    vec2 normal = distance > 0.0 ? delta_pos / distance : (side_before < 0.0 ? -line_normal : line_normal);
+    particle.position = closest + particle_radius * normal;
+
+    float normal_speed = dot(particle.velocity, normal);
+
+    if (normal_speed < 0.0)
+        particle.velocity -= (1.0 + wall_restitution) * normal_speed * normal;
+}
+
+// Keeps the particle a radius inside of the container and reflects its velocity off of the
+// boundary like off a wall. Particles which left the container are put back onto its boundary.
+void collide_container(inout Particle particle) {
+    if (container.z <= 0.0)
+        return;
+
+    vec2 offset = particle.position - container.xy;
+    vec2 q = abs(offset) - (container.zw - container_corner_radius);
+    float overlap = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0) - container_corner_radius + particle_radius;
+
+    if (overlap <= 0.0)
+        return;
+
+    // Outward normal of the closest point of the boundary.
+    vec2 normal = max(q.x, q.y) > 0.0 ? normalize(max(q, 0.0)) : (q.x > q.y ? vec2(1.0, 0.0) : vec2(0.0, 1.0));
+    normal *= vec2(offset.x < 0.0 ? -1.0 : 1.0, offset.y < 0.0 ? -1.0 : 1.0);
+
+    particle.position -= overlap * normal;
+
+    float normal_speed = dot(particle.velocity, normal);
+
+    if (normal_speed > 0.0)
+        particle.velocity -= (1.0 + wall_restitution) * normal_speed * normal;
+}
+
+// Moves the particle onto the closest point of the track and keeps only the part of its velocity
+// along the track. Collisions between neighbors on the track then push along it as well.
+void follow_track(inout Particle particle) {
+    if (track_segment_count == 0)
+        return;
+
+    float closest_distance2 = 1.0e30;
+    vec2 closest = particle.position;
+    vec2 tangent = vec2(0.0);
+
+    for (int i = 0; i < track_segment_count; ++i) {
+        vec4 segment = texelFetch(track, ivec2(i, 0), 0);
+        vec2 direction = segment.zw - segment.xy;
+        float length2 = dot(direction, direction);
+
+        if (length2 == 0.0)
+            continue;
+
+        vec2 point = segment.xy + clamp(dot(particle.position - segment.xy, direction) / length2, 0.0, 1.0) * direction;
+        vec2 delta_pos = particle.position - point;
+        float distance2 = dot(delta_pos, delta_pos);
+
+        if (distance2 < closest_distance2) {
+            closest_distance2 = distance2;
+            closest = point;
+            tangent = direction * inversesqrt(length2);
+        }
+    }
+
+    particle.position = closest;
+    particle.velocity = dot(particle.velocity, tangent) * tangent;
+}
+
+void move(inout Particle particle) {
+    bvec2 bounce = equal(boundary_mode, ivec2(BOUNDARY_BOUNCE));
+    bvec2 wrap = equal(boundary_mode, ivec2(BOUNDARY_WRAP));
+    vec2 previous_position = particle.position;
+
+    if (any(bounce))
+        move_bounce(particle, bounce);
+    else
+        particle.position += dt * particle.velocity;
+
+    // Before wrapping, which would look like crossing every platform on the way.
+    for (int i = 0; i < platform_count; ++i)
+        collide_platform(particle, previous_position, platforms[i]);
+
+    collide_container(particle);
+    follow_track(particle);
+
+    vec2 wrapped = domain_min + mod(particle.position - domain_min, domain_max - domain_min);
+    particle.position = mix(particle.position, wrapped, wrap);
+}
+
+void collide(inout Particle particle, in uint particle_id) {
+    if (!collisions_enabled)
+        return;
 
     #ifdef COLLISIONS
 
-    Bin bin;
     ivec2 bin_coords = ivec2(get_bin_coords(particle.position));
+    int checks_left = max_neighbor_checks;
 
-    load_bin(particle_id, uvec2(bin_coords), bin);
-    process_collisions(particle, bin);
+    collide_cell(particle, particle_id, bin_coords, checks_left);
 
     #ifdef EXACT_COLLISIONS
 
-    load_bin(particle_id, uvec2(bin_coords + ivec2(1, 0)), bin);
-    process_collisions(particle, bin);
+    collide_cell(particle, particle_id, bin_coords + ivec2(1, 0), checks_left);
 
-    load_bin(particle_id, uvec2(bin_coords + ivec2(-1, 0)), bin);
-    process_collisions(particle, bin);
+    collide_cell(particle, particle_id, bin_coords + ivec2(-1, 0), checks_left);
 
-    load_bin(particle_id, uvec2(bin_coords + ivec2(0, 1)), bin);
-    process_collisions(particle, bin);
+    collide_cell(particle, particle_id, bin_coords + ivec2(0, 1), checks_left);
 
-    load_bin(particle_id, uvec2(bin_coords + ivec2(0, -1)), bin);
-    process_collisions(particle, bin);
+    collide_cell(particle, particle_id, bin_coords + ivec2(0, -1), checks_left);
 
     #ifdef DIAGONAL_CELL_CHECKS
 
-    load_bin(particle_id, uvec2(bin_coords + ivec2(-1, -1)), bin);
-    process_collisions(particle, bin);
+    collide_cell(particle, particle_id, bin_coords + ivec2(-1, -1), checks_left);
 
-    load_bin(particle_id, uvec2(bin_coords + ivec2(-1, 1)), bin);
-    process_collisions(particle, bin);
+    collide_cell(particle, particle_id, bin_coords + ivec2(-1, 1), checks_left);
 
-    load_bin(particle_id, uvec2(bin_coords + ivec2(1, -1)), bin);
-    process_collisions(particle, bin);
+    collide_cell(particle, particle_id, bin_coords + ivec2(1, -1), checks_left);
 
-    load_bin(particle_id, uvec2(bin_coords + ivec2(1, 1)), bin);
-    process_collisions(particle, bin);
+    collide_cell(particle, particle_id, bin_coords + ivec2(1, 1), checks_left);
 
     #endif // DIAGONAL_CELL_CHECKS
     #endif // EXACT_COLLISIONS
@@ -163,6 +549,10 @@ void main() {
 
     #ifdef STATIC_COLLISIONS
 
+    // Static colliders change the velocity as well, so they are left to the velocity stage.
+    if (collision_stage == COLLISION_STAGE_POSITIONS)
+        return;
+
     static_collider(particle, StaticCollider(vec2(-1.0, 0.2), 0.08));
     static_collider(particle, StaticCollider(vec2(1.0, 0.2), 0.08));
     static_collider(particle, StaticCollider(vec2(-0.3, 0.7), 0.08));
@@ -172,15 +562,34 @@ void main() {
     static_collider(particle, StaticCollider(vec2(0.2, 0.0), 0.05));
 
     #endif
+}
 
-    // Update position
+// Scales `force` down to `max_force` if it is stronger, keeping its direction.
+vec2 clamp_force(vec2 force) {
+    float magnitude = length(force);
+    return magnitude > max_force ? force * (max_force / magnitude) : force;
+}
 
-//    particle.velocity -= 2.0 * vec2(lessThan(particle.position, vec2(-1.05))) * particle.velocity;
-//    particle.velocity -= 2.0 * vec2(greaterThan(particle.position, vec2(1.05))) * particle.velocity;
+vec2 sample_force_field(in vec2 position) {
+    vec2 uv = (position - domain_min) / (domain_max - domain_min);
+    return force_field_strength * textureLod(force_field, uv, 0.0).xy;
+}
 
-    particle.position += dt * particle.velocity;
-    //particle.position.y = max(particle.position.y, -1.0);
+// Gravity of the first zone containing `position`, or the global gravity outside of them.
+vec2 local_gravity(vec2 position) {
+    for (int i = 0; i < gravity_zone_count; ++i) {
+        vec4 bounds = gravity_zone_bounds[i];
 
+        if (all(greaterThanEqual(position, bounds.xy)) && all(lessThan(position, bounds.zw)))
+            return gravity_zone_gravity[i];
+    }
+
+    return gravity;
+}
+
+// Applies gravity, the force field and damping over `time`. Gravity is an acceleration, while
+// the force field holds forces, which accelerate heavier particles less.
+void apply_forces(inout Particle particle, float time) {
     //    gravity_field(particle, vec2(-1.0, -1.0), 0.1);
     //    gravity_field(particle, vec2(-1.0, 1.0), 0.1);
     //    gravity_field(particle, vec2(1.0, -1.0), 0.1);
@@ -190,7 +599,115 @@ void main() {
 
     //particle.velocity -= 0.01 * dt * particle.velocity;
 
-    particle.velocity += dt * vec2(0.0, -9.87 / 10.0);
+    particle.velocity += time * clamp_force(local_gravity(particle.position) + sample_force_field(particle.position) / particle.mass);
+    particle.velocity *= max(0.0, 1.0 - damping * time) * exp(-particle.drag * time);
+}
+
+// Eased morph progress of a single particle, which starts after a random delay.
+float morph_easing(float progress, in uint id) {
+    float delay = MORPH_STAGGER * hash(vec2(float(id), 0.5));
+    float t = clamp((progress - delay) / (1.0 - MORPH_STAGGER), 0.0, 1.0);
+    return t * t * (3.0 - 2.0 * t);
+}
+
+// Covers the share of the remaining distance to the target that the eased progress advanced
+// by in this step, so that the particle reaches its target exactly when the morph ends
+// without the start position being stored.
+void morph(inout Particle particle, in uint id) {
+    float previous = morph_easing(morph_progress.x, id);
+    float current = morph_easing(morph_progress.y, id);
+    vec2 target = texelFetch(morph_targets, ivec2(gl_FragCoord.xy), 0).xy;
+
+    float fraction = previous < 1.0 ? (current - previous) / (1.0 - previous) : 1.0;
+
+    particle.position = mix(particle.position, target, fraction);
+    particle.velocity = vec2(0.0);
+}
+
+bool is_frozen(in Particle particle) {
+    if (freeze_brush_radius.x <= 0.0)
+        return false;
+
+    vec2 delta_pos = (particle.position - freeze_brush_center) / freeze_brush_radius;
+    return dot(delta_pos, delta_pos) <= 1.0;
+}
+
+bool is_absorbed(in Particle particle) {
+    return particle.position == ABSORBED_POSITION;
+}
+
+bool in_sink(in Particle particle, in uint id) {
+    if (sink_radius <= 0.0)
+        return false;
+
+    vec2 delta_pos = particle.position - sink_center;
+    return dot(delta_pos, delta_pos) <= sink_radius * sink_radius && hash(vec2(float(id), sink_seed)) < sink_probability;
+}
+
+void main() {
+    uint particle_id = get_particle_id(ivec2(gl_FragCoord.xy));
+    Particle particle = load_particle(ivec2(gl_FragCoord.xy));
+
+    if (is_boundary(particle_id)) {
+        out_particle = vec4(particle.position, vec2(0.0));
+        return;
+    }
+
+    // Morphing, absorbed and frozen particles are handled by the velocity stage alone.
+    if (collision_stage == COLLISION_STAGE_POSITIONS) {
+        if (morph_progress.y <= 0.0 && !is_absorbed(particle) && !is_frozen(particle))
+            collide(particle, particle_id);
+
+        out_particle = vec4(particle.position, particle.velocity);
+        return;
+    }
+
+    if (morph_progress.y > 0.0) {
+        morph(particle, particle_id);
+        out_particle = vec4(particle.position, particle.velocity);
+        return;
+    }
+
+    // Absorbed particles stay parked, so that neither forces nor the walls bring them back.
+    if (is_absorbed(particle)) {
+        out_particle = vec4(particle.position, particle.velocity);
+        return;
+    }
+
+    if (is_frozen(particle)) {
+        out_particle = vec4(particle.position, vec2(0.0));
+        return;
+    }
+
+    apply_impulse(particle, particle_id);
+    apply_region_push(particle);
+    particle.velocity *= velocity_scale;
+    particle.velocity -= drift;
+
+//    particle.velocity -= 2.0 * vec2(lessThan(particle.position, vec2(-1.05))) * particle.velocity;
+//    particle.velocity -= 2.0 * vec2(greaterThan(particle.position, vec2(1.05))) * particle.velocity;
+
+    if (force_order == FORCE_ORDER_GRAVITY_FIRST) {
+        apply_forces(particle, dt);
+        collide(particle, particle_id);
+        move(particle);
+    } else if (force_order == FORCE_ORDER_SPLIT) {
+        apply_forces(particle, 0.5 * dt);
+        collide(particle, particle_id);
+        move(particle);
+        apply_forces(particle, 0.5 * dt);
+    } else {
+        collide(particle, particle_id);
+        move(particle);
+        apply_forces(particle, dt);
+    }
+
+    //particle.position.y = max(particle.position.y, -1.0);
+
+    if (in_sink(particle, particle_id)) {
+        particle.position = ABSORBED_POSITION;
+        particle.velocity = vec2(0.0);
+    }
 
     out_particle = vec4(particle.position, particle.velocity);
-}
\ No newline at end of file
+}
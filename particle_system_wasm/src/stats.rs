@@ -0,0 +1,168 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use glam::Vec2;
+use wasm_bindgen::prelude::*;
+
+pub type SharedStats = Rc<RefCell<Stats>>;
+
+/// Diagnostics collected by the render loop that can be queried from JS.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Number of grid cells holding 0, 1, ..., `BIN_CAPACITY` particles.
+    pub occupancy_histogram: Option<Vec<u32>>,
+    /// Change of momentum and kinetic energy over the last update pass.
+    pub conservation_drift: Option<ConservationDrift>,
+    /// Number of particles with a non-finite position, or outside of the domain although the
+    /// boundary mode should keep them in it, after the last update pass.
+    pub lost_particles: Option<u32>,
+    /// Number of particles absorbed by the sink and not replaced yet, after the last update pass.
+    pub absorbed_particles: Option<u32>,
+    /// Number of particles generation managed to place apart on startup or the last reset,
+    /// `None` without a start spacing.
+    pub placed_particles: Option<u32>,
+}
+
+/// Total momentum and kinetic energy of the particles, all of which have unit mass.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Conservation {
+    pub momentum: Vec2,
+    pub kinetic_energy: f32,
+}
+
+impl Conservation {
+    /// Sums over the particles given as their velocity and mass.
+    pub fn from_particles(particles: impl Iterator<Item=(Vec2, f32)>) -> Self {
+        particles.fold(Conservation::default(), |total, (velocity, mass)| Conservation {
+            momentum: total.momentum + mass * velocity,
+            kinetic_energy: total.kinetic_energy + 0.5 * mass * velocity.length_squared(),
+        })
+    }
+}
+
+/// Aggregates of the particle velocities, reduced on the GPU so that only a single texel has to
+/// be read back.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Reduction {
+    pub max_speed: f32,
+    /// Sum of mass times velocity over the particles.
+    pub momentum: Vec2,
+    pub total_mass: f32,
+}
+
+impl Reduction {
+    /// Velocity of the center of mass.
+    pub fn mean_velocity(self) -> Vec2 {
+        if self.total_mass > 0.0 {
+            self.momentum / self.total_mass
+        } else {
+            Vec2::ZERO
+        }
+    }
+}
+
+/// Difference between the conserved quantities after and before a simulation step.
+/// Gravity, damping and collisions with the domain walls contribute to it as well,
+/// so they should be disabled to check the particle collisions alone.
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone)]
+pub struct ConservationDrift {
+    /// Change of the total momentum, divided by the particle count.
+    #[wasm_bindgen(js_name = "momentumX")]
+    pub momentum_x: f32,
+    #[wasm_bindgen(js_name = "momentumY")]
+    pub momentum_y: f32,
+    /// Change of the total kinetic energy relative to its value before the step.
+    #[wasm_bindgen(js_name = "kineticEnergy")]
+    pub kinetic_energy: f32,
+}
+
+impl ConservationDrift {
+    pub fn between(before: Conservation, after: Conservation, particle_count: u32) -> Self {
+        let momentum = (after.momentum - before.momentum) / particle_count as f32;
+
+        let kinetic_energy = if before.kinetic_energy > 0.0 {
+            (after.kinetic_energy - before.kinetic_energy) / before.kinetic_energy
+        } else {
+            0.0
+        };
+
+        ConservationDrift {
+            momentum_x: momentum.x,
+            momentum_y: momentum.y,
+            kinetic_energy,
+        }
+    }
+}
+
+/// Comparison of the particle data after running the same simulation steps twice from the same
+/// state, see `measureDeterminism`.
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone)]
+pub struct Determinism {
+    /// Whether both runs ended with bitwise identical particle data.
+    pub identical: bool,
+    /// Largest difference of a position or velocity component between the runs, infinite if
+    /// a component is not finite in only one of them.
+    #[wasm_bindgen(js_name = "maxDifference")]
+    pub max_difference: f32,
+    /// Whether the runs are identical, or their largest difference is within the tolerance.
+    pub deterministic: bool,
+}
+
+impl Determinism {
+    pub fn between(first: &[f32], second: &[f32], tolerance: Option<f32>) -> Self {
+        let identical = first.iter().zip(second).all(|(a, b)| a.to_bits() == b.to_bits());
+
+        let max_difference = first.iter()
+            .zip(second)
+            .filter(|(a, b)| a.to_bits() != b.to_bits())
+            .map(|(a, b)| {
+                let difference = (a - b).abs();
+                if difference.is_nan() { f32::INFINITY } else { difference }
+            })
+            .fold(0.0, f32::max);
+
+        Determinism {
+            identical,
+            max_difference,
+            deterministic: identical || tolerance.is_some_and(|tolerance| max_difference <= tolerance),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_runs_are_deterministic_without_tolerance() {
+        let determinism = Determinism::between(&[0.5, -1.0, 0.0, 2.0], &[0.5, -1.0, 0.0, 2.0], None);
+
+        assert!(determinism.identical);
+        assert!(determinism.deterministic);
+        assert_eq!(determinism.max_difference, 0.0);
+    }
+
+    #[test]
+    fn differing_runs_are_only_deterministic_within_the_tolerance() {
+        let first = [0.5, -1.0, 0.0, 2.0];
+        let second = [0.5, -1.0, 1e-4, 2.0];
+
+        assert!(!Determinism::between(&first, &second, None).deterministic);
+        assert!(!Determinism::between(&first, &second, Some(1e-5)).deterministic);
+
+        let determinism = Determinism::between(&first, &second, Some(1e-3));
+        assert!(!determinism.identical);
+        assert!(determinism.deterministic);
+        assert_eq!(determinism.max_difference, 1e-4);
+    }
+
+    #[test]
+    fn a_component_that_is_nan_in_one_run_only_is_an_infinite_difference() {
+        let determinism = Determinism::between(&[f32::NAN, 0.0], &[1.0, 0.0], Some(f32::MAX));
+
+        assert_eq!(determinism.max_difference, f32::INFINITY);
+        assert!(!determinism.deterministic);
+    }
+}
@@ -0,0 +1,76 @@
+use wasm_bindgen::prelude::*;
+use web_sys::{HtmlCanvasElement, WebGl2RenderingContext, window};
+
+use crate::graphics::BIN_CAPACITY;
+
+type GL = WebGl2RenderingContext;
+
+/// Slotted binning blends particle ids as floats, which represent integers exactly up to 2^24.
+const MAX_EXACT_PARTICLE_ID: u64 = 1 << 24;
+
+/// WebGL2 capabilities the simulation depends on.
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SupportInfo {
+    pub webgl2: bool,
+    #[wasm_bindgen(js_name = "colorBufferFloat")]
+    pub color_buffer_float: bool,
+    #[wasm_bindgen(js_name = "maxTextureSize")]
+    pub max_texture_size: u32,
+    #[wasm_bindgen(js_name = "maxArrayTextureLayers")]
+    pub max_array_texture_layers: u32,
+}
+
+#[wasm_bindgen]
+impl SupportInfo {
+    /// Whether the simulation can run at all with these capabilities.
+    #[wasm_bindgen(getter, js_name = "isSupported")]
+    pub fn is_supported(&self) -> bool {
+        self.webgl2 && self.color_buffer_float
+    }
+}
+
+impl SupportInfo {
+    /// Largest particle count the textures could hold with these capabilities: a square data
+    /// texture of `MAX_TEXTURE_SIZE` texels, limited so that every particle id stays exact in the
+    /// binning pass. 0 if the simulation is not supported or the bins texture cannot have
+    /// `BIN_CAPACITY` layers.
+    pub fn particle_capacity(&self) -> u32 {
+        if !self.is_supported() || self.max_array_texture_layers < BIN_CAPACITY {
+            return 0;
+        }
+
+        let max_texture_size = self.max_texture_size as u64;
+        (max_texture_size * max_texture_size).min(MAX_EXACT_PARTICLE_ID) as u32
+    }
+}
+
+/// Queries the capabilities of a WebGL2 context created on `canvas`,
+/// or on a temporary canvas when none is given.
+pub fn probe_support(canvas: Option<HtmlCanvasElement>) -> Result<SupportInfo, JsValue> {
+    let canvas = match canvas {
+        Some(canvas) => canvas,
+        None => window().unwrap()
+            .document().unwrap()
+            .create_element("canvas")?
+            .dyn_into()?,
+    };
+
+    let gl = match canvas.get_context("webgl2")? {
+        Some(gl) => gl.dyn_into::<GL>()?,
+        None => return Ok(SupportInfo::default()),
+    };
+
+    Ok(SupportInfo {
+        webgl2: true,
+        color_buffer_float: gl.get_extension("EXT_color_buffer_float")?.is_some(),
+        max_texture_size: get_parameter_u32(&gl, GL::MAX_TEXTURE_SIZE)?,
+        max_array_texture_layers: get_parameter_u32(&gl, GL::MAX_ARRAY_TEXTURE_LAYERS)?,
+    })
+}
+
+fn get_parameter_u32(gl: &GL, parameter: u32) -> Result<u32, JsValue> {
+    Ok(gl.get_parameter(parameter)?
+        .as_f64()
+        .unwrap_or(0.0) as u32)
+}